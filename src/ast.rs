@@ -1,36 +1,105 @@
+use crate::table::STRICT_DATATYPES;
 use crate::{executor, table};
 use serde::{Deserialize, Serialize};
 use std::cmp::{Ord, Ordering};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, PartialEq)]
 pub enum Ast {
     Exit,
     Create(TableSchema),
+    /// `CREATE INDEX`, which does nothing if the index exists and `IF NOT
+    /// EXISTS` is given
+    CreateIndex(IndexSchema, bool),
     Insert(Insertion),
-    Select(Selection),
+    /// Boxed, as selections are much larger than the other statements
+    Select(Box<Selection>),
+    Update(Update),
+    Delete(Deletion),
+    DropTable(String, bool),
+    AlterTable(String, Alteration),
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    Release(String),
+    RollbackTo(String),
+    /// `PRAGMA name`, which reads a setting, or `PRAGMA name = value`
+    Pragma(String, Option<Value>),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ColumnSet {
     WildCard,
-    Names(Vec<String>),
+    /// Expressions the values of the result columns are computed with
+    Expressions(Vec<Expression>),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Value {
     Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
     Null,
 }
 
+impl Value {
+    /// Integer written in decimal, which is kept as a real if it is too
+    /// large for an i64, like SQLite does.
+    pub fn integer(digits: &str) -> Value {
+        digits
+            .parse::<i64>()
+            .map(Value::Integer)
+            .unwrap_or_else(|_| Value::Real(digits.parse::<f64>().unwrap()))
+    }
+
+    /// Rank of the storage class when values of different classes are
+    /// compared: NULL, then numbers, then text, then blobs.
+    fn class_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+}
+
+/// Compares an integer with a real exactly, without rounding the integer to
+/// the nearest real. NaN is larger than every number.
+fn cmp_integer_with_real(integer: i64, real: f64) -> Ordering {
+    // 2^63, the first real outside of the range of an i64
+    const I64_END: f64 = 9_223_372_036_854_775_808.0;
+    if real.is_nan() || real >= I64_END {
+        return Ordering::Less;
+    }
+    if real < -I64_END {
+        return Ordering::Greater;
+    }
+    let truncated = real.trunc();
+    match integer.cmp(&(truncated as i64)) {
+        Ordering::Equal => 0.0.partial_cmp(&(real - truncated)).unwrap(),
+        ordering => ordering,
+    }
+}
+
 impl Ord for Value {
+    /// Follows SQLite: NULL sorts first, then integers and reals by their
+    /// numeric value, then text and blobs by their bytes.
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
             (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
-            (Value::Null, Value::Null) => Ordering::Equal,
-            (Value::Integer(_), Value::Null) => Ordering::Less,
-            (Value::Null, Value::Integer(_)) => Ordering::Greater,
+            (Value::Real(a), Value::Real(b)) => a
+                .partial_cmp(b)
+                .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan())),
+            (Value::Integer(a), Value::Real(b)) => cmp_integer_with_real(*a, *b),
+            (Value::Real(a), Value::Integer(b)) => cmp_integer_with_real(*b, *a).reverse(),
+            (Value::Text(a), Value::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            (a, b) => a.class_rank().cmp(&b.class_rank()),
         }
     }
 }
@@ -41,24 +110,338 @@ impl PartialOrd for Value {
     }
 }
 
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    /// Integers and reals that compare equal hash the same.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.class_rank().hash(state);
+        match self {
+            Value::Integer(i) => i.hash(state),
+            Value::Real(r) => {
+                if r.is_nan() {
+                    f64::NAN.to_bits().hash(state)
+                } else if r.trunc() == *r && cmp_integer_with_real(*r as i64, *r).is_eq() {
+                    (*r as i64).hash(state)
+                } else {
+                    r.to_bits().hash(state)
+                }
+            }
+            Value::Text(s) => s.hash(state),
+            Value::Blob(b) => b.hash(state),
+            Value::Null => {}
+        }
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
             Value::Integer(i) => write!(f, "{}", i),
+            Value::Real(r) => write!(f, "{}", format_real(*r)),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Blob(b) => write!(f, "{}", String::from_utf8_lossy(b)),
             Value::Null => write!(f, "null"),
         }
     }
 }
 
+/// Writes the real like SQLite's "%!.15g": rounded to 15 significant
+/// digits, with a decimal point even when whole, and with an exponent when
+/// it is below 1e-4 or has more digits before the point.
+fn format_real(r: f64) -> String {
+    if r.is_infinite() {
+        return if r < 0.0 { "-Inf" } else { "Inf" }.to_string();
+    }
+    let scientific = format!("{:.14e}", r);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent = exponent.parse::<i32>().unwrap();
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+    let digits = digits.trim_end_matches('0');
+    let fraction = |digits: &str| match digits {
+        "" => "0".to_string(),
+        digits => digits.to_string(),
+    };
+    if !(-4..15).contains(&exponent) {
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        return format!(
+            "{}{}.{}e{}{:02}",
+            sign,
+            &digits[..1],
+            fraction(&digits[1..]),
+            exponent_sign,
+            exponent.abs()
+        );
+    }
+    if exponent < 0 {
+        let zeros = "0".repeat((-exponent - 1) as usize);
+        return format!("{}0.{}{}", sign, zeros, digits);
+    }
+    let whole_len = exponent as usize + 1;
+    let mut whole = digits[..digits.len().min(whole_len)].to_string();
+    whole.push_str(&"0".repeat(whole_len - whole.len()));
+    let fraction = fraction(digits.get(whole_len..).unwrap_or(""));
+    format!("{}{}.{}", sign, whole, fraction)
+}
+
+/// Values compared one after the other, like the keys of an index.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tuple(pub Vec<Value>);
+
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values = self.0.iter().map(Value::to_string).collect::<Vec<_>>();
+        write!(f, "({})", values.join(", "))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Value),
+    /// Column, which is qualified by its table as `table.column` if the
+    /// statement gives one
+    Column(String),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(Box<Expression>, BinaryOperator, Box<Expression>),
+    /// `IS NULL`, or `IS NOT NULL` when negated
+    IsNull(Box<Expression>, bool),
+    /// Call of the function of the lowercase name, where `COUNT(*)` has no
+    /// arguments
+    Function(String, Vec<Expression>),
+}
+
+impl Expression {
+    /// How tightly the operator of the expression binds, from 1 for `OR`
+    /// to 8 for unary minus, as in the grammar
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::Binary(_, BinaryOperator::Or, _) => 1,
+            Expression::Binary(_, BinaryOperator::And, _) => 2,
+            Expression::Unary(UnaryOperator::Not, _) => 3,
+            Expression::Binary(_, BinaryOperator::Equal | BinaryOperator::NotEqual, _)
+            | Expression::IsNull(_, _) => 4,
+            Expression::Binary(
+                _,
+                BinaryOperator::Less
+                | BinaryOperator::LessOrEqual
+                | BinaryOperator::Greater
+                | BinaryOperator::GreaterOrEqual,
+                _,
+            ) => 5,
+            Expression::Binary(_, BinaryOperator::Add | BinaryOperator::Subtract, _) => 6,
+            Expression::Binary(_, _, _) => 7,
+            Expression::Unary(UnaryOperator::Negate, _) => 8,
+            _ => 9,
+        }
+    }
+
+    pub fn unary(operator: UnaryOperator, operand: Expression) -> Expression {
+        Expression::Unary(operator, Box::new(operand))
+    }
+
+    pub fn binary(left: Expression, operator: BinaryOperator, right: Expression) -> Expression {
+        Expression::Binary(Box::new(left), operator, Box::new(right))
+    }
+
+    pub fn is_null(operand: Expression, negated: bool) -> Expression {
+        Expression::IsNull(Box::new(operand), negated)
+    }
+
+    pub fn function(name: &str, arguments: Vec<Expression>) -> Expression {
+        Expression::Function(name.to_lowercase(), arguments)
+    }
+}
+
+/// Writes the expression as SQL, with only the parentheses its operators
+/// need.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |f: &mut fmt::Formatter<'_>, operand: &Expression, precedence: u8| {
+            if operand.precedence() < precedence {
+                write!(f, "({})", operand)
+            } else {
+                write!(f, "{}", operand)
+            }
+        };
+        match self {
+            Expression::Literal(Value::Text(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expression::Literal(Value::Blob(b)) => {
+                let hex = b.iter().map(|byte| format!("{:02X}", byte));
+                write!(f, "X'{}'", hex.collect::<String>())
+            }
+            Expression::Literal(Value::Null) => write!(f, "NULL"),
+            // Reals are written with every digit, to be read back unchanged
+            Expression::Literal(Value::Real(r)) => write!(f, "{:?}", r),
+            Expression::Literal(value) => write!(f, "{}", value),
+            Expression::Column(name) => write!(f, "{}", name),
+            Expression::Unary(UnaryOperator::Negate, e) => {
+                write!(f, "-")?;
+                operand(f, e, self.precedence())
+            }
+            Expression::Unary(UnaryOperator::Not, e) => {
+                write!(f, "NOT ")?;
+                operand(f, e, self.precedence())
+            }
+            Expression::Binary(left, operator, right) => {
+                operand(f, left, self.precedence())?;
+                let operator = match operator {
+                    BinaryOperator::Or => "OR",
+                    BinaryOperator::And => "AND",
+                    BinaryOperator::Equal => "=",
+                    BinaryOperator::NotEqual => "!=",
+                    BinaryOperator::Less => "<",
+                    BinaryOperator::LessOrEqual => "<=",
+                    BinaryOperator::Greater => ">",
+                    BinaryOperator::GreaterOrEqual => ">=",
+                    BinaryOperator::Add => "+",
+                    BinaryOperator::Subtract => "-",
+                    BinaryOperator::Multiply => "*",
+                    BinaryOperator::Divide => "/",
+                    BinaryOperator::Remainder => "%",
+                };
+                write!(f, " {} ", operator)?;
+                // Operators are left associative
+                operand(f, right, self.precedence() + 1)
+            }
+            Expression::IsNull(e, negated) => {
+                operand(f, e, self.precedence())?;
+                match negated {
+                    true => write!(f, " IS NOT NULL"),
+                    false => write!(f, " IS NULL"),
+                }
+            }
+            Expression::Function(name, arguments) if arguments.is_empty() && name == "count" => {
+                write!(f, "count(*)")
+            }
+            Expression::Function(name, arguments) => {
+                let arguments = arguments.iter().map(Expression::to_string);
+                write!(f, "{}({})", name, arguments.collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+    pub expression: Expression,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl OrderingTerm {
+    /// NULLs come first in ascending order unless told otherwise, as they
+    /// are smaller than any other value.
+    pub fn new(
+        expression: Expression,
+        descending: bool,
+        nulls_first: Option<bool>,
+    ) -> OrderingTerm {
+        OrderingTerm {
+            expression,
+            descending,
+            nulls_first: nulls_first.unwrap_or(!descending),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TableReference {
+    pub name: String,
+    pub alias: Option<String>,
+}
+
+impl TableReference {
+    pub fn new(name: &str, alias: Option<String>) -> TableReference {
+        TableReference {
+            name: name.to_string(),
+            alias,
+        }
+    }
+
+    /// Name the columns of the table are qualified with in the statement
+    pub fn correlation_name(&self) -> &String {
+        self.alias.as_ref().unwrap_or(&self.name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum JoinOperator {
+    /// `JOIN`, `INNER JOIN` or a comma
+    Inner,
+    /// `LEFT [OUTER] JOIN`, which keeps the rows without a match
+    Left,
+    Cross,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Join {
+    pub operator: JoinOperator,
+    pub table: TableReference,
+    /// `ON` expression, without which every pair of rows matches
+    pub constraint: Option<Expression>,
+}
+
+/// Table of the FROM clause and the ones joined to it, in order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FromClause {
+    pub table: TableReference,
+    pub joins: Vec<Join>,
+}
+
+impl FromClause {
+    pub fn new(table: TableReference, joins: Vec<Join>) -> FromClause {
+        FromClause { table, joins }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Selection {
-    table_name: String,
+    from: FromClause,
     columns: ColumnSet,
+    where_clause: Option<Expression>,
+    group_by: Vec<Expression>,
+    having: Option<Expression>,
+    order_by: Vec<OrderingTerm>,
+    limit: Option<Expression>,
+    offset: Option<Expression>,
 }
 
 impl executor::Selection for Selection {
-    fn table_name(&self) -> &String {
-        &self.table_name
+    fn from(&self) -> &FromClause {
+        &self.from
     }
 
     fn validate(&self) -> Result<(), String> {
@@ -68,13 +451,56 @@ impl executor::Selection for Selection {
     fn columns(&self) -> ColumnSet {
         self.columns()
     }
+
+    fn where_clause(&self) -> Option<&Expression> {
+        self.where_clause.as_ref()
+    }
+
+    fn group_by(&self) -> &Vec<Expression> {
+        &self.group_by
+    }
+
+    fn having(&self) -> Option<&Expression> {
+        self.having.as_ref()
+    }
+
+    fn order_by(&self) -> &Vec<OrderingTerm> {
+        &self.order_by
+    }
+
+    fn limit(&self) -> Option<&Expression> {
+        self.limit.as_ref()
+    }
+
+    fn offset(&self) -> Option<&Expression> {
+        self.offset.as_ref()
+    }
 }
 
 impl Selection {
-    pub fn new(table_name: &str, columns: ColumnSet) -> Selection {
+    /// `limit` is the LIMIT clause along with its OFFSET, if there is one.
+    pub fn new(
+        from: FromClause,
+        columns: ColumnSet,
+        where_clause: Option<Expression>,
+        group_by: Vec<Expression>,
+        having: Option<Expression>,
+        order_by: Vec<OrderingTerm>,
+        limit: Option<(Expression, Option<Expression>)>,
+    ) -> Selection {
+        let (limit, offset) = match limit {
+            None => (None, None),
+            Some((limit, offset)) => (Some(limit), offset),
+        };
         Selection {
-            table_name: table_name.to_string(),
+            from,
             columns,
+            where_clause,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
         }
     }
     pub fn validate(&self) -> Result<(), String> {
@@ -86,17 +512,305 @@ impl Selection {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Update {
+    table_name: String,
+    assignments: Vec<(String, Expression)>,
+    where_clause: Option<Expression>,
+}
+
+impl executor::Update for Update {
+    fn table_name(&self) -> &String {
+        &self.table_name
+    }
+
+    fn assignments(&self) -> &Vec<(String, Expression)> {
+        &self.assignments
+    }
+
+    fn where_clause(&self) -> Option<&Expression> {
+        self.where_clause.as_ref()
+    }
+}
+
+impl Update {
+    pub fn new(
+        table_name: &str,
+        assignments: Vec<(String, Expression)>,
+        where_clause: Option<Expression>,
+    ) -> Update {
+        Update {
+            table_name: table_name.to_string(),
+            assignments,
+            where_clause,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Deletion {
+    table_name: String,
+    where_clause: Option<Expression>,
+}
+
+impl executor::Deletion for Deletion {
+    fn table_name(&self) -> &String {
+        &self.table_name
+    }
+
+    fn where_clause(&self) -> Option<&Expression> {
+        self.where_clause.as_ref()
+    }
+}
+
+impl Deletion {
+    pub fn new(table_name: &str, where_clause: Option<Expression>) -> Deletion {
+        Deletion {
+            table_name: table_name.to_string(),
+            where_clause,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Alteration {
+    RenameTo(String),
+    AddColumn(Column),
+    DropColumn(String),
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Column {
     pub name: String,
+    pub datatype: Option<String>,
     pub is_primary_key: bool,
+    pub not_null: bool,
+    pub unique: bool,
+    /// Constant expression giving the value of the column when an insertion
+    /// leaves it out
+    pub default: Option<Expression>,
+    /// Expressions over the columns of the row, which no row can make false
+    pub checks: Vec<Expression>,
+    /// Key of another table that the values of the column refer to
+    pub references: Option<ForeignKey>,
+}
+
+/// Constraint following the datatype in the definition of a column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraint {
+    PrimaryKey,
+    NotNull,
+    Unique,
+    Default(Expression),
+    Check(Expression),
+    /// `REFERENCES`, whose foreign key has yet to be given the column
+    References(ForeignKey),
 }
 
 impl Column {
-    pub fn new(name: &str, is_primary_key: bool) -> Column {
+    pub fn new(name: &str, datatype: Option<&str>, is_primary_key: bool) -> Column {
         Column {
             name: name.to_string(),
+            datatype: datatype.map(|datatype| datatype.to_uppercase()),
             is_primary_key,
+            not_null: false,
+            unique: false,
+            default: None,
+            checks: vec![],
+            references: None,
+        }
+    }
+
+    pub fn with_constraints(
+        name: &str,
+        datatype: Option<&str>,
+        constraints: Vec<ColumnConstraint>,
+    ) -> Column {
+        let mut column = Column::new(name, datatype, false);
+        for constraint in constraints {
+            match constraint {
+                ColumnConstraint::PrimaryKey => column.is_primary_key = true,
+                ColumnConstraint::NotNull => column.not_null = true,
+                ColumnConstraint::Unique => column.unique = true,
+                ColumnConstraint::Default(default) => column.default = Some(default),
+                ColumnConstraint::Check(check) => column.checks.push(check),
+                ColumnConstraint::References(foreign_key) => {
+                    column.references = Some(ForeignKey {
+                        columns: vec![name.to_string()],
+                        ..foreign_key
+                    })
+                }
+            }
+        }
+        column
+    }
+}
+
+/// Writes the definition of the column as SQL.
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(datatype) = &self.datatype {
+            write!(f, " {}", datatype)?;
+        }
+        if self.is_primary_key {
+            write!(f, " PRIMARY KEY")?;
+        }
+        if self.not_null {
+            write!(f, " NOT NULL")?;
+        }
+        if self.unique {
+            write!(f, " UNIQUE")?;
+        }
+        match &self.default {
+            None => {}
+            Some(default @ Expression::Literal(_))
+            | Some(default @ Expression::Unary(UnaryOperator::Negate, _)) => {
+                write!(f, " DEFAULT {}", default)?
+            }
+            // Other expressions only parse back in parentheses
+            Some(default) => write!(f, " DEFAULT ({})", default)?,
+        }
+        for check in &self.checks {
+            write!(f, " CHECK ({})", check)?;
+        }
+        if let Some(foreign_key) = &self.references {
+            write!(f, " {}", foreign_key)?;
+        }
+        Ok(())
+    }
+}
+
+impl Column {
+    /// Checks that the default value is constant and that the CHECK
+    /// expressions only refer to the `columns` of the table.
+    pub fn validate_constraints(&self, columns: &HashMap<String, usize>) -> Result<(), String> {
+        if let Some(default) = &self.default {
+            if executor::expression::validate(default, &HashMap::new()).is_err() {
+                return Err(format!(
+                    "default value of column [{}] is not constant",
+                    self.name
+                ));
+            }
+        }
+        for check in &self.checks {
+            executor::expression::validate(check, columns)?;
+        }
+        Ok(())
+    }
+}
+
+/// Constraint over several columns, following the columns in the
+/// definition of a table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    PrimaryKey(Vec<String>),
+    Unique(Vec<String>),
+    ForeignKey(ForeignKey),
+}
+
+impl TableConstraint {
+    fn columns(&self) -> &Vec<String> {
+        match self {
+            TableConstraint::PrimaryKey(columns) | TableConstraint::Unique(columns) => columns,
+            TableConstraint::ForeignKey(foreign_key) => &foreign_key.columns,
+        }
+    }
+}
+
+impl fmt::Display for TableConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableConstraint::PrimaryKey(columns) => {
+                write!(f, "PRIMARY KEY ({})", columns.join(", "))
+            }
+            TableConstraint::Unique(columns) => write!(f, "UNIQUE ({})", columns.join(", ")),
+            TableConstraint::ForeignKey(foreign_key) => write!(
+                f,
+                "FOREIGN KEY ({}) {}",
+                foreign_key.columns.join(", "),
+                foreign_key
+            ),
+        }
+    }
+}
+
+/// Constraint that the values of the columns of each row are those of a
+/// row of the parent table, unless one of them is NULL.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    pub parent_table: String,
+    /// Columns of the parent table, which default to its primary key
+    pub parent_columns: Vec<String>,
+    pub on_delete: ForeignKeyAction,
+    pub on_update: ForeignKeyAction,
+}
+
+/// What happens to the rows referring to a row of the parent table when
+/// that row is deleted or its key updated.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ForeignKeyAction {
+    /// Fails the statement if rows still refer to a missing row at its end
+    NoAction,
+    /// Fails the statement right away
+    Restrict,
+    SetNull,
+    /// Deletes the referring rows, or updates them to the new key
+    Cascade,
+}
+
+impl ForeignKey {
+    /// Foreign key from the columns to the parent table, with the actions of
+    /// the `ON DELETE` and `ON UPDATE` clauses, which are flagged by whether
+    /// they are for deletions.
+    pub fn new(
+        columns: Vec<String>,
+        parent_table: &str,
+        parent_columns: Vec<String>,
+        actions: Vec<(bool, ForeignKeyAction)>,
+    ) -> ForeignKey {
+        let mut foreign_key = ForeignKey {
+            columns,
+            parent_table: parent_table.to_lowercase(),
+            parent_columns,
+            on_delete: ForeignKeyAction::NoAction,
+            on_update: ForeignKeyAction::NoAction,
+        };
+        for (on_delete, action) in actions {
+            match on_delete {
+                true => foreign_key.on_delete = action,
+                false => foreign_key.on_update = action,
+            }
+        }
+        foreign_key
+    }
+}
+
+/// Writes the `REFERENCES` clause of the foreign key, without its columns.
+impl fmt::Display for ForeignKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "REFERENCES {}", self.parent_table)?;
+        if !self.parent_columns.is_empty() {
+            write!(f, "({})", self.parent_columns.join(", "))?;
+        }
+        if self.on_delete != ForeignKeyAction::NoAction {
+            write!(f, " ON DELETE {}", self.on_delete)?;
+        }
+        if self.on_update != ForeignKeyAction::NoAction {
+            write!(f, " ON UPDATE {}", self.on_update)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ForeignKeyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForeignKeyAction::NoAction => write!(f, "NO ACTION"),
+            ForeignKeyAction::Restrict => write!(f, "RESTRICT"),
+            ForeignKeyAction::SetNull => write!(f, "SET NULL"),
+            ForeignKeyAction::Cascade => write!(f, "CASCADE"),
         }
     }
 }
@@ -105,6 +819,8 @@ impl Column {
 pub struct TableSchema {
     pub name: String,
     pub columns: Vec<Column>,
+    pub constraints: Vec<TableConstraint>,
+    pub strict: bool,
 }
 
 impl TableSchema {
@@ -112,7 +828,38 @@ impl TableSchema {
         TableSchema {
             name: name.to_string(),
             columns,
+            constraints: vec![],
+            strict: false,
+        }
+    }
+
+    pub fn new_strict(name: &str, columns: Vec<Column>) -> TableSchema {
+        TableSchema {
+            strict: true,
+            ..TableSchema::new(name, columns)
+        }
+    }
+}
+
+/// Writes the `CREATE TABLE` statement of the table, without the semicolon
+/// ending it.
+impl fmt::Display for TableSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let definitions = self
+            .columns
+            .iter()
+            .map(Column::to_string)
+            .chain(self.constraints.iter().map(TableConstraint::to_string));
+        write!(
+            f,
+            "CREATE TABLE {} ({})",
+            self.name,
+            definitions.collect::<Vec<_>>().join(", ")
+        )?;
+        if self.strict {
+            write!(f, " STRICT")?;
         }
+        Ok(())
     }
 }
 
@@ -125,10 +872,25 @@ impl table::TableSchema for TableSchema {
         return self.columns.clone();
     }
 
+    fn constraints(&self) -> Vec<TableConstraint> {
+        self.constraints.clone()
+    }
+
+    fn is_strict(&self) -> bool {
+        self.strict
+    }
+
     fn validate(&self) -> Result<(), String> {
         let mut column_names = HashSet::new();
         let mut has_primary_key = false;
+        let columns = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| (column.name.clone(), i))
+            .collect();
         for c in &self.columns {
+            c.validate_constraints(&columns)?;
             if column_names.contains(&c.name) {
                 return Err(format!("duplicate column name: {}", c.name));
             }
@@ -141,17 +903,104 @@ impl table::TableSchema for TableSchema {
             if c.is_primary_key {
                 has_primary_key = true
             }
+            if self.strict {
+                match &c.datatype {
+                    None => return Err(format!("missing datatype for {}.{}", self.name, c.name)),
+                    Some(datatype) if !STRICT_DATATYPES.contains(&datatype.as_str()) => {
+                        return Err(format!(
+                            "unknown datatype for {}.{}: \"{}\"",
+                            self.name, c.name, datatype
+                        ))
+                    }
+                    _ => {}
+                }
+            }
             column_names.insert(c.name.clone());
         }
+        for constraint in &self.constraints {
+            if let TableConstraint::PrimaryKey(_) = constraint {
+                if has_primary_key {
+                    return Err(format!(
+                        "table \"{}\" has more than one primary key",
+                        self.name
+                    ));
+                }
+                has_primary_key = true;
+            }
+            for name in constraint.columns() {
+                if !column_names.contains(name) {
+                    return Err(format!("no such column: {}", name));
+                }
+            }
+        }
+        for column in &self.columns {
+            if let Some(foreign_key) = &column.references {
+                if foreign_key.parent_columns.len() > 1 {
+                    return Err(format!(
+                        "foreign key on {} should reference only one column of table {}",
+                        column.name, foreign_key.parent_table
+                    ));
+                }
+            }
+        }
+        for constraint in &self.constraints {
+            if let TableConstraint::ForeignKey(foreign_key) = constraint {
+                let num_parent_columns = foreign_key.parent_columns.len();
+                if num_parent_columns > 0 && num_parent_columns != foreign_key.columns.len() {
+                    return Err(
+                        "number of columns in foreign key does not match the number \
+                        of columns in the referenced table"
+                            .to_string(),
+                    );
+                }
+            }
+        }
         Ok(())
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexSchema {
+    pub name: String,
+    pub table_name: String,
+    pub columns: Vec<String>,
+    /// Whether no two rows can have the same values in the columns, unless
+    /// one of them is NULL
+    pub unique: bool,
+}
+
+impl IndexSchema {
+    pub fn new(name: &str, table_name: &str, columns: Vec<String>, unique: bool) -> IndexSchema {
+        IndexSchema {
+            name: name.to_string(),
+            table_name: table_name.to_string(),
+            columns,
+            unique,
+        }
+    }
+}
+
+/// Writes the `CREATE INDEX` statement of the index, without the semicolon
+/// ending it.
+impl fmt::Display for IndexSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CREATE {}INDEX {} ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            self.name,
+            self.table_name,
+            self.columns.join(", ")
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Insertion {
     pub table_name: String,
     column_names: Option<Vec<String>>,
-    values: Vec<Value>,
+    /// Constant expressions giving the values of the row
+    values: Vec<Expression>,
 }
 
 impl executor::Insertion for Insertion {
@@ -176,7 +1025,7 @@ impl Insertion {
     pub fn new(
         table_name: &str,
         column_names: Option<Vec<String>>,
-        values: Vec<Value>,
+        values: Vec<Expression>,
     ) -> Insertion {
         return Insertion {
             table_name: table_name.to_string(),
@@ -190,6 +1039,9 @@ impl Insertion {
         };
     }
     pub fn validate(&self) -> Result<(), String> {
+        for value in &self.values {
+            executor::expression::validate(value, &HashMap::new())?;
+        }
         return self
             .column_names
             .as_ref()
@@ -214,7 +1066,11 @@ impl Insertion {
     }
 
     pub fn values(&self) -> Box<dyn Iterator<Item = Value>> {
-        Box::new(self.values.clone().into_iter())
+        let values = self
+            .values
+            .iter()
+            .map(|value| executor::expression::evaluate(value, &HashMap::new(), &[], &[]));
+        Box::new(values.collect::<Vec<_>>().into_iter())
     }
 }
 
@@ -236,12 +1092,29 @@ mod test_parsing {
                 Ast::Insert(Insertion::new(
                     "apples",
                     Some(vec!["slices".to_string()]),
-                    vec![Value::Integer(15)],
+                    vec![Expression::Literal(Value::Integer(15))],
                 ))
             )
         }
     }
 
+    #[test]
+    fn strict_create_table_statement() {
+        let statement = "CREATE TABLE apples(slices int, name varchar, core BLOB) STRICT;";
+        let parse_result = sqlite3::AstParser::new().parse(statement);
+        assert_eq!(
+            parse_result.unwrap(),
+            Ast::Create(TableSchema::new_strict(
+                "apples",
+                vec![
+                    Column::new("slices", Some("INT"), false),
+                    Column::new("name", Some("VARCHAR"), false),
+                    Column::new("core", Some("BLOB"), false),
+                ]
+            ))
+        )
+    }
+
     #[test]
     fn create_table_statement() {
         let statement = "CREATE TABLE apples(slices INTEGER);";
@@ -254,11 +1127,432 @@ mod test_parsing {
                 insert_stmt,
                 Ast::Create(TableSchema {
                     name: "apples".to_string(),
-                    columns: vec![Column::new("slices", false)]
+                    columns: vec![Column::new("slices", Some("INTEGER"), false)],
+                    strict: false,
+                    constraints: vec![],
                 })
             )
         }
     }
+
+    #[test]
+    fn create_table_statement_with_column_constraints() {
+        let statement = "CREATE TABLE apples(id INTEGER PRIMARY KEY NOT NULL, \
+            kind TEXT UNIQUE DEFAULT 'fuji', weight REAL DEFAULT -1 CHECK (weight != 0));";
+        let mut weight = Column::new("weight", Some("REAL"), false);
+        weight.default = Some(Expression::Literal(Value::Integer(-1)));
+        weight.checks = vec![Expression::binary(
+            Expression::Column("weight".to_string()),
+            BinaryOperator::NotEqual,
+            Expression::Literal(Value::Integer(0)),
+        )];
+        assert_eq!(
+            sqlite3::AstParser::new().parse(statement),
+            Ok(Ast::Create(TableSchema::new(
+                "apples",
+                vec![
+                    Column::with_constraints(
+                        "id",
+                        Some("INTEGER"),
+                        vec![ColumnConstraint::PrimaryKey, ColumnConstraint::NotNull]
+                    ),
+                    Column::with_constraints(
+                        "kind",
+                        Some("TEXT"),
+                        vec![
+                            ColumnConstraint::Unique,
+                            ColumnConstraint::Default(Expression::Literal(Value::Text(
+                                "fuji".to_string()
+                            ))),
+                        ]
+                    ),
+                    weight,
+                ],
+            )))
+        );
+    }
+
+    #[test]
+    fn create_table_statement_with_table_constraints() {
+        let statement = "CREATE TABLE likes(person INTEGER, fruit TEXT, rank INTEGER, \
+            PRIMARY KEY (person, fruit), UNIQUE (person, rank));";
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(
+            sqlite3::AstParser::new().parse(statement),
+            Ok(Ast::Create(TableSchema {
+                constraints: vec![
+                    TableConstraint::PrimaryKey(names(&["person", "fruit"])),
+                    TableConstraint::Unique(names(&["person", "rank"])),
+                ],
+                ..TableSchema::new(
+                    "likes",
+                    vec![
+                        Column::new("person", Some("INTEGER"), false),
+                        Column::new("fruit", Some("TEXT"), false),
+                        Column::new("rank", Some("INTEGER"), false),
+                    ],
+                )
+            }))
+        );
+    }
+
+    #[test]
+    fn create_table_statement_with_foreign_keys() {
+        let statement = "CREATE TABLE pets(id INTEGER REFERENCES owners ON DELETE CASCADE, \
+            name TEXT, FOREIGN KEY (id, name) REFERENCES owners(id, pet) \
+            ON UPDATE SET NULL ON DELETE NO ACTION);";
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        let mut id = Column::new("id", Some("INTEGER"), false);
+        id.references = Some(ForeignKey {
+            on_delete: ForeignKeyAction::Cascade,
+            ..ForeignKey::new(names(&["id"]), "owners", vec![], vec![])
+        });
+        assert_eq!(
+            sqlite3::AstParser::new().parse(statement),
+            Ok(Ast::Create(TableSchema {
+                constraints: vec![TableConstraint::ForeignKey(ForeignKey::new(
+                    names(&["id", "name"]),
+                    "owners",
+                    names(&["id", "pet"]),
+                    vec![
+                        (false, ForeignKeyAction::SetNull),
+                        (true, ForeignKeyAction::NoAction),
+                    ],
+                ))],
+                ..TableSchema::new("pets", vec![id, Column::new("name", Some("TEXT"), false)])
+            }))
+        );
+    }
+
+    #[test]
+    fn schemas_are_written_as_the_statements_they_are_parsed_from() {
+        let parser = sqlite3::AstParser::new();
+        for statement in &[
+            "CREATE TABLE pets (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE DEFAULT 'rex', \
+            age INTEGER DEFAULT -1 CHECK (age >= 0) CHECK (age < 30), \
+            legs DEFAULT (2 + 2) REFERENCES legs(n) ON DELETE SET NULL)",
+            "CREATE TABLE owners (a, b, PRIMARY KEY (a, b), UNIQUE (b, a), \
+            FOREIGN KEY (a, b) REFERENCES people ON UPDATE CASCADE) STRICT",
+            "CREATE UNIQUE INDEX names ON pets (name, id)",
+        ] {
+            match parser.parse(&format!("{};", statement)) {
+                Ok(Ast::Create(schema)) => assert_eq!(schema.to_string(), *statement),
+                Ok(Ast::CreateIndex(index, _)) => assert_eq!(index.to_string(), *statement),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn keywords_that_are_not_reserved_name_tables_and_columns() {
+        let parser = sqlite3::AstParser::new();
+        let statement = "CREATE TABLE end (id INTEGER PRIMARY KEY, first TEXT, last TEXT, \
+            text TEXT, index INTEGER, left INTEGER, to REFERENCES set)";
+        match parser.parse(&format!("{};", statement)) {
+            Ok(Ast::Create(schema)) => assert_eq!(schema.to_string(), statement),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        for statement in &[
+            "INSERT INTO end (first, last) VALUES ('a', 'b');",
+            "UPDATE end SET set = 1, offset = 2 WHERE left = 3;",
+            "SELECT first, max(last) FROM end AS left ORDER BY first NULLS LAST LIMIT 1 OFFSET 2;",
+            "ALTER TABLE end ADD key TEXT;",
+            "ALTER TABLE end RENAME TO begin;",
+            "CREATE INDEX first ON end (last, text);",
+        ] {
+            assert!(parser.parse(statement).is_ok(), "{}", statement);
+        }
+        match parser.parse("SELECT * FROM end left JOIN set ON end.left = set.left;") {
+            Ok(Ast::Select(selection)) => {
+                assert_eq!(selection.from.table, TableReference::new("end", None));
+                assert_eq!(selection.from.joins[0].operator, JoinOperator::Left);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn pragma_statement() {
+        let parser = sqlite3::AstParser::new();
+        assert_eq!(
+            parser.parse("PRAGMA foreign_keys;"),
+            Ok(Ast::Pragma("foreign_keys".to_string(), None))
+        );
+        assert_eq!(
+            parser.parse("pragma foreign_keys = on;"),
+            Ok(Ast::Pragma(
+                "foreign_keys".to_string(),
+                Some(Value::Text("on".to_string()))
+            ))
+        );
+        assert_eq!(
+            parser.parse("PRAGMA foreign_keys = 0;"),
+            Ok(Ast::Pragma(
+                "foreign_keys".to_string(),
+                Some(Value::Integer(0))
+            ))
+        );
+        for (value, expected) in &[
+            ("-1", Value::Integer(-1)),
+            ("-9223372036854775808", Value::Integer(i64::MIN)),
+            ("-99999999999999999999", Value::Real(-1e20)),
+        ] {
+            assert_eq!(
+                parser.parse(&format!("PRAGMA foreign_keys = {};", value)),
+                Ok(Ast::Pragma(
+                    "foreign_keys".to_string(),
+                    Some(expected.clone())
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn expressions_are_written_back_as_sql() {
+        let parse = |expression: &str| {
+            let statement = format!("SELECT {} FROM t;", expression);
+            match sqlite3::AstParser::new().parse(&statement) {
+                Ok(Ast::Select(selection)) => match selection.columns {
+                    ColumnSet::Expressions(expressions) => expressions[0].clone(),
+                    columns => panic!("unexpected columns: {:?}", columns),
+                },
+                result => panic!("unexpected result: {:?}", result),
+            }
+        };
+        let expressions = [
+            "a - (b - c) * -d",
+            "NOT (a OR b) AND c IS NOT NULL",
+            "a = (b = c)",
+            "count(*) + max(a, 'it''s', X'0AFF', NULL)",
+        ];
+        for expression in &expressions {
+            assert_eq!(parse(expression).to_string(), *expression);
+        }
+        assert_eq!(parse("((a + b)) + (c)").to_string(), "a + b + c");
+    }
+
+    #[test]
+    fn create_index_statement() {
+        let parser = sqlite3::AstParser::new();
+        assert_eq!(
+            parser.parse("CREATE UNIQUE INDEX IF NOT EXISTS by_kind ON apples(kind, weight);"),
+            Ok(Ast::CreateIndex(
+                IndexSchema::new(
+                    "by_kind",
+                    "apples",
+                    vec!["kind".to_string(), "weight".to_string()],
+                    true
+                ),
+                true
+            ))
+        );
+        assert_eq!(
+            parser.parse("create index w on apples(weight);"),
+            Ok(Ast::CreateIndex(
+                IndexSchema::new("w", "apples", vec!["weight".to_string()], false),
+                false
+            ))
+        );
+    }
+
+    #[test]
+    fn transaction_statements() {
+        let parser = sqlite3::AstParser::new();
+        assert_eq!(parser.parse("BEGIN;"), Ok(Ast::Begin));
+        assert_eq!(parser.parse("begin transaction;"), Ok(Ast::Begin));
+        assert_eq!(parser.parse("COMMIT;"), Ok(Ast::Commit));
+        assert_eq!(parser.parse("END TRANSACTION;"), Ok(Ast::Commit));
+        assert_eq!(parser.parse("ROLLBACK;"), Ok(Ast::Rollback));
+    }
+
+    #[test]
+    fn inserted_values_are_constant_expressions() {
+        let statement = "INSERT INTO t VALUES(-1, -0.5, -9223372036854775808, - -2, 1 + 2);";
+        let insertion = match sqlite3::AstParser::new().parse(statement) {
+            Ok(Ast::Insert(insertion)) => insertion,
+            result => panic!("unexpected result: {:?}", result),
+        };
+        // Negative integers are literals so that the smallest one fits an i64
+        assert_eq!(
+            insertion.values[2],
+            Expression::Literal(Value::Integer(i64::MIN))
+        );
+        assert_eq!(insertion.validate(), Ok(()));
+        assert_eq!(
+            insertion.values().collect::<Vec<_>>(),
+            vec![
+                Value::Integer(-1),
+                Value::Real(-0.5),
+                Value::Integer(i64::MIN),
+                Value::Integer(2),
+                Value::Integer(3),
+            ]
+        );
+
+        let statement = "INSERT INTO t VALUES(-a);";
+        match sqlite3::AstParser::new().parse(statement) {
+            Ok(Ast::Insert(insertion)) => {
+                assert_eq!(insertion.validate(), Err("no such column: a".to_string()))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn literals_of_every_type() {
+        let statement =
+            "INSERT INTO t VALUES(7, 2.5, 'it''s', x'00fF', NULL, 1e3, 99999999999999999999);";
+        assert_eq!(
+            sqlite3::AstParser::new().parse(statement),
+            Ok(Ast::Insert(Insertion::new(
+                "t",
+                None,
+                vec![
+                    Value::Integer(7),
+                    Value::Real(2.5),
+                    Value::Text("it's".to_string()),
+                    Value::Blob(vec![0x00, 0xff]),
+                    Value::Null,
+                    Value::Real(1000.0),
+                    Value::Real(1e20),
+                ]
+                .into_iter()
+                .map(Expression::Literal)
+                .collect(),
+            )))
+        );
+    }
+
+    #[test]
+    fn select_statement_with_aggregates() {
+        let statement = "SELECT kind, COUNT(*), group_concat(name, ';') FROM apples \
+            GROUP BY kind HAVING max(weight) > 2;";
+        let column = |name: &str| Expression::Column(name.to_string());
+        assert_eq!(
+            sqlite3::AstParser::new().parse(statement),
+            Ok(Ast::Select(Box::new(Selection::new(
+                FromClause::new(TableReference::new("apples", None), vec![]),
+                ColumnSet::Expressions(vec![
+                    column("kind"),
+                    Expression::function("count", vec![]),
+                    Expression::function(
+                        "group_concat",
+                        vec![
+                            column("name"),
+                            Expression::Literal(Value::Text(";".to_string()))
+                        ]
+                    ),
+                ]),
+                None,
+                vec![column("kind")],
+                Some(Expression::binary(
+                    Expression::function("max", vec![column("weight")]),
+                    BinaryOperator::Greater,
+                    Expression::Literal(Value::Integer(2)),
+                )),
+                vec![],
+                None,
+            ))))
+        );
+    }
+
+    #[test]
+    fn savepoint_statements() {
+        let parser = sqlite3::AstParser::new();
+        let name = "chunk1".to_string();
+        assert_eq!(
+            parser.parse("SAVEPOINT chunk1;"),
+            Ok(Ast::Savepoint(name.clone()))
+        );
+        assert_eq!(
+            parser.parse("RELEASE chunk1;"),
+            Ok(Ast::Release(name.clone()))
+        );
+        assert_eq!(
+            parser.parse("release savepoint chunk1;"),
+            Ok(Ast::Release(name.clone()))
+        );
+        assert_eq!(
+            parser.parse("ROLLBACK TO chunk1;"),
+            Ok(Ast::RollbackTo(name.clone()))
+        );
+        assert_eq!(
+            parser.parse("ROLLBACK TRANSACTION TO SAVEPOINT chunk1;"),
+            Ok(Ast::RollbackTo(name))
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_value {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(value: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn values_of_different_types_follow_sqlite_ordering() {
+        let mut values = vec![
+            Value::Blob(vec![0]),
+            Value::Text("b".to_string()),
+            Value::Real(1.5),
+            Value::Integer(2),
+            Value::Text("a".to_string()),
+            Value::Integer(1),
+            Value::Null,
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                Value::Null,
+                Value::Integer(1),
+                Value::Real(1.5),
+                Value::Integer(2),
+                Value::Text("a".to_string()),
+                Value::Text("b".to_string()),
+                Value::Blob(vec![0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn integers_and_reals_are_compared_exactly() {
+        assert_eq!(Value::Integer(3), Value::Real(3.0));
+        assert_eq!(hash(&Value::Integer(3)), hash(&Value::Real(3.0)));
+        assert_eq!(Value::Integer(-2) < Value::Real(-1.5), true);
+        assert_eq!(Value::Integer(-1) > Value::Real(-1.5), true);
+        // 2^53 + 1 is not a real, rounding it would make both equal
+        assert_eq!(
+            Value::Integer(9_007_199_254_740_993) > Value::Real(9_007_199_254_740_992.0),
+            true
+        );
+        assert_eq!(Value::Integer(i64::MAX) < Value::Real(1e19), true);
+    }
+
+    #[test]
+    fn values_are_displayed_like_sqlite() {
+        assert_eq!(Value::Real(2.0).to_string(), "2.0");
+        assert_eq!(Value::Real(0.25).to_string(), "0.25");
+        assert_eq!(Value::Real(0.1 + 0.2).to_string(), "0.3");
+        assert_eq!(Value::Real(-123456.789).to_string(), "-123456.789");
+        assert_eq!(Value::Real(0.0001).to_string(), "0.0001");
+        assert_eq!(Value::Real(0.00001).to_string(), "1.0e-05");
+        assert_eq!(Value::Real(1e14).to_string(), "100000000000000.0");
+        assert_eq!(Value::Real(1e20).to_string(), "1.0e+20");
+        assert_eq!(
+            Value::Real(9.223372036854776e18).to_string(),
+            "9.22337203685478e+18"
+        );
+        assert_eq!(Value::Real(f64::INFINITY).to_string(), "Inf");
+        assert_eq!(Value::Real(f64::NEG_INFINITY).to_string(), "-Inf");
+        assert_eq!(Value::Text("pear".to_string()).to_string(), "pear");
+        assert_eq!(Value::Blob(b"ab".to_vec()).to_string(), "ab");
+    }
 }
 
 #[cfg(test)]
@@ -271,7 +1565,10 @@ mod test_insertion {
         let insertion = Insertion::new(
             table_name,
             Some(vec!["count".to_string()]),
-            vec![Value::Integer(32), Value::Integer(1337)],
+            vec![
+                Expression::Literal(Value::Integer(32)),
+                Expression::Literal(Value::Integer(1337)),
+            ],
         );
         let result = insertion.validate();
         assert_eq!(result.is_err(), true);
@@ -287,7 +1584,10 @@ mod test_table_schema {
     fn validation_fails_if_there_are_duplicate_column_names() {
         let table_schema = super::TableSchema::new(
             "kings",
-            vec![Column::new("henry", false), Column::new("henry", false)],
+            vec![
+                Column::new("henry", None, false),
+                Column::new("henry", None, false),
+            ],
         );
 
         let result = table_schema.validate();
@@ -298,10 +1598,62 @@ mod test_table_schema {
     fn validation_fails_if_there_are_duplicate_primary_keys() {
         let table_schema = super::TableSchema::new(
             "kings",
-            vec![Column::new("henry", true), Column::new("james", true)],
+            vec![
+                Column::new("henry", None, true),
+                Column::new("james", None, true),
+            ],
         );
 
         let result = table_schema.validate();
         assert_eq!(result.is_err(), true);
     }
+
+    #[test]
+    fn validation_fails_if_table_constraints_are_on_unknown_columns_or_a_second_primary_key() {
+        let mut table_schema = super::TableSchema::new(
+            "kings",
+            vec![
+                Column::new("henry", None, true),
+                Column::new("james", None, false),
+            ],
+        );
+        table_schema.constraints = vec![TableConstraint::Unique(vec![
+            "james".to_string(),
+            "george".to_string(),
+        ])];
+        assert_eq!(
+            table_schema.validate(),
+            Err("no such column: george".to_string())
+        );
+
+        table_schema.constraints = vec![TableConstraint::PrimaryKey(vec![
+            "henry".to_string(),
+            "james".to_string(),
+        ])];
+        assert_eq!(
+            table_schema.validate(),
+            Err("table \"kings\" has more than one primary key".to_string())
+        );
+        table_schema.columns[0].is_primary_key = false;
+        assert_eq!(table_schema.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validation_fails_if_strict_columns_have_no_or_unknown_datatypes() {
+        let table_schema =
+            super::TableSchema::new_strict("kings", vec![Column::new("henry", None, false)]);
+        assert_eq!(
+            table_schema.validate(),
+            Err("missing datatype for kings.henry".to_string())
+        );
+
+        let table_schema = super::TableSchema::new_strict(
+            "kings",
+            vec![Column::new("henry", Some("varchar"), false)],
+        );
+        assert_eq!(
+            table_schema.validate(),
+            Err("unknown datatype for kings.henry: \"VARCHAR\"".to_string())
+        );
+    }
 }
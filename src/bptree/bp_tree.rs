@@ -1,69 +1,274 @@
 use super::bp_tree_node::{BPTreeNode, InternalNode, LeafNode};
+use super::range::Range;
 use super::Serializer;
-use super::{Entry, Key, Value};
+use super::{Entry, Key, Pager, Value};
 use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 use std::rc::Rc;
 
+/// B+tree whose nodes live on the pages of a `Pager`.
+///
+/// The root stays on the same page for the lifetime of the tree, so a tree
+/// can be found again from nothing but its root page.
 #[derive(Clone)]
 pub struct BPTree<K: Key, V: Value> {
     pub page_byte_size: usize,
-    root_node: Option<BPTreeNode<K, V>>,
+    root_page: u32,
     degree: usize,
     serializer: Serializer,
-}
-
-macro_rules! rcref {
-    ($expr:expr) => {{
-        Rc::new(RefCell::new($expr))
-    }};
+    pager: Rc<RefCell<dyn Pager>>,
+    entries: PhantomData<Entry<K, V>>,
 }
 
 impl<K: Key + 'static, V: Value + 'static> BPTree<K, V> {
-    pub fn new(degree: usize, page_byte_size: usize, serializer: Serializer) -> BPTree<K, V> {
+    /// Creates an empty tree on a newly allocated page.
+    ///
+    /// `page_byte_size` is the number of bytes a node may take up and can be
+    /// at most the page size of the pager.
+    pub fn new(
+        degree: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+        pager: Rc<RefCell<dyn Pager>>,
+    ) -> Result<BPTree<K, V>, String> {
+        let root_page = {
+            let mut pager = pager.borrow_mut();
+            if page_byte_size > pager.page_size() {
+                return Err(format!(
+                    "nodes of {} bytes do not fit in pages of {} bytes",
+                    page_byte_size,
+                    pager.page_size()
+                ));
+            }
+            let root_page = pager.allocate_page()?;
+            BPTreeNode::LeafNode(LeafNode::<K, V>::new()).store(&mut *pager, root_page)?;
+            root_page
+        };
+        Ok(BPTree::open(
+            root_page,
+            degree,
+            page_byte_size,
+            serializer,
+            pager,
+        ))
+    }
+
+    /// Opens the tree whose root is on `root_page`.
+    pub fn open(
+        root_page: u32,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+        pager: Rc<RefCell<dyn Pager>>,
+    ) -> BPTree<K, V> {
         BPTree {
             degree,
             serializer,
             page_byte_size,
-            root_node: None,
+            root_page,
+            pager,
+            entries: PhantomData,
         }
     }
+
+    /// Creates an empty tree of other entries, laid out like this one on
+    /// the same pager.
+    pub fn new_sibling<K2: Key + 'static, V2: Value + 'static>(
+        &self,
+    ) -> Result<BPTree<K2, V2>, String> {
+        BPTree::new(
+            self.degree,
+            self.page_byte_size,
+            self.serializer.clone(),
+            self.pager.clone(),
+        )
+    }
+
+    /// Opens the tree of other entries whose root is on `root_page`, laid
+    /// out like this one on the same pager.
+    pub fn open_sibling<K2: Key + 'static, V2: Value + 'static>(
+        &self,
+        root_page: u32,
+    ) -> BPTree<K2, V2> {
+        BPTree::open(
+            root_page,
+            self.degree,
+            self.page_byte_size,
+            self.serializer.clone(),
+            self.pager.clone(),
+        )
+    }
+
+    pub fn root_page(&self) -> u32 {
+        self.root_page
+    }
+
     pub fn insert(&mut self, entry: Entry<K, V>) -> Result<(), String> {
-        match &mut self.root_node {
-            None => {
-                let new_root = LeafNode::new_from_entry(entry);
-                self.root_node = Some(BPTreeNode::LeafNode(rcref!(new_root)));
+        // Both halves of a split leaf have to fit on a page
+        if self.serializer.size(&entry) * 2 > self.page_byte_size {
+            return Err("string or blob too big".to_string());
+        }
+
+        let mut pager = self.pager.borrow_mut();
+        let pager = &mut *pager;
+        let mut root_node = BPTreeNode::load(pager, self.root_page)?;
+        let has_node_split_into_two = root_node.insert(
+            self.root_page,
+            entry,
+            self.degree,
+            self.page_byte_size,
+            self.serializer.clone(),
+            pager,
+        )?;
+        match has_node_split_into_two {
+            None => Ok(()),
+            Some((key, right_page)) => {
+                // The left half moves off the root page to make room for
+                // the node joining both halves
+                let left_page = pager.allocate_page()?;
+                root_node.store(pager, left_page)?;
+                let new_root = match root_node {
+                    BPTreeNode::LeafNode(_) => {
+                        InternalNode::<K, V>::from_leaves(key, left_page, right_page)
+                    }
+                    BPTreeNode::InternalNode(_) => {
+                        InternalNode::from_internals(key, left_page, right_page)
+                    }
+                };
+                BPTreeNode::InternalNode(new_root).store(pager, self.root_page)
+            }
+        }
+    }
+
+    /// Removes the entry with `key` and returns its value.
+    ///
+    /// Nodes left less than half full are merged with or take entries from
+    /// a sibling. When the root is left with a single child, that child
+    /// takes its place on the root page.
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, String> {
+        let mut pager = self.pager.borrow_mut();
+        let pager = &mut *pager;
+        let mut root_node = BPTreeNode::<K, V>::load(pager, self.root_page)?;
+        let removed = root_node.remove(
+            self.root_page,
+            key,
+            self.degree,
+            self.page_byte_size,
+            self.serializer.clone(),
+            pager,
+        )?;
+        if let BPTreeNode::InternalNode(internal_node) = &root_node {
+            if internal_node.num_keys() == 0 {
+                let child_page = internal_node.first_child();
+                BPTreeNode::<K, V>::load(pager, child_page)?.store(pager, self.root_page)?;
+                pager.free_page(child_page)?;
             }
-            Some(root_node) => {
-                match root_node.insert(
-                    entry,
-                    self.degree,
-                    self.page_byte_size,
-                    self.serializer.clone(),
-                ) {
-                    Err(err) => return Err(err),
-                    Ok(has_node_split_into_two) => match has_node_split_into_two {
-                        None => return Ok(()),
-                        Some(split_node) => match (root_node, &split_node) {
-                            (BPTreeNode::LeafNode(left), BPTreeNode::LeafNode(right)) => {
-                                self.root_node = Some(BPTreeNode::InternalNode(rcref!(
-                                    InternalNode::from_leaves(left.clone(), right.clone())
-                                )));
-                            }
-                            (BPTreeNode::InternalNode(left), BPTreeNode::InternalNode(right)) => {
-                                self.root_node = Some(BPTreeNode::InternalNode(rcref!(
-                                    InternalNode::from_internals(left.clone(), right.clone())
-                                )));
-                            }
-                            _ => {
-                                debug_assert!(false, "oops");
-                            }
-                        },
-                    },
+        }
+        Ok(removed)
+    }
+
+    /// Replaces the value stored under `key` and returns the previous one.
+    ///
+    /// The value is changed in place in its leaf, unless the leaf no longer
+    /// fits on its page, in which case the entry is removed and inserted
+    /// again so that the leaf gets split.
+    pub fn update(&mut self, key: &K, value: V) -> Result<Option<V>, String> {
+        let entry = Entry::new(key.clone(), value);
+        if self.serializer.size(&entry) * 2 > self.page_byte_size {
+            return Err("string or blob too big".to_string());
+        }
+
+        let (page_no, mut leaf_node) = self.find_leaf(Some(key))?;
+        let previous = match leaf_node.replace(key, entry.value.clone()) {
+            None => return Ok(None),
+            Some(previous) => previous,
+        };
+        if self.serializer.size(&leaf_node) < self.page_byte_size {
+            BPTreeNode::LeafNode(leaf_node).store(&mut *self.pager.borrow_mut(), page_no)?;
+        } else {
+            self.remove(key)?;
+            self.insert(entry)?;
+        }
+        Ok(Some(previous))
+    }
+
+    /// Frees every page of the tree, the root page included, after which
+    /// the tree can no longer be used.
+    pub fn free(self) -> Result<(), String> {
+        let mut pager = self.pager.borrow_mut();
+        let pager = &mut *pager;
+        BPTreeNode::<K, V>::load(pager, self.root_page)?.free(self.root_page, pager)
+    }
+
+    /// Returns the value stored under `key`.
+    pub fn get(&self, key: &K) -> Result<Option<V>, String> {
+        let (_, leaf_node) = self.find_leaf(Some(key))?;
+        Ok(leaf_node.get(key).cloned())
+    }
+
+    /// Returns the entry with the largest key, reading only the pages on
+    /// the rightmost path of the tree.
+    pub fn last(&self) -> Result<Option<(K, V)>, String> {
+        let mut pager = self.pager.borrow_mut();
+        let pager = &mut *pager;
+        let mut page_no = self.root_page;
+        loop {
+            match BPTreeNode::<K, V>::load(pager, page_no)? {
+                BPTreeNode::LeafNode(leaf_node) => {
+                    let last = leaf_node.into_entries().pop();
+                    return Ok(last.map(|entry| (entry.key, entry.value)));
+                }
+                BPTreeNode::InternalNode(internal_node) => {
+                    page_no = internal_node.last_child();
                 }
             }
         }
+    }
+
+    pub fn contains_key(&self, key: &K) -> Result<bool, String> {
+        let (_, leaf_node) = self.find_leaf(Some(key))?;
+        Ok(leaf_node.get(key).is_some())
+    }
 
-        Ok(())
+    /// Returns the entries with keys in `range` in key order. Only the leaf
+    /// holding the start of the range is read up front, later leaves are
+    /// read as the iterator reaches them.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Result<Range<K, V>, String> {
+        let (_, leaf_node) = match range.start_bound() {
+            Bound::Included(start) | Bound::Excluded(start) => self.find_leaf(Some(start))?,
+            Bound::Unbounded => self.find_leaf(None)?,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => Bound::Included(end.clone()),
+            Bound::Excluded(end) => Bound::Excluded(end.clone()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Ok(Range::new(
+            self.pager.clone(),
+            leaf_node,
+            range.start_bound(),
+            end,
+        ))
+    }
+
+    /// Loads the leaf that `key` belongs in, or the leftmost leaf if there
+    /// is no key, along with its page.
+    fn find_leaf(&self, key: Option<&K>) -> Result<(u32, LeafNode<K, V>), String> {
+        let mut pager = self.pager.borrow_mut();
+        let pager = &mut *pager;
+        let mut page_no = self.root_page;
+        loop {
+            match BPTreeNode::<K, V>::load(pager, page_no)? {
+                BPTreeNode::LeafNode(leaf_node) => return Ok((page_no, leaf_node)),
+                BPTreeNode::InternalNode(internal_node) => {
+                    page_no = match key {
+                        Some(key) => internal_node.child(key),
+                        None => internal_node.first_child(),
+                    };
+                }
+            }
+        }
     }
 
     /// Returns a depth-first traversal of the keys in the tree.
@@ -72,45 +277,48 @@ impl<K: Key + 'static, V: Value + 'static> BPTree<K, V> {
     /// the construction of the tree.
     #[allow(dead_code)]
     fn keys(&mut self) -> Vec<K> {
-        match &self.root_node {
-            None => vec![],
-            Some(root_node) => root_node.keys(),
-        }
+        let mut pager = self.pager.borrow_mut();
+        BPTreeNode::<K, V>::load(&mut *pager, self.root_page)
+            .and_then(|root_node| root_node.keys(&mut *pager))
+            .unwrap()
     }
 }
 
+/// Iterates over the values in key order, reading one leaf at a time. The
+/// iteration ends with the error of a page that cannot be read.
 impl<K: Key + 'static, V: Value + 'static> IntoIterator for BPTree<K, V> {
-    type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
+    type Item = Result<V, String>;
+    type IntoIter = Box<dyn Iterator<Item = Result<V, String>>>;
     fn into_iter(self) -> Self::IntoIter {
-        match self.root_node {
-            None => vec![].into_iter(),
-            Some(root_node) => root_node.into_iter(),
+        match self.range(..) {
+            Ok(range) => Box::new(range.map(|entry| entry.map(|(_, value)| value))),
+            Err(err) => Box::new(std::iter::once(Err(err))),
         }
     }
 }
 
 #[cfg(test)]
 mod bptree_test {
+    use super::super::pager::in_memory;
     use super::*;
     use pretty_assertions::assert_eq;
 
     #[test]
     fn insertion_works() {
-        let mut bptree = BPTree::new(3, 3, Serializer::Mock);
+        let mut bptree = BPTree::new(3, 3, Serializer::Mock, in_memory()).unwrap();
         bptree.insert(Entry::new(1, vec![1, 2, 3])).unwrap();
         bptree.insert(Entry::new(3, vec![400, 500, 600])).unwrap();
         bptree.insert(Entry::new(2, vec![-1, -2, -3])).unwrap();
 
         assert_eq!(
-            bptree.into_iter().collect::<Vec<Vec<i32>>>(),
-            vec![vec![1, 2, 3], vec![-1, -2, -3], vec![400, 500, 600]],
+            bptree.into_iter().collect::<Result<Vec<Vec<i32>>, _>>(),
+            Ok(vec![vec![1, 2, 3], vec![-1, -2, -3], vec![400, 500, 600]]),
         );
     }
 
     #[test]
     fn tree_is_built_correctly() {
-        let mut bptree = BPTree::new(4, 4, Serializer::Mock);
+        let mut bptree = BPTree::new(4, 4, Serializer::Mock, in_memory()).unwrap();
         assert_eq!(bptree.insert(Entry::new(1, vec![1])).is_err(), false);
         assert_eq!(bptree.insert(Entry::new(2, vec![1])).is_err(), false);
         assert_eq!(bptree.insert(Entry::new(3, vec![1])).is_err(), false);
@@ -122,11 +330,314 @@ mod bptree_test {
         assert_eq!(bptree.insert(Entry::new(20, vec![1])).is_err(), false);
         assert_eq!(bptree.insert(Entry::new(30, vec![1])).is_err(), false);
 
-        println!("{}", bptree.root_node.clone().unwrap());
-
         assert_eq!(
             bptree.keys(),
             vec![1, 2, 3, 3, 4, 3, 4, 5, 5, 6, 10, 10, 11, 20, 20, 30]
         );
     }
+
+    #[test]
+    fn tree_can_be_reopened_from_its_root_page() {
+        let pager = in_memory();
+        let mut bptree = BPTree::new(4, 4, Serializer::Mock, pager.clone()).unwrap();
+        for i in 1..=10 {
+            bptree.insert(Entry::new(i, vec![i])).unwrap();
+        }
+
+        let reopened =
+            BPTree::<i32, Vec<i32>>::open(bptree.root_page(), 4, 4, Serializer::Mock, pager);
+        assert_eq!(
+            reopened.into_iter().collect::<Result<Vec<Vec<i32>>, _>>(),
+            Ok((1..=10).map(|i| vec![i]).collect::<Vec<Vec<i32>>>()),
+        );
+    }
+
+    fn tree_with_keys(keys: impl Iterator<Item = i32>) -> BPTree<i32, Vec<i32>> {
+        tree_with_keys_on(in_memory(), keys)
+    }
+
+    fn tree_with_keys_on(
+        pager: Rc<RefCell<dyn Pager>>,
+        keys: impl Iterator<Item = i32>,
+    ) -> BPTree<i32, Vec<i32>> {
+        let mut bptree = BPTree::new(4, 4, Serializer::Mock, pager).unwrap();
+        for i in keys {
+            bptree.insert(Entry::new(i, vec![i])).unwrap();
+        }
+        bptree
+    }
+
+    /// Pager passing on to another one, which keeps track of the pages read
+    /// and fails to read the page marked as unreadable.
+    struct UnreliablePager {
+        pager: Rc<RefCell<dyn Pager>>,
+        reads: Vec<u32>,
+        unreadable: Option<u32>,
+    }
+
+    impl Pager for UnreliablePager {
+        fn page_size(&self) -> usize {
+            self.pager.borrow().page_size()
+        }
+
+        fn read_page(&mut self, page_no: u32) -> Result<Vec<u8>, String> {
+            self.reads.push(page_no);
+            match self.unreadable {
+                Some(unreadable) if unreadable == page_no => Err("disk I/O error".to_string()),
+                _ => self.pager.borrow_mut().read_page(page_no),
+            }
+        }
+
+        fn write_page(&mut self, page_no: u32, bytes: Vec<u8>) -> Result<(), String> {
+            self.pager.borrow_mut().write_page(page_no, bytes)
+        }
+
+        fn allocate_page(&mut self) -> Result<u32, String> {
+            self.pager.borrow_mut().allocate_page()
+        }
+
+        fn free_page(&mut self, page_no: u32) -> Result<(), String> {
+            self.pager.borrow_mut().free_page(page_no)
+        }
+    }
+
+    fn unreliable_pager() -> Rc<RefCell<UnreliablePager>> {
+        Rc::new(RefCell::new(UnreliablePager {
+            pager: in_memory(),
+            reads: vec![],
+            unreadable: None,
+        }))
+    }
+
+    #[test]
+    fn values_are_found_by_key() {
+        let bptree = tree_with_keys((1..=30).map(|i| i * 2));
+
+        for i in 1..=30 {
+            assert_eq!(bptree.get(&(i * 2)).unwrap(), Some(vec![i * 2]));
+            assert_eq!(bptree.get(&(i * 2 - 1)).unwrap(), None);
+        }
+        assert_eq!(bptree.contains_key(&2).unwrap(), true);
+        assert_eq!(bptree.contains_key(&0).unwrap(), false);
+    }
+
+    #[test]
+    fn last_entry_is_the_one_with_the_largest_key() {
+        let mut bptree = tree_with_keys((1..=30).rev());
+        assert_eq!(bptree.last().unwrap(), Some((30, vec![30])));
+
+        for i in (11..=30).rev() {
+            bptree.remove(&i).unwrap();
+        }
+        assert_eq!(bptree.last().unwrap(), Some((10, vec![10])));
+        assert_eq!(tree_with_keys(0..0).last().unwrap(), None);
+    }
+
+    #[test]
+    fn ranges_span_multiple_leaves() {
+        let bptree = tree_with_keys((1..=30).rev());
+        let keys =
+            |range: Range<i32, Vec<i32>>| range.map(|entry| entry.unwrap().0).collect::<Vec<i32>>();
+
+        assert_eq!(
+            keys(bptree.range(5..12).unwrap()),
+            (5..12).collect::<Vec<i32>>()
+        );
+        assert_eq!(keys(bptree.range(..=3).unwrap()), vec![1, 2, 3]);
+        assert_eq!(keys(bptree.range(28..).unwrap()), vec![28, 29, 30]);
+        assert_eq!(
+            keys(
+                bptree
+                    .range((Bound::Excluded(10), Bound::Included(13)))
+                    .unwrap()
+            ),
+            vec![11, 12, 13]
+        );
+        assert_eq!(keys(bptree.range(31..).unwrap()), vec![]);
+        assert_eq!(keys(bptree.range(..).unwrap()).len(), 30);
+    }
+
+    #[test]
+    fn values_are_iterated_over_in_key_order_one_leaf_at_a_time() {
+        let pager = unreliable_pager();
+        // Visits the keys in a scrambled order
+        let bptree = tree_with_keys_on(pager.clone(), (1..=30).map(|i| i * 37 % 31));
+        let (first_page, first_leaf) = bptree.find_leaf(None).unwrap();
+        let second_page = first_leaf.next().unwrap();
+        let first_leaf_len = first_leaf.into_entries().len();
+        pager.borrow_mut().reads.clear();
+
+        let mut values = bptree.into_iter();
+        assert_eq!(pager.borrow().reads.last(), Some(&first_page));
+        let first_values = values.by_ref().take(first_leaf_len).collect::<Vec<_>>();
+        assert!(!pager.borrow().reads.contains(&second_page));
+        let values = first_values.into_iter().chain(values);
+        assert_eq!(
+            values.collect::<Result<Vec<_>, _>>(),
+            Ok((1..=30).map(|i| vec![i]).collect::<Vec<_>>())
+        );
+        assert!(pager.borrow().reads.contains(&second_page));
+    }
+
+    #[test]
+    fn leaves_that_cannot_be_read_end_the_iteration_with_the_error() {
+        let pager = unreliable_pager();
+        let bptree = tree_with_keys_on(pager.clone(), 1..=30);
+        let (_, first_leaf) = bptree.find_leaf(None).unwrap();
+        pager.borrow_mut().unreadable = first_leaf.next();
+        let entries = bptree.range(..).unwrap().collect::<Vec<_>>();
+
+        let num_read = entries.iter().take_while(|entry| entry.is_ok()).count();
+        assert_eq!(num_read, first_leaf.into_entries().len());
+        assert_eq!(&entries[num_read..], [Err("disk I/O error".to_string())]);
+        assert_eq!(
+            bptree.into_iter().collect::<Result<Vec<_>, _>>(),
+            Err("disk I/O error".to_string())
+        );
+    }
+
+    #[test]
+    fn removed_entries_are_no_longer_found() {
+        let mut bptree = tree_with_keys(1..=10);
+
+        assert_eq!(bptree.remove(&4).unwrap(), Some(vec![4]));
+        assert_eq!(bptree.remove(&4).unwrap(), None);
+        assert_eq!(bptree.get(&4).unwrap(), None);
+        assert_eq!(
+            bptree.into_iter().collect::<Result<Vec<Vec<i32>>, _>>(),
+            Ok(vec![1, 2, 3, 5, 6, 7, 8, 9, 10]
+                .into_iter()
+                .map(|i| vec![i])
+                .collect::<Vec<Vec<i32>>>())
+        );
+    }
+
+    #[test]
+    fn removed_keys_can_be_inserted_again() {
+        let mut bptree = tree_with_keys(1..=6);
+        bptree.remove(&3).unwrap();
+
+        assert_eq!(bptree.insert(Entry::new(3, vec![3])).is_err(), false);
+        assert_eq!(bptree.insert(Entry::new(3, vec![3])).is_err(), true);
+        assert_eq!(bptree.get(&3).unwrap(), Some(vec![3]));
+    }
+
+    #[test]
+    fn underfull_leaves_are_merged_with_a_sibling() {
+        let mut bptree = tree_with_keys(1..=6);
+        assert_eq!(bptree.keys(), vec![1, 2, 3, 3, 4, 3, 4, 5, 5, 6]);
+
+        bptree.remove(&1).unwrap();
+        assert_eq!(bptree.keys(), vec![2, 3, 4, 5, 5, 6]);
+    }
+
+    #[test]
+    fn underfull_leaves_take_entries_from_a_sibling() {
+        let mut bptree = tree_with_keys(1..=5);
+        assert_eq!(bptree.keys(), vec![1, 2, 3, 3, 4, 5]);
+
+        bptree.remove(&1).unwrap();
+        assert_eq!(bptree.keys(), vec![2, 3, 4, 4, 5]);
+    }
+
+    #[test]
+    fn underfull_internal_nodes_are_merged_and_the_root_collapses() {
+        let mut bptree = tree_with_keys(1..=12);
+        assert_eq!(
+            bptree.keys(),
+            vec![1, 2, 3, 3, 4, 3, 4, 5, 5, 6, 7, 7, 8, 9, 9, 10, 9, 10, 11, 11, 12]
+        );
+
+        for i in 1..=3 {
+            bptree.remove(&i).unwrap();
+        }
+        assert_eq!(
+            bptree.keys(),
+            vec![4, 5, 6, 7, 7, 8, 7, 8, 9, 9, 10, 9, 10, 11, 11, 12]
+        );
+    }
+
+    #[test]
+    fn underfull_internal_nodes_take_entries_from_a_sibling() {
+        let mut bptree = tree_with_keys(1..=14);
+        for i in 1..=3 {
+            bptree.remove(&i).unwrap();
+        }
+        assert_eq!(
+            bptree.keys(),
+            vec![4, 5, 6, 7, 7, 8, 7, 8, 9, 9, 10, 11, 11, 12, 13, 13, 14]
+        );
+    }
+
+    #[test]
+    fn removing_every_entry_leaves_an_empty_tree_behind() {
+        let pager = in_memory();
+        let mut bptree = BPTree::new(4, 4, Serializer::Mock, pager.clone()).unwrap();
+        for i in 1..=100 {
+            bptree.insert(Entry::new(i, vec![i])).unwrap();
+        }
+        let num_pages = pager.borrow().num_pages();
+
+        // Visits the keys in a scrambled order
+        let keys = (1..=100).map(|i| i * 37 % 101).collect::<Vec<i32>>();
+        for (removed, key) in keys.iter().enumerate() {
+            assert_eq!(bptree.remove(key).unwrap(), Some(vec![*key]));
+            for remaining in &keys[removed + 1..] {
+                assert_eq!(bptree.contains_key(remaining).unwrap(), true);
+            }
+            assert_eq!(bptree.clone().into_iter().count(), 99 - removed);
+        }
+        assert_eq!(bptree.keys(), vec![]);
+
+        for i in 1..=100 {
+            bptree.insert(Entry::new(i, vec![i])).unwrap();
+        }
+        assert_eq!(pager.borrow().num_pages(), num_pages);
+    }
+
+    #[test]
+    fn updated_values_replace_the_previous_ones() {
+        let mut bptree = tree_with_keys(1..=8);
+        assert_eq!(bptree.update(&3, vec![30]), Ok(Some(vec![3])));
+        assert_eq!(bptree.update(&9, vec![90]), Ok(None));
+        assert_eq!(bptree.get(&3), Ok(Some(vec![30])));
+        assert_eq!(bptree.get(&9), Ok(None));
+    }
+
+    #[test]
+    fn updated_values_that_overflow_their_leaf_split_it() {
+        let mut bptree = BPTree::new(4, 64, Serializer::RMP, in_memory()).unwrap();
+        for i in 1..=4 {
+            bptree.insert(Entry::new(i, vec![i])).unwrap();
+        }
+        bptree.update(&2, vec![2; 24]).unwrap();
+        let entries = bptree.range(..).unwrap().collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            entries,
+            Ok(vec![
+                (1, vec![1]),
+                (2, vec![2; 24]),
+                (3, vec![3]),
+                (4, vec![4])
+            ])
+        );
+    }
+
+    #[test]
+    fn freed_trees_give_back_all_of_their_pages() {
+        let pager = in_memory();
+        let bptree = tree_with_keys_on(pager.clone(), 1..=40);
+        let num_pages = pager.borrow().num_pages();
+
+        bptree.free().unwrap();
+        tree_with_keys_on(pager.clone(), 1..=40);
+        assert_eq!(pager.borrow().num_pages(), num_pages);
+    }
+
+    #[test]
+    fn entries_too_large_for_a_page_are_rejected() {
+        let mut bptree = BPTree::new(4, 64, Serializer::RMP, in_memory()).unwrap();
+        assert_eq!(bptree.insert(Entry::new(1, vec![1; 64])).is_err(), true);
+        assert_eq!(bptree.insert(Entry::new(1, vec![1; 8])).is_err(), false);
+    }
 }
@@ -1,32 +1,24 @@
 use super::super::super::Serializer;
 use super::Entry;
 use super::{BPTreeNode, InternalNodeEntry};
-use super::{Key, Value};
+use super::{Key, Pager, Value};
 use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::Display;
 
-impl<K: Key + 'static, V: Value + 'static> IntoIterator for InternalNodeEntry<K, V> {
-    type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
-    fn into_iter(self) -> Self::IntoIter {
-        self.left.into_iter()
-    }
-}
-
-impl<K: Key, V: Value> PartialOrd for InternalNodeEntry<K, V> {
+impl<K: Key> PartialOrd for InternalNodeEntry<K> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<K: Key, V: Value> Ord for InternalNodeEntry<K, V> {
+impl<K: Key> Ord for InternalNodeEntry<K> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.key.cmp(&other.key)
     }
 }
 
-impl<K: Key + 'static, V: Value + 'static> Display for InternalNodeEntry<K, V> {
+impl<K: Key + 'static> Display for InternalNodeEntry<K> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
@@ -36,48 +28,38 @@ impl<K: Key + 'static, V: Value + 'static> Display for InternalNodeEntry<K, V> {
     }
 }
 
-impl<K: Key + 'static, V: Value + 'static> InternalNodeEntry<K, V> {
-    pub fn new(key: K, left: BPTreeNode<K, V>, right: BPTreeNode<K, V>) -> InternalNodeEntry<K, V> {
+impl<K: Key + 'static> InternalNodeEntry<K> {
+    pub fn new(key: K, left: u32, right: u32) -> InternalNodeEntry<K> {
         InternalNodeEntry { key, left, right }
     }
 
-    pub fn insert(
-        &mut self,
+    pub fn insert<V: Value + 'static>(
+        &self,
         entry: Entry<K, V>,
         degree: usize,
         page_byte_size: usize,
         serializer: Serializer,
-    ) -> Result<Option<BPTreeNode<K, V>>, String> {
-        if entry.key < self.key {
-            self.left.insert(entry, degree, page_byte_size, serializer)
-        } else {
-            self.right.insert(entry, degree, page_byte_size, serializer)
-        }
+        pager: &mut dyn Pager,
+    ) -> Result<Option<(K, u32)>, String> {
+        let page_no = self.side(&entry.key);
+        let mut node = BPTreeNode::load(pager, page_no)?;
+        node.insert(page_no, entry, degree, page_byte_size, serializer, pager)
     }
 
-    pub fn side(&self, key: &K) -> BPTreeNode<K, V> {
+    /// Page of the child that `key` belongs in.
+    pub fn side(&self, key: &K) -> u32 {
         if key < &self.key {
-            match &self.left {
-                BPTreeNode::LeafNode(leaf_node) => BPTreeNode::LeafNode(leaf_node.clone()),
-                BPTreeNode::InternalNode(internal_node) => {
-                    BPTreeNode::InternalNode(internal_node.clone())
-                }
-            }
+            self.left
         } else {
-            match &self.right {
-                BPTreeNode::LeafNode(leaf_node) => BPTreeNode::LeafNode(leaf_node.clone()),
-                BPTreeNode::InternalNode(internal_node) => {
-                    BPTreeNode::InternalNode(internal_node.clone())
-                }
-            }
+            self.right
         }
     }
 
-    pub fn keys(&self) -> Vec<K> {
-        let mut keys = self.left.keys();
+    pub fn keys<V: Value + 'static>(&self, pager: &mut dyn Pager) -> Result<Vec<K>, String> {
+        let mut keys = BPTreeNode::<K, V>::load(pager, self.left)?.keys(pager)?;
         keys.push(self.key.clone());
-        keys.extend(self.right.keys());
+        keys.extend(BPTreeNode::<K, V>::load(pager, self.right)?.keys(pager)?);
 
-        keys
+        Ok(keys)
     }
 }
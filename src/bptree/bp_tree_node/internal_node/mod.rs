@@ -1,23 +1,14 @@
+use super::super::Serializer;
 use super::Entry;
-use super::{BPTreeNode, InternalNode, InternalNodeEntry, LeafNode};
-use super::{Key, Value};
-use std::cell::RefCell;
+use super::{BPTreeNode, InternalNode, InternalNodeEntry};
+use super::{Key, Pager, Value};
+use std::cmp;
 use std::fmt;
 use std::fmt::Display;
-use std::rc::Rc;
-use super::super::Serializer;
+use std::marker::PhantomData;
 
 mod internal_node_entry;
 
-impl<K: Key + 'static, V: Value + 'static> IntoIterator for InternalNode<K, V> {
-    type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
-    fn into_iter(self) -> Self::IntoIter {
-        debug_assert!(!self.entries.is_empty(), "internal node must have entries");
-        self.entries[0].clone().into_iter()
-    }
-}
-
 impl<K: Key + 'static, V: Value + 'static> Display for InternalNode<K, V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut first = true;
@@ -36,177 +27,366 @@ impl<K: Key + 'static, V: Value + 'static> Display for InternalNode<K, V> {
 }
 
 impl<K: Key + 'static, V: Value + 'static> InternalNode<K, V> {
-    pub fn from_leaves(
-        left: Rc<RefCell<LeafNode<K, V>>>,
-        right: Rc<RefCell<LeafNode<K, V>>>,
-    ) -> InternalNode<K, V> {
-        InternalNode::from_two_nodes(BPTreeNode::LeafNode(left), BPTreeNode::LeafNode(right))
+    pub fn from_leaves(key: K, left: u32, right: u32) -> InternalNode<K, V> {
+        InternalNode::from_two_nodes(key, left, right)
     }
 
-    pub fn from_internals(
-        left: Rc<RefCell<InternalNode<K, V>>>,
-        right: Rc<RefCell<InternalNode<K, V>>>,
-    ) -> InternalNode<K, V> {
-        debug_assert!(
-            right.borrow().entries.len() > 0,
-            "right node should have entries"
-        );
-        let key = right.borrow().left_key();
-        let new_right = Rc::new(RefCell::new(InternalNode::new_with_entries(
-            right.borrow().entries.clone()[1..].to_vec(),
-        )));
-        InternalNode { entries: vec![InternalNodeEntry::new(
-            key,
-            BPTreeNode::InternalNode(left),
-            BPTreeNode::InternalNode(new_right),
-        )] }
+    /// Joins the halves of a split internal node. `key` is the middle key
+    /// that moved up out of the split node.
+    pub fn from_internals(key: K, left: u32, right: u32) -> InternalNode<K, V> {
+        InternalNode::from_two_nodes(key, left, right)
     }
 
-    fn from_two_nodes(left: BPTreeNode<K, V>, right: BPTreeNode<K, V>) -> InternalNode<K, V> {
-        debug_assert!(right.len() > 0, "right node should have entries");
-        let key = right.left_key();
-        InternalNode { entries: vec![InternalNodeEntry::new(key, left, right)] }
+    fn from_two_nodes(key: K, left: u32, right: u32) -> InternalNode<K, V> {
+        InternalNode::new_with_entries(vec![InternalNodeEntry::new(key, left, right)])
     }
 
-    fn new_with_entries(entries: Vec<InternalNodeEntry<K, V>>) -> InternalNode<K, V> {
-        InternalNode { entries }
+    fn new_with_entries(entries: Vec<InternalNodeEntry<K>>) -> InternalNode<K, V> {
+        InternalNode {
+            entries,
+            values: PhantomData,
+        }
     }
 
-    pub fn left_key(&self) -> K {
-        let entries = &self.entries;
+    /// Page of the leftmost child.
+    pub fn first_child(&self) -> u32 {
         debug_assert!(
-            entries.len() > 0,
+            self.entries.len() > 0,
             "internal node should have at least 1 entry"
         );
-        return entries[0].key.clone();
+        self.entries[0].left
     }
 
-    pub fn right_key(&self) -> K {
-        let entries = &self.entries;
-        debug_assert!(
-            entries.len() > 0,
-            "internal node should have at least 1 entry"
-        );
-        return entries[entries.len() - 1].key.clone();
+    /// Page of the rightmost child.
+    pub fn last_child(&self) -> u32 {
+        *self.parts().1.last().unwrap()
+    }
+
+    /// Pages of the children from left to right.
+    pub fn children(&self) -> Vec<u32> {
+        self.parts().1
     }
 
-    pub fn insert(&mut self, entry: Entry<K, V>, degree: usize, page_byte_size: usize, serializer: Serializer) -> Result<Option<BPTreeNode<K, V>>, String> {
-        match self
+    /// Page of the child whose subtree holds `key`.
+    pub fn child(&self, key: &K) -> u32 {
+        self.parts().1[self.child_index(key)]
+    }
+
+    pub fn insert(
+        &mut self,
+        entry: Entry<K, V>,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+        pager: &mut dyn Pager,
+    ) -> Result<Option<(K, u32)>, String> {
+        // A key equal to a separator belongs to its right. The separator may
+        // be left over from a removed entry, so duplicates are only caught
+        // by the leaves.
+        let index = match self
             .entries
             .binary_search_by_key(&entry.key, |internal_node| internal_node.key.clone())
         {
-            Err(index) => {
-                let mut existing_index = index;
-                if existing_index == self.entries.len() {
-                    existing_index -= 1;
-                }
-
-                let key = entry.key.clone();
-                match self.entries[existing_index].insert(entry, degree, page_byte_size, serializer) {
-                    Err(err) => return Err(err),
-                    Ok(has_node_split_into_two) => match has_node_split_into_two {
-                        None => {}
-                        Some(split_node) => {
-                            let new_internal_node_entry = InternalNodeEntry::new(
-                                split_node.left_key(),
-                                self.entries[existing_index].side(&key),
-                                split_node,
-                            );
-                            self.insert_node_at(new_internal_node_entry, index);
-                        }
-                    },
-                }
-                if self.entries.len() >= degree {
-                    return Ok(Some(BPTreeNode::InternalNode(self.split())));
-                }
-            } 
-            Ok(_) => {
-                return Err(format!("duplicate entry: {}", entry.key));
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+        let mut existing_index = index;
+        if existing_index == self.entries.len() {
+            existing_index -= 1;
+        }
+
+        let key = entry.key.clone();
+        match self.entries[existing_index].insert(
+            entry,
+            degree,
+            page_byte_size,
+            serializer.clone(),
+            pager,
+        )? {
+            None => {}
+            Some((split_key, split_page)) => {
+                let new_internal_node_entry = InternalNodeEntry::new(
+                    split_key,
+                    self.entries[existing_index].side(&key),
+                    split_page,
+                );
+                self.insert_node_at(new_internal_node_entry, index);
             }
         }
+        if self.entries.len() >= degree || serializer.size(self) >= page_byte_size {
+            return self.split(pager).map(Some);
+        }
         Ok(None)
     }
 
-    fn split(&mut self) -> Rc<RefCell<InternalNode<K, V>>> {
+    /// Removes `key` from the subtree of the child it belongs in, then
+    /// rebalances that child with a sibling if it is left underfull.
+    pub fn remove(
+        &mut self,
+        key: &K,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+        pager: &mut dyn Pager,
+    ) -> Result<Option<V>, String> {
+        let index = self.child_index(key);
+        let page_no = self.parts().1[index];
+        let mut child = BPTreeNode::<K, V>::load(pager, page_no)?;
+        let removed = child.remove(
+            page_no,
+            key,
+            degree,
+            page_byte_size,
+            serializer.clone(),
+            pager,
+        )?;
+        if removed.is_some() && child.is_underfull(degree, page_byte_size, &serializer) {
+            let left_index = if index > 0 { index - 1 } else { index };
+            self.rebalance_children(left_index, degree, page_byte_size, serializer, pager)?;
+        }
+        Ok(removed)
+    }
+
+    /// Merges the children at `index` and `index + 1` if they fit on a page
+    /// together, or else evens them out by moving entries from one to the
+    /// other.
+    fn rebalance_children(
+        &mut self,
+        index: usize,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+        pager: &mut dyn Pager,
+    ) -> Result<(), String> {
+        let (mut keys, mut children) = self.parts();
+        let (left_page, right_page) = (children[index], children[index + 1]);
+        let (left, split) = match (
+            BPTreeNode::<K, V>::load(pager, left_page)?,
+            BPTreeNode::<K, V>::load(pager, right_page)?,
+        ) {
+            (BPTreeNode::LeafNode(mut left), BPTreeNode::LeafNode(right)) => {
+                let split = left.join(right, page_byte_size, serializer);
+                (
+                    BPTreeNode::LeafNode(left),
+                    split.map(|(key, right)| (key, BPTreeNode::LeafNode(right))),
+                )
+            }
+            (BPTreeNode::InternalNode(mut left), BPTreeNode::InternalNode(right)) => {
+                let split = left.join(
+                    keys[index].clone(),
+                    right,
+                    degree,
+                    page_byte_size,
+                    serializer,
+                );
+                (
+                    BPTreeNode::InternalNode(left),
+                    split.map(|(key, right)| (key, BPTreeNode::InternalNode(right))),
+                )
+            }
+            _ => return Err(super::malformed_page_error(right_page)),
+        };
+
+        left.store(pager, left_page)?;
+        match split {
+            None => {
+                pager.free_page(right_page)?;
+                keys.remove(index);
+                children.remove(index + 1);
+            }
+            Some((key, right)) => {
+                right.store(pager, right_page)?;
+                keys[index] = key;
+            }
+        }
+        self.set_parts(keys, children);
+        Ok(())
+    }
+
+    /// Takes over the separator and entries of the right sibling. If the
+    /// result is too large, it is split again and the new right half is
+    /// returned along with the key moving up to the parent.
+    pub fn join(
+        &mut self,
+        separator: K,
+        right: InternalNode<K, V>,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+    ) -> Option<(K, InternalNode<K, V>)> {
+        let (mut keys, mut children) = self.parts();
+        let (right_keys, right_children) = right.parts();
+        keys.push(separator);
+        keys.extend(right_keys);
+        children.extend(right_children);
+        self.set_parts(keys, children);
+
+        if self.entries.len() < degree && serializer.size(self) < page_byte_size {
+            return None;
+        }
+        Some(self.split_off_half())
+    }
+
+    /// Whether the node has fewer keys than half of what fits in it.
+    pub fn is_underfull(&self, degree: usize) -> bool {
+        self.num_keys() < cmp::max(1, (degree - 1) / 2)
+    }
+
+    pub fn num_keys(&self) -> usize {
+        self.parts().0.len()
+    }
+
+    fn split(&mut self, pager: &mut dyn Pager) -> Result<(K, u32), String> {
+        let (key, new_right) = self.split_off_half();
+        let page_no = pager.allocate_page()?;
+        BPTreeNode::InternalNode(new_right).store(pager, page_no)?;
+        Ok((key, page_no))
+    }
+
+    /// Moves the upper half of the entries to a new node. The middle key
+    /// is returned to go up into the parent.
+    fn split_off_half(&mut self) -> (K, InternalNode<K, V>) {
         let mid_index = self.entries.len() / 2;
-        let right_split = self.entries.split_off(mid_index);
-        let new_right = InternalNode::new_with_entries(right_split);
-        Rc::new(RefCell::new(new_right))
+        let mut right_split = self.entries.split_off(mid_index);
+        let key = right_split.remove(0).key;
+        (key, InternalNode::new_with_entries(right_split))
+    }
+
+    /// Index of the child whose subtree holds `key`.
+    fn child_index(&self, key: &K) -> usize {
+        self.parts().0.partition_point(|separator| separator <= key)
+    }
+
+    /// Separator keys and child pages of the node, where child `i` holds
+    /// the keys from separator `i - 1` up to separator `i`.
+    fn parts(&self) -> (Vec<K>, Vec<u32>) {
+        if self.has_single_child() {
+            return (vec![], vec![self.entries[0].left]);
+        }
+        let keys = self.entries.iter().map(|entry| entry.key.clone()).collect();
+        let children = self
+            .entries
+            .iter()
+            .take(1)
+            .map(|entry| entry.left)
+            .chain(self.entries.iter().map(|entry| entry.right))
+            .collect();
+        (keys, children)
     }
 
-    fn insert_node_at(&mut self, entry: InternalNodeEntry<K, V>, index: usize) {
-        let entry_clone = entry.clone();
+    fn set_parts(&mut self, keys: Vec<K>, children: Vec<u32>) {
+        if keys.is_empty() {
+            // Only until the parent rebalances the node, or the tree makes
+            // the child its new root
+            self.entries.truncate(1);
+            self.entries[0].left = children[0];
+            self.entries[0].right = children[0];
+            return;
+        }
+        self.entries = keys
+            .into_iter()
+            .zip(children.windows(2))
+            .map(|(key, pages)| InternalNodeEntry::new(key, pages[0], pages[1]))
+            .collect();
+    }
+
+    /// Whether the node was left without keys by a merge of its children.
+    /// It then keeps one entry pointing to the remaining child on both
+    /// sides.
+    fn has_single_child(&self) -> bool {
+        self.entries.len() == 1 && self.entries[0].left == self.entries[0].right
+    }
+
+    fn insert_node_at(&mut self, entry: InternalNodeEntry<K>, index: usize) {
+        let (left, right) = (entry.left, entry.right);
         self.entries.insert(index, entry);
 
-        match self.entries.get_mut(index - 1) {
-            None => {}
-            Some(left) => {
-                left.right = entry_clone.left.clone();
-            }
+        if index > 0 {
+            self.entries[index - 1].right = left;
         }
         match self.entries.get_mut(index + 1) {
             None => {}
-            Some(right) => {
-                right.left = entry_clone.right.clone();
+            Some(next) => {
+                next.left = right;
             }
         }
     }
 
-    pub fn keys(&self) -> Vec<K> {
+    pub fn keys(&self, pager: &mut dyn Pager) -> Result<Vec<K>, String> {
         let mut keys = vec![];
         for entry in &self.entries {
-            keys.extend(entry.keys());
+            keys.extend(entry.keys::<V>(pager)?);
         }
 
-        keys
+        Ok(keys)
     }
 }
 
 #[cfg(test)]
 mod internal_node_test {
+    use super::super::LeafNode;
     use super::*;
+    use crate::bptree::pager::in_memory;
     use pretty_assertions::assert_eq;
 
     macro_rules! new_leaf_node {
-        ($page_byte_size:expr, $($key:expr => $value:expr),*) => {{
+        ($pager:expr, $page_byte_size:expr, $($key:expr => $value:expr),*) => {{
             let mut leafnode = LeafNode::<i32, Vec<i32>>::new();
-            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, Serializer::Mock).is_err(), false);)*
+            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, Serializer::Mock, $pager).is_err(), false);)*
             leafnode
         }};
     }
 
     macro_rules! new_internal_node {
-        ($left:expr, $right:expr) => {{
-            let rc_right_node = Some(Rc::new(RefCell::new($right)));
-            $left.next = rc_right_node.clone();
-
-            InternalNode::from_leaves(
-                Rc::new(RefCell::new($left)),
-                rc_right_node.unwrap().clone(),
-            )
+        ($pager:expr, $left:expr, $right:expr) => {{
+            let left_page = $pager.allocate_page().unwrap();
+            let right_page = $pager.allocate_page().unwrap();
+            let right = $right;
+            let key = right.left_key();
+            $left.next = Some(right_page);
+            BPTreeNode::LeafNode($left)
+                .store($pager, left_page)
+                .unwrap();
+            BPTreeNode::LeafNode(right)
+                .store($pager, right_page)
+                .unwrap();
+
+            InternalNode::<i32, Vec<i32>>::from_leaves(key, left_page, right_page)
         }};
     }
 
     macro_rules! insert {
-        ($inode:expr, $page_byte_size:expr, $($key:expr => $value:expr),*) => {{
-            $(assert_eq!($inode.insert(Entry::new($key, $value), $page_byte_size, $page_byte_size, Serializer::Mock).is_err(), false);)*
+        ($inode:expr, $pager:expr, $page_byte_size:expr, $($key:expr => $value:expr),*) => {{
+            $(assert_eq!($inode.insert(Entry::new($key, $value), $page_byte_size, $page_byte_size, Serializer::Mock, $pager).is_err(), false);)*
         }};
     }
 
     #[test]
     fn has_correct_iteration_order_after_insertion() {
         let page_byte_size = 3;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
 
-        let mut left_node = new_leaf_node!(page_byte_size, 1 => vec![1, 2, 3]);
+        let mut left_node = new_leaf_node!(pager, page_byte_size, 1 => vec![1, 2, 3]);
         let internal_node = new_internal_node!(
+            pager,
             left_node,
             new_leaf_node!(
-                page_byte_size, 
-                3 => vec![400, 500, 600], 
+                pager,
+                page_byte_size,
+                3 => vec![400, 500, 600],
                 2 => vec![-1, -2, -3])
         );
+
+        let mut values = vec![];
+        let mut page_no = Some(internal_node.first_child());
+        while let Some(leaf_page) = page_no {
+            let leaf_node = LeafNode::<i32, Vec<i32>>::load(pager, leaf_page).unwrap();
+            values.extend(leaf_node.values().cloned());
+            page_no = leaf_node.next();
+        }
         assert_eq!(
-            internal_node.into_iter().collect::<Vec<Vec<i32>>>(),
+            values,
             vec![vec![1, 2, 3], vec![-1, -2, -3], vec![400, 500, 600]]
         );
     }
@@ -214,67 +394,90 @@ mod internal_node_test {
     #[test]
     fn internal_node_is_built_correctly() {
         let page_byte_size = 3;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
 
-        let mut left_node = new_leaf_node!(page_byte_size, 1 => vec![1,2,3]);
+        let mut left_node = new_leaf_node!(pager, page_byte_size, 1 => vec![1,2,3]);
         let mut internal_node = new_internal_node!(
+            pager,
             left_node,
             new_leaf_node!(
-                page_byte_size, 
-                3 => vec![400, 500, 600], 
+                pager,
+                page_byte_size,
+                3 => vec![400, 500, 600],
                 2 => vec![-1, -2, -3])
         );
-        insert!(internal_node, page_byte_size, 4 => vec![1]);
-        assert_eq!(internal_node.keys(), vec![1, 2, 2, 2, 3, 3, 4]);
+        insert!(internal_node, pager, page_byte_size, 4 => vec![1]);
+        assert_eq!(
+            internal_node.keys(pager).unwrap(),
+            vec![1, 2, 2, 2, 3, 3, 4]
+        );
     }
 
     #[test]
     fn internal_node_is_built_correctly2() {
         let page_byte_size = 4;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
 
         let mut left_leafnode = new_leaf_node!(
-            page_byte_size, 
+            pager,
+            page_byte_size,
             1 => vec![1, 2, 3],
             2 => vec![1, 2, 3],
             3 => vec![1, 2, 3]);
-        let right_leafnode = left_leafnode
-            .insert(Entry::new(4, vec![1, 2, 3]), page_byte_size, Serializer::Mock)
+        let (key, right_page) = left_leafnode
+            .insert(
+                Entry::new(4, vec![1, 2, 3]),
+                page_byte_size,
+                Serializer::Mock,
+                pager,
+            )
             .unwrap()
             .unwrap();
 
-        let rc_right_node = Some(right_leafnode);
-        left_leafnode.next = rc_right_node.clone();
+        let left_page = pager.allocate_page().unwrap();
+        BPTreeNode::LeafNode(left_leafnode)
+            .store(pager, left_page)
+            .unwrap();
 
-        let internal_node = InternalNode::from_leaves(
-            Rc::new(RefCell::new(left_leafnode)),
-            rc_right_node.unwrap().clone(),
-        );
+        let internal_node = InternalNode::<i32, Vec<i32>>::from_leaves(key, left_page, right_page);
 
-        assert_eq!(internal_node.keys(), vec![1, 2, 3, 3, 4]);
+        assert_eq!(internal_node.keys(pager).unwrap(), vec![1, 2, 3, 3, 4]);
     }
 
     #[test]
     fn internal_node_is_built_correctly3() {
         let page_byte_size = 4;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
 
         let mut left_leafnode = new_leaf_node!(
-            page_byte_size, 
+            pager,
+            page_byte_size,
             1 => vec![1, 2, 3],
             2 => vec![1, 2, 3],
             3 => vec![1, 2, 3]);
-        let right_leafnode = left_leafnode
-            .insert(Entry::new(4, vec![1, 2, 3]), page_byte_size, Serializer::Mock)
+        let (key, right_page) = left_leafnode
+            .insert(
+                Entry::new(4, vec![1, 2, 3]),
+                page_byte_size,
+                Serializer::Mock,
+                pager,
+            )
             .unwrap()
             .unwrap();
 
-        let rc_right_node = Some(right_leafnode);
-        left_leafnode.next = rc_right_node.clone();
+        let left_page = pager.allocate_page().unwrap();
+        BPTreeNode::LeafNode(left_leafnode)
+            .store(pager, left_page)
+            .unwrap();
 
-        let mut internal_node = InternalNode::from_leaves(
-            Rc::new(RefCell::new(left_leafnode)),
-            rc_right_node.unwrap().clone(),
-        );
+        let mut internal_node =
+            InternalNode::<i32, Vec<i32>>::from_leaves(key, left_page, right_page);
 
         insert!(internal_node,
+            pager,
             page_byte_size,
             10 => vec![1],
             11 => vec![1],
@@ -283,7 +486,7 @@ mod internal_node_test {
             20 => vec![1]
         );
         assert_eq!(
-            internal_node.keys(),
+            internal_node.keys(pager).unwrap(),
             vec![1, 2, 3, 3, 4, 3, 4, 5, 5, 6, 5, 6, 10, 10, 11, 20]
         );
     }
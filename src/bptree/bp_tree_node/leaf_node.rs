@@ -1,20 +1,12 @@
 use super::Entry;
-use super::LeafNode;
-use super::{Key, Value};
-use std::cell::RefCell;
+use super::{BPTreeNode, LeafNode};
+use super::{Key, Pager, Value};
+use std::cmp;
 use std::fmt;
 use std::fmt::Display;
-use std::rc::Rc;
 
 use super::super::Serializer;
 
-macro_rules! rcref {
-    ($expr:expr) => {{
-        Rc::new(RefCell::new($expr))
-    }};
-}
-
-
 impl<K: Key + 'static, V: Value + 'static> Display for LeafNode<K, V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -28,34 +20,11 @@ impl<K: Key + 'static, V: Value + 'static> Display for LeafNode<K, V> {
     }
 }
 
-impl<K: Key + 'static, V: Value + 'static> IntoIterator for LeafNode<K, V> {
-    type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
-    fn into_iter(self) -> Self::IntoIter {
-        let mut all_entries = self
-            .entries
-            .iter()
-            .map(|entry| entry.value.clone())
-            .collect::<Vec<V>>();
-        match &self.next {
-            None => {}
-            Some(next_entries) => {
-                all_entries.extend(next_entries.borrow().clone().into_iter());
-            }
-        }
-        all_entries.into_iter()
-    }
-}
-
 impl<K: Key + 'static, V: Value + 'static> LeafNode<K, V> {
     pub fn new() -> LeafNode<K, V> {
         LeafNode::new_with_entries(vec![])
     }
 
-    pub fn new_from_entry(entry: Entry<K, V>) -> LeafNode<K, V> {
-        LeafNode::new_with_entries(vec![entry])
-    }
-
     fn new_with_entries(entries: Vec<Entry<K, V>>) -> LeafNode<K, V> {
         LeafNode {
             entries,
@@ -63,17 +32,47 @@ impl<K: Key + 'static, V: Value + 'static> LeafNode<K, V> {
         }
     }
 
+    pub fn load(pager: &mut dyn Pager, page_no: u32) -> Result<LeafNode<K, V>, String> {
+        match BPTreeNode::load(pager, page_no)? {
+            BPTreeNode::LeafNode(leaf_node) => Ok(leaf_node),
+            BPTreeNode::InternalNode(_) => Err(super::malformed_page_error(page_no)),
+        }
+    }
+
+    /// Page of the leaf holding the next larger keys.
+    pub fn next(&self) -> Option<u32> {
+        self.next
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|entry| &entry.value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries
+            .binary_search_by(|entry| entry.key.cmp(key))
+            .ok()
+            .map(|index| &self.entries[index].value)
+    }
+
+    pub fn into_entries(self) -> Vec<Entry<K, V>> {
+        self.entries
+    }
+
+    /// Inserts the entry, moving the upper half of the entries to a new
+    /// right sibling once the leaf no longer fits in `page_byte_size`.
     pub fn insert(
         &mut self,
         entry: Entry<K, V>,
         page_byte_size: usize,
         serializer: Serializer,
-    ) -> Result<Option<Rc<RefCell<LeafNode<K, V>>>>, String> {
+        pager: &mut dyn Pager,
+    ) -> Result<Option<(K, u32)>, String> {
         match self.entries.binary_search(&entry) {
             Err(index) => {
                 self.entries.insert(index, entry);
-                if serializer.serialize(&self.entries).len() >= page_byte_size {
-                    return Ok(Some(self.split()));
+                if serializer.size(self) >= page_byte_size {
+                    return self.split(serializer, pager).map(Some);
                 }
             }
             Ok(_) => {
@@ -83,13 +82,79 @@ impl<K: Key + 'static, V: Value + 'static> LeafNode<K, V> {
         Ok(None)
     }
 
-    fn split(&mut self) -> Rc<RefCell<LeafNode<K, V>>> {
-        let mid_index = self.entries.len() / 2;
-        let right_split = self.entries.split_off(mid_index);
+    /// Removes the entry with `key`, returning its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries
+            .binary_search_by(|entry| entry.key.cmp(key))
+            .ok()
+            .map(|index| self.entries.remove(index).value)
+    }
+
+    /// Replaces the value of the entry with `key`, returning the previous one.
+    pub fn replace(&mut self, key: &K, value: V) -> Option<V> {
+        self.entries
+            .binary_search_by(|entry| entry.key.cmp(key))
+            .ok()
+            .map(|index| std::mem::replace(&mut self.entries[index].value, value))
+    }
+
+    /// Takes over the entries of the right sibling. If they no longer fit
+    /// in `page_byte_size`, they are split again into two halves of similar
+    /// size and the new right half is returned along with its first key.
+    pub fn join(
+        &mut self,
+        right: LeafNode<K, V>,
+        page_byte_size: usize,
+        serializer: Serializer,
+    ) -> Option<(K, LeafNode<K, V>)> {
+        let next = right.next;
+        self.entries.extend(right.entries);
+        if serializer.size(self) < page_byte_size {
+            self.next = next;
+            return None;
+        }
+        let right_split = self.entries.split_off(self.split_index(serializer));
         let mut new_right = LeafNode::new_with_entries(right_split);
-        new_right.next = self.next.clone();
-        self.next = Some(rcref!(new_right));
-        self.next.clone().unwrap()
+        new_right.next = next;
+        Some((new_right.left_key(), new_right))
+    }
+
+    /// Whether the leaf takes up less than half of `page_byte_size`.
+    pub fn is_underfull(&self, page_byte_size: usize, serializer: &Serializer) -> bool {
+        serializer.size(self) < page_byte_size / 2
+    }
+
+    fn split(&mut self, serializer: Serializer, pager: &mut dyn Pager) -> Result<(K, u32), String> {
+        let right_split = self.entries.split_off(self.split_index(serializer));
+        let mut new_right = LeafNode::new_with_entries(right_split);
+        new_right.next = self.next;
+
+        let page_no = pager.allocate_page()?;
+        let key = new_right.left_key();
+        BPTreeNode::LeafNode(new_right).store(pager, page_no)?;
+        self.next = Some(page_no);
+        Ok((key, page_no))
+    }
+
+    /// Index dividing the entries into the two halves closest in size, so
+    /// that both fit on a page even when entry sizes vary.
+    fn split_index(&self, serializer: Serializer) -> usize {
+        let sizes = self
+            .entries
+            .iter()
+            .map(|entry| serializer.size(entry))
+            .collect::<Vec<usize>>();
+        let total_size: usize = sizes.iter().sum();
+        let mut left_size = 0;
+        let mut best = (usize::MAX, 1);
+        for (i, size) in sizes[..sizes.len() - 1].iter().enumerate() {
+            left_size += size;
+            let larger_half = cmp::max(left_size, total_size - left_size);
+            if larger_half < best.0 {
+                best = (larger_half, i + 1);
+            }
+        }
+        best.1
     }
 
     pub fn left_key(&self) -> K {
@@ -98,15 +163,6 @@ impl<K: Key + 'static, V: Value + 'static> LeafNode<K, V> {
         return entries[0].key.clone();
     }
 
-    pub fn right_key(&self) -> K {
-        let entries = &self.entries;
-        debug_assert!(
-            entries.len() > 0,
-            "internal node should have at least 1 entry"
-        );
-        return entries[entries.len() - 1].key.clone();
-    }
-
     pub fn keys(&self) -> Vec<K> {
         self.entries
             .iter()
@@ -118,12 +174,13 @@ impl<K: Key + 'static, V: Value + 'static> LeafNode<K, V> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bptree::pager::in_memory;
     use pretty_assertions::assert_eq;
 
     macro_rules! new_leaf_node {
-        ($page_byte_size:expr, $($key:expr => $value:expr),*) => {{
+        ($pager:expr, $page_byte_size:expr, $($key:expr => $value:expr),*) => {{
             let mut leafnode = LeafNode::<i32, Vec<i32>>::new();
-            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, Serializer::Mock).is_err(), false);)*
+            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, Serializer::Mock, $pager).is_err(), false);)*
             leafnode
         }};
     }
@@ -131,15 +188,23 @@ mod tests {
     #[test]
     fn has_correct_iteration_order_after_insertion() {
         let page_byte_size = 3;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
 
         let leafnode = new_leaf_node!(
-            page_byte_size, 
-            1 => vec![1,2,3], 
-            3 => vec![400, 500, 600], 
+            pager,
+            page_byte_size,
+            1 => vec![1,2,3],
+            3 => vec![400, 500, 600],
             2 => vec![-1, -2, -3]);
+        let next = LeafNode::<i32, Vec<i32>>::load(pager, leafnode.next().unwrap()).unwrap();
 
         assert_eq!(
-            leafnode.into_iter().collect::<Vec<Vec<i32>>>(),
+            leafnode
+                .values()
+                .chain(next.values())
+                .cloned()
+                .collect::<Vec<Vec<i32>>>(),
             vec![vec![1, 2, 3], vec![-1, -2, -3], vec![400, 500, 600]]
         );
     }
@@ -147,16 +212,21 @@ mod tests {
     #[test]
     fn nodes_are_split_when_page_byte_size_is_reached() {
         let page_byte_size = 3;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
 
         let leafnode = new_leaf_node!(
-            page_byte_size, 
-            1 => vec![1,2,3], 
-            3 => vec![400, 500, 600], 
+            pager,
+            page_byte_size,
+            1 => vec![1,2,3],
+            3 => vec![400, 500, 600],
             2 => vec![-1, -2, -3]);
         assert_eq!(leafnode.entries, vec![Entry::new(1, vec![1, 2, 3]),]);
         assert_ne!(leafnode.next, None);
         assert_eq!(
-            leafnode.next.unwrap().borrow().entries,
+            LeafNode::load(pager, leafnode.next.unwrap())
+                .unwrap()
+                .entries,
             vec![
                 Entry::new(2, vec![-1, -2, -3]),
                 Entry::new(3, vec![400, 500, 600]),
@@ -167,8 +237,11 @@ mod tests {
     #[test]
     fn nodes_are_split_when_page_byte_size_is_reached2() {
         let page_byte_size = 4;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
 
         let leafnode = new_leaf_node!(
+            pager,
             page_byte_size,
             1 => vec![1,2,3],
             3 => vec![400, 500, 600],
@@ -184,7 +257,9 @@ mod tests {
         );
         assert_ne!(leafnode.next, None);
         assert_eq!(
-            leafnode.next.unwrap().borrow().entries,
+            LeafNode::load(pager, leafnode.next.unwrap())
+                .unwrap()
+                .entries,
             vec![
                 Entry::new(3, vec![400, 500, 600]),
                 Entry::new(4, vec![-1, -2, -3]),
@@ -192,15 +267,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn nodes_are_split_into_halves_of_similar_size() {
+        let page_byte_size = 38;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
+
+        let mut leafnode = LeafNode::<i32, Vec<i32>>::new();
+        for entry in vec![
+            Entry::new(1, vec![1; 20]),
+            Entry::new(2, vec![1]),
+            Entry::new(3, vec![1]),
+            Entry::new(4, vec![1]),
+        ] {
+            leafnode
+                .insert(entry, page_byte_size, Serializer::RMP, pager)
+                .unwrap();
+        }
+        assert_eq!(leafnode.keys(), vec![1]);
+        let next = LeafNode::<i32, Vec<i32>>::load(pager, leafnode.next.unwrap()).unwrap();
+        assert_eq!(next.keys(), vec![2, 3, 4]);
+    }
+
     #[test]
     fn duplicate_insertion_fails() {
         let page_byte_size = 3;
+        let pager = in_memory();
+        let pager = &mut *pager.borrow_mut();
         let mut leafnode = new_leaf_node!(
-            page_byte_size,  
-            1 => vec![1,2,3], 
+            pager,
+            page_byte_size,
+            1 => vec![1,2,3],
             3 => vec![400, 500, 600]);
         assert_eq!(
-            leafnode.insert(Entry::new(3, vec![-1, -2, -3]), page_byte_size, Serializer::Mock).is_err(),
+            leafnode
+                .insert(
+                    Entry::new(3, vec![-1, -2, -3]),
+                    page_byte_size,
+                    Serializer::Mock,
+                    pager
+                )
+                .is_err(),
             true
         );
     }
@@ -1,106 +1,172 @@
+use super::serializer::Measurable;
 use super::Entry;
-use super::{Key, Value};
-use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use super::{Key, Pager, Value};
 use std::cmp::Eq;
 use std::fmt;
-use std::fmt::{Debug, Display};
-
-use std::rc::Rc;
+use std::fmt::Display;
+use std::marker::PhantomData;
 
 mod internal_node;
 mod leaf_node;
 
-#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
-pub enum BPTreeNode<K: Key, V: Value> {
-    LeafNode(Rc<RefCell<LeafNode<K, V>>>),
-    InternalNode(Rc<RefCell<InternalNode<K, V>>>),
-}
+/// First byte of every page holding a node, telling the two kinds apart.
+const LEAF_PAGE: u8 = 0x0d;
+const INTERNAL_PAGE: u8 = 0x05;
 
-impl<K: Key + 'static, V: Value + 'static> IntoIterator for BPTreeNode<K, V> {
-    type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
-    fn into_iter(self) -> Self::IntoIter {
-        match &self {
-            BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().clone().into_iter(),
-            BPTreeNode::InternalNode(internal_node) => internal_node.borrow().clone().into_iter(),
-        }
-    }
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BPTreeNode<K: Key, V: Value> {
+    LeafNode(LeafNode<K, V>),
+    InternalNode(InternalNode<K, V>),
 }
 
 impl<K: Key + 'static, V: Value + 'static> Display for BPTreeNode<K, V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            BPTreeNode::LeafNode(leaf_node) => write!(f, "{}", leaf_node.borrow()),
-            BPTreeNode::InternalNode(internal_node) => write!(f, "{}", internal_node.borrow()),
+            BPTreeNode::LeafNode(leaf_node) => write!(f, "{}", leaf_node),
+            BPTreeNode::InternalNode(internal_node) => write!(f, "{}", internal_node),
         }
     }
 }
 
 impl<K: Key + 'static, V: Value + 'static> BPTreeNode<K, V> {
+    pub fn load(pager: &mut dyn Pager, page_no: u32) -> Result<BPTreeNode<K, V>, String> {
+        let page = pager.read_page(page_no)?;
+        let node = match page[0] {
+            LEAF_PAGE => rmp_serde::from_slice(&page[1..]).map(BPTreeNode::LeafNode),
+            INTERNAL_PAGE => rmp_serde::from_slice(&page[1..]).map(BPTreeNode::InternalNode),
+            _ => return Err(malformed_page_error(page_no)),
+        };
+        node.map_err(|_| malformed_page_error(page_no))
+    }
+
+    pub fn store(&self, pager: &mut dyn Pager, page_no: u32) -> Result<(), String> {
+        let (page_type, body) = match &self {
+            BPTreeNode::LeafNode(leaf_node) => (LEAF_PAGE, rmp_serde::to_vec(leaf_node)),
+            BPTreeNode::InternalNode(internal_node) => {
+                (INTERNAL_PAGE, rmp_serde::to_vec(internal_node))
+            }
+        };
+        let mut page = vec![page_type];
+        page.extend(body.map_err(|err| err.to_string())?);
+        pager.write_page(page_no, page)
+    }
+
+    /// Inserts the entry below this node, which lives on `page_no`.
+    ///
+    /// If the node had to split, returns the first key and the page of the
+    /// new right sibling.
     pub fn insert(
         &mut self,
+        page_no: u32,
         entry: Entry<K, V>,
         degree: usize,
         page_byte_size: usize,
         serializer: super::Serializer,
-    ) -> Result<Option<BPTreeNode<K, V>>, String> {
-        match &self {
-            BPTreeNode::LeafNode(leaf_node) => leaf_node
-                .borrow_mut()
-                .insert(entry, page_byte_size, serializer)
-                .map(|opt| opt.map(|rc| BPTreeNode::LeafNode(rc))),
+        pager: &mut dyn Pager,
+    ) -> Result<Option<(K, u32)>, String> {
+        let split = match self {
+            BPTreeNode::LeafNode(leaf_node) => {
+                leaf_node.insert(entry, page_byte_size, serializer, pager)?
+            }
             BPTreeNode::InternalNode(internal_node) => {
-                internal_node
-                    .borrow_mut()
-                    .insert(entry, degree, page_byte_size, serializer)
+                internal_node.insert(entry, degree, page_byte_size, serializer, pager)?
             }
-        }
+        };
+        self.store(pager, page_no)?;
+        Ok(split)
     }
 
-    fn left_key(&self) -> K {
-        match &self {
-            BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().left_key(),
-            BPTreeNode::InternalNode(internal_node) => internal_node.borrow().left_key(),
+    /// Removes the entry with `key` from below this node, which lives on
+    /// `page_no`, and returns its value.
+    ///
+    /// The node may be left underfull, which is for the parent to fix.
+    pub fn remove(
+        &mut self,
+        page_no: u32,
+        key: &K,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: super::Serializer,
+        pager: &mut dyn Pager,
+    ) -> Result<Option<V>, String> {
+        let removed = match self {
+            BPTreeNode::LeafNode(leaf_node) => leaf_node.remove(key),
+            BPTreeNode::InternalNode(internal_node) => {
+                internal_node.remove(key, degree, page_byte_size, serializer, pager)?
+            }
+        };
+        if removed.is_some() {
+            self.store(pager, page_no)?;
         }
+        Ok(removed)
     }
 
-    fn right_key(&self) -> K {
-        match &self {
-            BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().right_key(),
-            BPTreeNode::InternalNode(internal_node) => internal_node.borrow().right_key(),
+    /// Frees the page of the node, which is on `page_no`, and the pages of
+    /// its subtree.
+    pub fn free(&self, page_no: u32, pager: &mut dyn Pager) -> Result<(), String> {
+        if let BPTreeNode::InternalNode(internal_node) = self {
+            for child in internal_node.children() {
+                BPTreeNode::<K, V>::load(pager, child)?.free(child, pager)?;
+            }
         }
+        pager.free_page(page_no)
     }
 
-    fn len(&self) -> usize {
+    pub fn is_underfull(
+        &self,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: &super::Serializer,
+    ) -> bool {
         match &self {
-            BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().entries.len(),
-            BPTreeNode::InternalNode(internal_node) => internal_node.borrow().entries.len(),
+            BPTreeNode::LeafNode(leaf_node) => leaf_node.is_underfull(page_byte_size, serializer),
+            BPTreeNode::InternalNode(internal_node) => internal_node.is_underfull(degree),
         }
     }
 
-    pub fn keys(&self) -> Vec<K> {
+    pub fn keys(&self, pager: &mut dyn Pager) -> Result<Vec<K>, String> {
         match &self {
-            BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().keys(),
-            BPTreeNode::InternalNode(internal_node) => internal_node.borrow().keys(),
+            BPTreeNode::LeafNode(leaf_node) => Ok(leaf_node.keys()),
+            BPTreeNode::InternalNode(internal_node) => internal_node.keys(pager),
         }
     }
 }
 
+fn malformed_page_error(page_no: u32) -> String {
+    format!("database disk image is malformed (page {})", page_no)
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(bound(deserialize = ""))]
 pub struct LeafNode<K: Key, V: Value> {
     entries: Vec<Entry<K, V>>,
-    next: Option<Rc<RefCell<LeafNode<K, V>>>>,
+    next: Option<u32>,
+}
+
+impl<K: Key, V: Value> Measurable for LeafNode<K, V> {
+    fn num_entries(&self) -> usize {
+        self.entries.len()
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Eq, PartialEq)]
-struct InternalNodeEntry<K: Key, V: Value> {
+#[serde(bound(deserialize = ""))]
+struct InternalNodeEntry<K: Key> {
     key: K,
-    left: BPTreeNode<K, V>,
-    right: BPTreeNode<K, V>,
+    left: u32,
+    right: u32,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(bound(deserialize = ""))]
 pub struct InternalNode<K: Key, V: Value> {
-    entries: Vec<InternalNodeEntry<K, V>>,
+    entries: Vec<InternalNodeEntry<K>>,
+    #[serde(skip)]
+    values: PhantomData<V>,
+}
+
+impl<K: Key, V: Value> Measurable for InternalNode<K, V> {
+    fn num_entries(&self) -> usize {
+        self.entries.len()
+    }
 }
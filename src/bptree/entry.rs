@@ -1,9 +1,11 @@
+use super::serializer::Measurable;
 use super::{Key, Value};
 use serde;
 use std::cmp::{Eq, Ord, Ordering};
 use std::fmt::Debug;
 
 #[derive(serde::Serialize, serde::Deserialize, Eq, Debug, Clone, PartialEq)]
+#[serde(bound(deserialize = ""))]
 pub struct Entry<K: Key, V: Value> {
     pub key: K,
     pub value: V,
@@ -21,6 +23,12 @@ impl<K: Key, V: Value> Ord for Entry<K, V> {
     }
 }
 
+impl<K: Key, V: Value> Measurable for Entry<K, V> {
+    fn num_entries(&self) -> usize {
+        1
+    }
+}
+
 impl<K: Key, V: Value> Entry<K, V> {
     pub fn new(key: K, value: V) -> Entry<K, V> {
         Entry { key, value }
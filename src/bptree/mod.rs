@@ -1,3 +1,4 @@
+use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use std::cmp::Ord;
 use std::fmt::{Debug, Display};
@@ -6,10 +7,21 @@ use std::hash::Hash;
 mod bp_tree;
 mod bp_tree_node;
 mod entry;
+mod pager;
+mod range;
 mod serializer;
 
-pub trait Key = Hash + Serialize + Eq + Ord + Display + Debug + Clone + Display;
-pub trait Value = Serialize + Eq + Debug + Clone;
+pub trait Key = Hash + Serialize + DeserializeOwned + Eq + Ord + Display + Debug + Clone + Display;
+pub trait Value = Serialize + DeserializeOwned + Eq + Debug + Clone;
+
+/// Fixed-size pages that the nodes of a tree are stored on.
+pub trait Pager {
+    fn page_size(&self) -> usize;
+    fn read_page(&mut self, page_no: u32) -> Result<Vec<u8>, String>;
+    fn write_page(&mut self, page_no: u32, bytes: Vec<u8>) -> Result<(), String>;
+    fn allocate_page(&mut self) -> Result<u32, String>;
+    fn free_page(&mut self, page_no: u32) -> Result<(), String>;
+}
 
 pub use bp_tree::BPTree;
 pub use entry::Entry;
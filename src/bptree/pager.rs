@@ -0,0 +1,40 @@
+use super::Pager;
+use crate::pager;
+use std::io::{Read, Seek, Write};
+
+#[cfg(test)]
+use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+impl<F: Write + Read + Seek> Pager for pager::Pager<F> {
+    fn page_size(&self) -> usize {
+        self.page_size()
+    }
+
+    fn read_page(&mut self, page_no: u32) -> Result<Vec<u8>, String> {
+        self.read_page(page_no)
+    }
+
+    fn write_page(&mut self, page_no: u32, bytes: Vec<u8>) -> Result<(), String> {
+        self.write_page(page_no, bytes)
+    }
+
+    fn allocate_page(&mut self) -> Result<u32, String> {
+        self.allocate_page()
+    }
+
+    fn free_page(&mut self, page_no: u32) -> Result<(), String> {
+        self.free_page(page_no)
+    }
+}
+
+/// Pager over an in-memory file for tests that need somewhere to put nodes.
+#[cfg(test)]
+pub fn in_memory() -> Rc<RefCell<pager::Pager<Cursor<Vec<u8>>>>> {
+    let pager = pager::Pager::new(
+        Cursor::new(vec![]),
+        Cursor::new(vec![]),
+        lru::LruCache::new(16),
+        4096,
+    );
+    Rc::new(RefCell::new(pager.unwrap()))
+}
@@ -0,0 +1,75 @@
+use super::bp_tree_node::LeafNode;
+use super::{Entry, Key, Pager, Value};
+use std::cell::RefCell;
+use std::ops::Bound;
+use std::rc::Rc;
+
+/// Iterator over the entries of a tree in key order, loading one leaf at a
+/// time by following the `next` pages of the leaves. A leaf that cannot be
+/// read ends the iteration with the error.
+pub struct Range<K: Key, V: Value> {
+    pager: Rc<RefCell<dyn Pager>>,
+    entries: std::vec::IntoIter<Entry<K, V>>,
+    next: Option<u32>,
+    end: Bound<K>,
+}
+
+impl<K: Key + 'static, V: Value + 'static> Range<K, V> {
+    /// Starts at the first entry of `leaf_node` that is not below `start`.
+    pub fn new(
+        pager: Rc<RefCell<dyn Pager>>,
+        leaf_node: LeafNode<K, V>,
+        start: Bound<&K>,
+        end: Bound<K>,
+    ) -> Range<K, V> {
+        let next = leaf_node.next();
+        let mut entries = leaf_node.into_entries();
+        let skipped = entries
+            .iter()
+            .take_while(|entry| match start {
+                Bound::Included(start) => &entry.key < start,
+                Bound::Excluded(start) => &entry.key <= start,
+                Bound::Unbounded => false,
+            })
+            .count();
+        entries.drain(..skipped);
+        Range {
+            pager,
+            entries: entries.into_iter(),
+            next,
+            end,
+        }
+    }
+
+    fn is_past_end(&self, key: &K) -> bool {
+        match &self.end {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+impl<K: Key + 'static, V: Value + 'static> Iterator for Range<K, V> {
+    type Item = Result<(K, V), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                if self.is_past_end(&entry.key) {
+                    self.entries = vec![].into_iter();
+                    self.next = None;
+                    return None;
+                }
+                return Some(Ok((entry.key, entry.value)));
+            }
+            let page_no = self.next.take()?;
+            let leaf_node = match LeafNode::<K, V>::load(&mut *self.pager.borrow_mut(), page_no) {
+                Ok(leaf_node) => leaf_node,
+                Err(err) => return Some(Err(err)),
+            };
+            self.next = leaf_node.next();
+            self.entries = leaf_node.into_entries().into_iter();
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use super::{Entry, Key, Value};
 use rmp_serde;
+use serde::ser::Serialize;
 
 #[derive(Clone)]
 pub enum Serializer {
@@ -7,25 +7,20 @@ pub enum Serializer {
     Mock,
 }
 
+/// Anything that takes up space on a page.
+pub trait Measurable: Serialize {
+    fn num_entries(&self) -> usize;
+}
+
 impl Serializer {
-    pub fn serialize<K: Key, V: Value>(&self, entries: &Vec<Entry<K, V>>) -> Vec<u8> {
+    /// Number of bytes the value occupies once it is written to a page.
+    ///
+    /// The mock serializer counts one byte per entry so that tests can
+    /// decide exactly when nodes split.
+    pub fn size<M: Measurable>(&self, value: &M) -> usize {
         match self {
-            Serializer::Mock => {
-                let mut buf = Vec::new();
-                for _ in entries {
-                    buf.push(1 as u8);
-                }
-
-                buf
-            }
-            Serializer::RMP => {
-                let mut buf = Vec::new();
-                entries
-                    .serialize(&mut rmp_serde::Serializer::new(&mut buf))
-                    .unwrap();
-
-                buf
-            }
+            Serializer::Mock => value.num_entries(),
+            Serializer::RMP => rmp_serde::to_vec(value).unwrap().len(),
         }
     }
 }
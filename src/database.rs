@@ -0,0 +1,148 @@
+//! Database stored in a file or kept in memory, opened with the tables
+//! listed in its catalog.
+
+use crate::ast::{Tuple, Value};
+use crate::executor::Executor;
+use crate::{bptree, pager, table};
+use std::cell::RefCell;
+use std::fs::OpenOptions;
+use std::io::{Cursor, Read, Seek, Write};
+use std::rc::Rc;
+
+/// Name opening a new database kept in memory instead of a file
+pub const MEMORY: &str = ":memory:";
+
+/// Page size of new databases, while existing ones keep their own
+const PAGE_SIZE: u16 = 4096;
+const PAGE_CACHE_SIZE: usize = 256;
+const BPTREE_DEGREE: usize = 4;
+
+/// File or buffer holding the pages of a database or its transaction log.
+pub trait Storage: Read + Write + Seek {}
+
+impl<S: Read + Write + Seek> Storage for S {}
+
+pub type Pager = Rc<RefCell<pager::Pager<Box<dyn Storage>>>>;
+pub type Tree = bptree::BPTree<Tuple, Vec<Value>>;
+pub type Table = table::Table<Tree>;
+
+pub struct Database {
+    pub pager: Pager,
+    pub executor: Executor<Table, Pager>,
+}
+
+impl Database {
+    /// Opens the database stored in the file at `path`, creating it if
+    /// there is none, with its transaction log in a file next to it.
+    /// `MEMORY` opens a new database that is gone once closed.
+    pub fn open(path: &str) -> Result<Database, String> {
+        let (transaction_log, file): (Box<dyn Storage>, Box<dyn Storage>) = match path {
+            MEMORY => (Box::new(Cursor::new(vec![])), Box::new(Cursor::new(vec![]))),
+            _ => {
+                let open = |path: &str| {
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(path)
+                        .map_err(|err| format!("unable to open database \"{}\": {}", path, err))
+                };
+                let file = open(path)?;
+                (Box::new(open(&format!("{}-log", path))?), Box::new(file))
+            }
+        };
+        let pager = pager::Pager::new(
+            transaction_log,
+            file,
+            lru::LruCache::new(PAGE_CACHE_SIZE),
+            PAGE_SIZE,
+        )?;
+        Database::with_pager(Rc::new(RefCell::new(pager)))
+    }
+
+    /// Opens the database stored by the pager, whose catalog is created on
+    /// the first page if it is new.
+    pub fn with_pager(pager: Pager) -> Result<Database, String> {
+        let is_new = pager.borrow().num_pages() <= table::catalog::ROOT_PAGE;
+        let rows = match is_new {
+            // The catalog of a new database is committed right away, so that
+            // it stays on the first page whatever the first statement does
+            true => {
+                let rows = new_tree(&pager)?;
+                pager.borrow_mut().commit()?;
+                rows
+            }
+            false => bptree::BPTree::open(
+                table::catalog::ROOT_PAGE,
+                BPTREE_DEGREE,
+                pager.borrow().page_size(),
+                bptree::Serializer::RMP,
+                pager.clone(),
+            ),
+        };
+        let (catalog, tables) = table::catalog::open(rows)?;
+        let mut executor = Executor::new(pager.clone());
+        executor.open(catalog, tables);
+        Ok(Database { pager, executor })
+    }
+
+    /// Copies the committed changes from the transaction log into the
+    /// database file, which has to be done before the database is closed
+    /// for another one to open the file.
+    pub fn checkpoint(&self) -> Result<(), String> {
+        self.pager.borrow_mut().checkpoint()
+    }
+}
+
+/// Creates an empty tree for the rows of a new table.
+pub fn new_tree(pager: &Pager) -> Result<Tree, String> {
+    // An existing database keeps the page size it was created with
+    let page_byte_size = pager.borrow().page_size();
+    bptree::BPTree::new(
+        BPTREE_DEGREE,
+        page_byte_size,
+        bptree::Serializer::RMP,
+        pager.clone(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::testing::run;
+    use std::{env, fs, process};
+
+    #[test]
+    fn databases_stored_in_a_file_are_opened_with_their_tables() {
+        let path = env::temp_dir().join(format!("rsqlite3-database-{}.db", process::id()));
+        let path = path.to_str().unwrap();
+        let mut database = Database::open(path).unwrap();
+        let run = |database: &mut Database, statement| {
+            run(&mut database.executor, &database.pager, statement)
+        };
+        run(
+            &mut database,
+            "CREATE TABLE pets(id INTEGER PRIMARY KEY, name TEXT);",
+        )
+        .unwrap();
+        run(&mut database, "INSERT INTO pets VALUES (1, 'rex');").unwrap();
+        database.checkpoint().unwrap();
+        drop(database);
+
+        let mut database = Database::open(path).unwrap();
+        run(&mut database, "INSERT INTO pets VALUES (2, 'tom');").unwrap();
+        // Committed changes are found in the transaction log until checkpointed
+        let mut database = Database::open(path).unwrap();
+        let rows = run(&mut database, "SELECT name FROM pets;");
+        fs::remove_file(path).unwrap();
+        fs::remove_file(format!("{}-log", path)).unwrap();
+        assert_eq!(
+            rows,
+            Ok(vec![
+                vec![Value::Text("rex".to_string())],
+                vec![Value::Text("tom".to_string())],
+            ])
+        );
+    }
+}
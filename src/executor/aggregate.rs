@@ -0,0 +1,301 @@
+use super::expression;
+use crate::ast::{Expression, Value};
+use crate::table::Affinity;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// Aggregate function called by a query along with the expressions of its
+/// arguments
+pub type Call = (String, Vec<Expression>);
+
+/// Whether `name` is one of the aggregate functions.
+pub fn is_aggregate(name: &str) -> bool {
+    ["count", "sum", "avg", "min", "max", "group_concat"].contains(&name)
+}
+
+/// Name of the column holding the results of the call at `index`, which no
+/// column of a table can have.
+pub fn result_column(index: usize) -> String {
+    format!("#{}", index)
+}
+
+/// Replaces the aggregate calls in the expression by the columns holding
+/// their results, adding the calls to `calls` unless they are there already.
+pub fn extract_calls(expression: &Expression, calls: &mut Vec<Call>) -> Expression {
+    match expression {
+        Expression::Function(name, arguments) if is_aggregate(name) => {
+            let call = (name.clone(), arguments.clone());
+            let index = match calls.iter().position(|other| *other == call) {
+                Some(index) => index,
+                None => {
+                    calls.push(call);
+                    calls.len() - 1
+                }
+            };
+            Expression::Column(result_column(index))
+        }
+        Expression::Function(name, arguments) => Expression::Function(
+            name.clone(),
+            arguments
+                .iter()
+                .map(|argument| extract_calls(argument, calls))
+                .collect(),
+        ),
+        Expression::Unary(operator, operand) => {
+            Expression::unary(*operator, extract_calls(operand, calls))
+        }
+        Expression::Binary(left, operator, right) => Expression::binary(
+            extract_calls(left, calls),
+            *operator,
+            extract_calls(right, calls),
+        ),
+        Expression::IsNull(operand, negated) => {
+            Expression::is_null(extract_calls(operand, calls), *negated)
+        }
+        expression => expression.clone(),
+    }
+}
+
+/// Groups the rows by the values of the `group_by` expressions, in the
+/// order of these values. NULLs are grouped together like SQLite does. Each
+/// group comes out as its last row followed by the results of the calls over
+/// its rows. Without GROUP BY all rows make up one group, which is there
+/// even if there are none, as a row of `row_len` NULLs. Fails with the first
+/// error among the rows.
+pub fn group(
+    rows: impl Iterator<Item = Result<Vec<Value>, String>>,
+    row_len: usize,
+    group_by: &[Expression],
+    calls: &[Call],
+    columns: &HashMap<String, usize>,
+    affinities: &[Affinity],
+) -> Result<Vec<Vec<Value>>, String> {
+    let accumulators = || {
+        calls
+            .iter()
+            .map(|(name, arguments)| Accumulator::new(name, arguments.len()))
+            .collect::<Result<Vec<_>, String>>()
+    };
+    let mut groups = HashMap::new();
+    if group_by.is_empty() {
+        groups.insert(vec![], (vec![Value::Null; row_len], accumulators()?));
+    }
+    for row in rows {
+        let row = row?;
+        let key = group_by
+            .iter()
+            .map(|expression| expression::evaluate(expression, columns, affinities, &row))
+            .collect::<Vec<_>>();
+        let (last_row, accumulators) = match groups.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert((vec![], accumulators()?)),
+        };
+        for (accumulator, (_, arguments)) in accumulators.iter_mut().zip(calls) {
+            let arguments = arguments
+                .iter()
+                .map(|argument| expression::evaluate(argument, columns, affinities, &row))
+                .collect::<Vec<_>>();
+            accumulator.step(&arguments)?;
+        }
+        *last_row = row;
+    }
+
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(groups
+        .into_iter()
+        .map(|(_, (mut row, accumulators))| {
+            row.extend(accumulators.iter().map(Accumulator::finish));
+            row
+        })
+        .collect())
+}
+
+/// State of an aggregate function over the rows of a group, which are fed
+/// to it one at a time as the values of its arguments.
+#[derive(Clone, Debug)]
+pub enum Accumulator {
+    /// `COUNT(*)` when counting every row
+    Count {
+        count: i64,
+        every_row: bool,
+    },
+    /// Integer while every value summed is one
+    Sum(Option<Value>),
+    Avg {
+        total: f64,
+        count: i64,
+    },
+    Min(Value),
+    Max(Value),
+    GroupConcat(Option<String>),
+}
+
+impl Accumulator {
+    pub fn new(name: &str, num_arguments: usize) -> Result<Accumulator, String> {
+        let accumulator = match (name, num_arguments) {
+            ("count", 0) | ("count", 1) => Accumulator::Count {
+                count: 0,
+                every_row: num_arguments == 0,
+            },
+            ("sum", 1) => Accumulator::Sum(None),
+            ("avg", 1) => Accumulator::Avg {
+                total: 0.0,
+                count: 0,
+            },
+            ("min", 1) => Accumulator::Min(Value::Null),
+            ("max", 1) => Accumulator::Max(Value::Null),
+            ("group_concat", 1) | ("group_concat", 2) => Accumulator::GroupConcat(None),
+            (name, _) if is_aggregate(name) => {
+                return Err(format!("wrong number of arguments to function {}()", name))
+            }
+            (name, _) => return Err(format!("no such function: {}", name)),
+        };
+        Ok(accumulator)
+    }
+
+    /// Adds the row whose arguments have the values in `arguments`. NULLs
+    /// are left out by every function but `COUNT(*)`.
+    pub fn step(&mut self, arguments: &[Value]) -> Result<(), String> {
+        if let Accumulator::Count { count, every_row } = self {
+            if *every_row || arguments[0] != Value::Null {
+                *count += 1;
+            }
+            return Ok(());
+        }
+        let value = match arguments.first() {
+            None | Some(Value::Null) => return Ok(()),
+            Some(value) => value,
+        };
+        match self {
+            Accumulator::Sum(sum) => {
+                let value = expression::to_number(value);
+                *sum = Some(match (sum.take(), value) {
+                    (None, value) => value,
+                    (Some(Value::Integer(a)), Value::Integer(b)) => {
+                        Value::Integer(a.checked_add(b).ok_or("integer overflow")?)
+                    }
+                    (Some(a), b) => Value::Real(as_real(&a) + as_real(&b)),
+                });
+            }
+            Accumulator::Avg { total, count } => {
+                *total += as_real(&expression::to_number(value));
+                *count += 1;
+            }
+            Accumulator::Min(min) => {
+                if *min == Value::Null || value < min {
+                    *min = value.clone();
+                }
+            }
+            Accumulator::Max(max) => {
+                if *max == Value::Null || value > max {
+                    *max = value.clone();
+                }
+            }
+            Accumulator::GroupConcat(text) => {
+                let separator = match arguments.get(1) {
+                    None => ",".to_string(),
+                    Some(Value::Null) => "".to_string(),
+                    Some(separator) => separator.to_string(),
+                };
+                *text = Some(match text.take() {
+                    None => value.to_string(),
+                    Some(text) => format!("{}{}{}", text, separator, value),
+                });
+            }
+            Accumulator::Count { .. } => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Result over the rows added so far, which is NULL for an empty group
+    /// except for counts.
+    pub fn finish(&self) -> Value {
+        match self {
+            Accumulator::Count { count, .. } => Value::Integer(*count),
+            Accumulator::Sum(sum) => sum.clone().unwrap_or(Value::Null),
+            Accumulator::Avg { count: 0, .. } => Value::Null,
+            Accumulator::Avg { total, count } => Value::Real(total / *count as f64),
+            Accumulator::Min(value) | Accumulator::Max(value) => value.clone(),
+            Accumulator::GroupConcat(text) => text.clone().map_or(Value::Null, Value::Text),
+        }
+    }
+}
+
+fn as_real(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Real(r) => *r,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn aggregate(name: &str, rows: &[Vec<Value>]) -> Result<Value, String> {
+        let mut accumulator = Accumulator::new(name, rows.first().map_or(1, Vec::len))?;
+        for row in rows {
+            accumulator.step(row)?;
+        }
+        Ok(accumulator.finish())
+    }
+
+    #[test]
+    fn nulls_are_left_out_of_aggregates() {
+        let rows = [
+            vec![Value::Integer(3)],
+            vec![Value::Null],
+            vec![Value::Integer(1)],
+        ];
+        assert_eq!(aggregate("count", &rows), Ok(Value::Integer(2)));
+        assert_eq!(aggregate("sum", &rows), Ok(Value::Integer(4)));
+        assert_eq!(aggregate("avg", &rows), Ok(Value::Real(2.0)));
+        assert_eq!(aggregate("min", &rows), Ok(Value::Integer(1)));
+        assert_eq!(aggregate("max", &rows), Ok(Value::Integer(3)));
+        assert_eq!(
+            aggregate("group_concat", &rows),
+            Ok(Value::Text("3,1".to_string()))
+        );
+
+        let mut count_rows = Accumulator::new("count", 0).unwrap();
+        for _ in &rows {
+            count_rows.step(&[]).unwrap();
+        }
+        assert_eq!(count_rows.finish(), Value::Integer(3));
+    }
+
+    #[test]
+    fn aggregates_of_no_values_are_null_except_counts() {
+        let rows = [vec![Value::Null]];
+        assert_eq!(aggregate("count", &rows), Ok(Value::Integer(0)));
+        assert_eq!(aggregate("sum", &rows), Ok(Value::Null));
+        assert_eq!(aggregate("avg", &rows), Ok(Value::Null));
+        assert_eq!(aggregate("max", &rows), Ok(Value::Null));
+        assert_eq!(aggregate("group_concat", &rows), Ok(Value::Null));
+    }
+
+    #[test]
+    fn sums_of_integers_stay_integers_until_a_real_is_added() {
+        let rows = [vec![Value::Integer(1)], vec![Value::Text("2".to_string())]];
+        assert_eq!(aggregate("sum", &rows), Ok(Value::Integer(3)));
+        let rows = [vec![Value::Integer(1)], vec![Value::Real(0.5)]];
+        assert_eq!(aggregate("sum", &rows), Ok(Value::Real(1.5)));
+        let rows = [vec![Value::Integer(i64::MAX)], vec![Value::Integer(1)]];
+        assert_eq!(aggregate("sum", &rows), Err("integer overflow".to_string()));
+    }
+
+    #[test]
+    fn unknown_functions_and_wrong_numbers_of_arguments_are_rejected() {
+        assert_eq!(
+            Accumulator::new("sum", 2).err(),
+            Some("wrong number of arguments to function sum()".to_string())
+        );
+        assert_eq!(
+            Accumulator::new("median", 1).err(),
+            Some("no such function: median".to_string())
+        );
+    }
+}
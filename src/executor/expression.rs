@@ -0,0 +1,524 @@
+use super::aggregate;
+use crate::ast::{BinaryOperator, Expression, UnaryOperator, Value};
+use crate::table::Affinity;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Bound;
+
+/// Index of the columns whose name several tables of a join have.
+pub const AMBIGUOUS: usize = usize::MAX;
+
+/// Checks that every column the expression refers to is one of `columns`.
+/// Aggregate calls have to be replaced by the columns holding their results
+/// before, which is only done where they are allowed.
+pub fn validate(expression: &Expression, columns: &HashMap<String, usize>) -> Result<(), String> {
+    match expression {
+        Expression::Literal(_) => Ok(()),
+        Expression::Column(name) => match columns.get(name) {
+            None => Err(format!("no such column: {}", name)),
+            Some(&AMBIGUOUS) => Err(format!("ambiguous column name: {}", name)),
+            Some(_) => Ok(()),
+        },
+        Expression::Unary(_, operand) | Expression::IsNull(operand, _) => {
+            validate(operand, columns)
+        }
+        Expression::Binary(left, _, right) => {
+            validate(left, columns)?;
+            validate(right, columns)
+        }
+        Expression::Function(name, _) if aggregate::is_aggregate(name) => {
+            Err(format!("misuse of aggregate: {}()", name))
+        }
+        Expression::Function(name, _) => Err(format!("no such function: {}", name)),
+    }
+}
+
+/// Value of the expression for `row`, whose values are found through the
+/// column indices in `columns` and have the affinities at the same indices
+/// in `affinities`. Null stands for unknown, so that comparisons and logic
+/// operators follow three-valued logic like SQLite.
+pub fn evaluate(
+    expression: &Expression,
+    columns: &HashMap<String, usize>,
+    affinities: &[Affinity],
+    row: &[Value],
+) -> Value {
+    let evaluate = |expression| evaluate(expression, columns, affinities, row);
+    match expression {
+        Expression::Literal(value) => value.clone(),
+        Expression::Column(name) => row[columns[name]].clone(),
+        Expression::Function(name, _) => unreachable!("{}() is not validated", name),
+        Expression::IsNull(operand, negated) => {
+            let is_null = evaluate(operand) == Value::Null;
+            Value::Integer((is_null != *negated) as i64)
+        }
+        Expression::Unary(operator, operand) => {
+            let operand = evaluate(operand);
+            match operator {
+                UnaryOperator::Not => truth(&operand).map_or(Value::Null, |t| boolean(!t)),
+                UnaryOperator::Negate => match to_number(&operand) {
+                    Value::Integer(i) => i
+                        .checked_neg()
+                        .map_or(Value::Real(-(i as f64)), Value::Integer),
+                    Value::Real(r) => Value::Real(-r),
+                    value => value,
+                },
+            }
+        }
+        Expression::Binary(left, BinaryOperator::And, right) => {
+            let left = truth(&evaluate(left));
+            if left == Some(false) {
+                return boolean(false);
+            }
+            match (left, truth(&evaluate(right))) {
+                (_, Some(false)) => boolean(false),
+                (Some(true), Some(true)) => boolean(true),
+                _ => Value::Null,
+            }
+        }
+        Expression::Binary(left, BinaryOperator::Or, right) => {
+            let left = truth(&evaluate(left));
+            if left == Some(true) {
+                return boolean(true);
+            }
+            match (left, truth(&evaluate(right))) {
+                (_, Some(true)) => boolean(true),
+                (Some(false), Some(false)) => boolean(false),
+                _ => Value::Null,
+            }
+        }
+        Expression::Binary(left_operand, operator, right_operand) => {
+            let left = evaluate(left_operand);
+            let right = evaluate(right_operand);
+            if left == Value::Null || right == Value::Null {
+                return Value::Null;
+            }
+            let (left, right) = match is_comparison(*operator) {
+                true => {
+                    let (left_affinity, right_affinity) = comparison_affinities(
+                        affinity(left_operand, columns, affinities),
+                        affinity(right_operand, columns, affinities),
+                    );
+                    (left_affinity.apply(left), right_affinity.apply(right))
+                }
+                false => (left, right),
+            };
+            match operator {
+                BinaryOperator::Equal => boolean(left == right),
+                BinaryOperator::NotEqual => boolean(left != right),
+                BinaryOperator::Less => boolean(left < right),
+                BinaryOperator::LessOrEqual => boolean(left <= right),
+                BinaryOperator::Greater => boolean(left > right),
+                BinaryOperator::GreaterOrEqual => boolean(left >= right),
+                _ => arithmetic(*operator, to_number(&left), to_number(&right)),
+            }
+        }
+    }
+}
+
+fn is_comparison(operator: BinaryOperator) -> bool {
+    matches!(
+        operator,
+        BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::Less
+            | BinaryOperator::LessOrEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterOrEqual
+    )
+}
+
+/// Affinity of the expression as an operand of a comparison. Only columns
+/// have one, other expressions count as having none, like columns of BLOB
+/// affinity.
+pub fn affinity(
+    expression: &Expression,
+    columns: &HashMap<String, usize>,
+    affinities: &[Affinity],
+) -> Affinity {
+    let index = match expression {
+        Expression::Column(name) => columns.get(name),
+        _ => None,
+    };
+    index
+        .and_then(|index| affinities.get(*index))
+        .copied()
+        .unwrap_or(Affinity::Blob)
+}
+
+/// Affinities the operands of a comparison are converted with before they
+/// are compared, given the affinities of their expressions. Like SQLite,
+/// an operand is converted to a number if only the other one is numeric,
+/// or else to text if it has no affinity and the other one is text. BLOB
+/// affinity leaves a value as it is.
+pub fn comparison_affinities(left: Affinity, right: Affinity) -> (Affinity, Affinity) {
+    let is_numeric = |affinity| {
+        matches!(
+            affinity,
+            Affinity::Integer | Affinity::Real | Affinity::Numeric
+        )
+    };
+    match (left, right) {
+        (left, right) if is_numeric(left) && !is_numeric(right) => {
+            (Affinity::Blob, Affinity::Numeric)
+        }
+        (left, right) if !is_numeric(left) && is_numeric(right) => {
+            (Affinity::Numeric, Affinity::Blob)
+        }
+        (Affinity::Text, Affinity::Blob) => (Affinity::Blob, Affinity::Text),
+        (Affinity::Blob, Affinity::Text) => (Affinity::Text, Affinity::Blob),
+        _ => (Affinity::Blob, Affinity::Blob),
+    }
+}
+
+/// Terms of the expression that all have to hold for it to hold.
+pub fn conjuncts(expression: &Expression) -> Vec<&Expression> {
+    match expression {
+        Expression::Binary(left, BinaryOperator::And, right) => {
+            let mut terms = conjuncts(left);
+            terms.extend(conjuncts(right));
+            terms
+        }
+        expression => vec![expression],
+    }
+}
+
+/// Bounds of the values a column is restricted to
+pub type Range = (Bound<Value>, Bound<Value>);
+
+/// Ranges that comparisons with constants restrict the values of the first
+/// `num_columns` columns to, for the expression to hold. The range of each
+/// of these columns comes with its index. The constants are converted like
+/// the comparisons convert them, so that the ranges hold the values of the
+/// columns the comparisons hold for.
+pub fn column_ranges(
+    expression: &Expression,
+    columns: &HashMap<String, usize>,
+    affinities: &[Affinity],
+    num_columns: usize,
+) -> Vec<(usize, Range)> {
+    let column = |expression: &Expression| match expression {
+        Expression::Column(name) => columns.get(name).filter(|i| **i < num_columns).cloned(),
+        _ => None,
+    };
+    let no_columns = HashMap::new();
+    let constant = |expression: &Expression| {
+        validate(expression, &no_columns)
+            .ok()
+            .map(|_| evaluate(expression, &no_columns, &[], &[]))
+    };
+    let mut ranges: Vec<(usize, Range)> = vec![];
+    for conjunct in conjuncts(expression) {
+        let (left, operator, right) = match conjunct {
+            Expression::Binary(left, operator, right) => (left, *operator, right),
+            _ => continue,
+        };
+        // Puts the column on the left of the comparison
+        let (column, operator, value) = match (column(left), column(right)) {
+            (Some(column), None) => match constant(right) {
+                None => continue,
+                Some(value) => (column, operator, value),
+            },
+            (None, Some(column)) => match constant(left) {
+                None => continue,
+                Some(value) => (column, flip(operator), value),
+            },
+            _ => continue,
+        };
+        // Constants have no affinity, so only they are converted
+        let column_affinity = affinities.get(column).copied().unwrap_or(Affinity::Blob);
+        let (_, value_affinity) = comparison_affinities(column_affinity, Affinity::Blob);
+        let value = value_affinity.apply(value);
+        let range = match operator {
+            BinaryOperator::Equal => (Bound::Included(value.clone()), Bound::Included(value)),
+            BinaryOperator::Less => (Bound::Unbounded, Bound::Excluded(value)),
+            BinaryOperator::LessOrEqual => (Bound::Unbounded, Bound::Included(value)),
+            BinaryOperator::Greater => (Bound::Excluded(value), Bound::Unbounded),
+            BinaryOperator::GreaterOrEqual => (Bound::Included(value), Bound::Unbounded),
+            _ => continue,
+        };
+        match ranges.iter_mut().find(|(i, _)| *i == column) {
+            None => ranges.push((column, range)),
+            Some((_, (start, end))) => {
+                *start = tighter(start.clone(), range.0, Ordering::Greater);
+                *end = tighter(end.clone(), range.1, Ordering::Less);
+            }
+        }
+    }
+    ranges
+}
+
+/// Operator comparing the operands the other way around.
+fn flip(operator: BinaryOperator) -> BinaryOperator {
+    match operator {
+        BinaryOperator::Less => BinaryOperator::Greater,
+        BinaryOperator::LessOrEqual => BinaryOperator::GreaterOrEqual,
+        BinaryOperator::Greater => BinaryOperator::Less,
+        BinaryOperator::GreaterOrEqual => BinaryOperator::LessOrEqual,
+        operator => operator,
+    }
+}
+
+/// The bound leaving out more values, which is the one further in the
+/// direction of `inwards`.
+fn tighter(a: Bound<Value>, b: Bound<Value>, inwards: Ordering) -> Bound<Value> {
+    let ordering = match (&a, &b) {
+        (Bound::Unbounded, _) => return b,
+        (_, Bound::Unbounded) => return a,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            x.cmp(y)
+        }
+    };
+    match (ordering, &a) {
+        (Ordering::Equal, Bound::Excluded(_)) => a,
+        (Ordering::Equal, _) => b,
+        (ordering, _) if ordering == inwards => a,
+        _ => b,
+    }
+}
+
+/// Whether the row passes a WHERE clause, which rejects unknown results.
+pub fn is_true(value: &Value) -> bool {
+    truth(value) == Some(true)
+}
+
+/// Whether the row fails a CHECK constraint, which lets unknown results
+/// through.
+pub fn is_false(value: &Value) -> bool {
+    truth(value) == Some(false)
+}
+
+fn truth(value: &Value) -> Option<bool> {
+    match to_number(value) {
+        Value::Integer(i) => Some(i != 0),
+        Value::Real(r) => Some(r != 0.0),
+        _ => None,
+    }
+}
+
+fn boolean(b: bool) -> Value {
+    Value::Integer(b as i64)
+}
+
+fn arithmetic(operator: BinaryOperator, left: Value, right: Value) -> Value {
+    if let (Value::Integer(l), Value::Integer(r)) = (&left, &right) {
+        let (l, r) = (*l, *r);
+        let result = match operator {
+            BinaryOperator::Add => l.checked_add(r),
+            BinaryOperator::Subtract => l.checked_sub(r),
+            BinaryOperator::Multiply => l.checked_mul(r),
+            // Division by zero has no result rather than failing the query
+            BinaryOperator::Divide | BinaryOperator::Remainder if r == 0 => return Value::Null,
+            BinaryOperator::Divide => l.checked_div(r),
+            BinaryOperator::Remainder => l.checked_rem(r).or(Some(0)),
+            _ => unreachable!("{:?} is not arithmetic", operator),
+        };
+        // Results that overflow an integer are computed as reals instead
+        if let Some(result) = result {
+            return Value::Integer(result);
+        }
+    }
+    let (l, r) = (as_real(&left), as_real(&right));
+    let result = match operator {
+        BinaryOperator::Add => l + r,
+        BinaryOperator::Subtract => l - r,
+        BinaryOperator::Multiply => l * r,
+        BinaryOperator::Divide if r == 0.0 => return Value::Null,
+        BinaryOperator::Divide => l / r,
+        // Like SQLite, the remainder of reals is that of their integer parts
+        BinaryOperator::Remainder => match (l as i64, r as i64) {
+            (_, 0) => return Value::Null,
+            (l, r) => l.checked_rem(r).unwrap_or(0) as f64,
+        },
+        _ => unreachable!("{:?} is not arithmetic", operator),
+    };
+    // Like SQLite, results that are not a number, such as the difference of
+    // two infinities, are NULL
+    match result.is_nan() {
+        true => Value::Null,
+        false => Value::Real(result),
+    }
+}
+
+fn as_real(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Real(r) => *r,
+        _ => 0.0,
+    }
+}
+
+/// Numeric value of the operand of an arithmetic or logic operator. Text and
+/// blobs are read up to the end of their longest numeric prefix, which is 0
+/// if there is none.
+pub fn to_number(value: &Value) -> Value {
+    let text = match value {
+        Value::Text(text) => text.clone(),
+        Value::Blob(bytes) => String::from_utf8_lossy(bytes).to_string(),
+        value => return value.clone(),
+    };
+    let text = text.trim_start();
+    let prefix = (0..=text.len())
+        .rev()
+        .filter(|end| text.is_char_boundary(*end))
+        .map(|end| &text[..end])
+        .find(|prefix| {
+            prefix.ends_with(|c: char| c.is_ascii_digit() || c == '.')
+                && prefix.parse::<f64>().is_ok()
+        });
+    match prefix {
+        None => Value::Integer(0),
+        Some(prefix) => match prefix.parse::<i64>() {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::Real(prefix.parse::<f64>().unwrap()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Ast, Selection};
+    use crate::executor::Selection as _;
+    use crate::sqlite3;
+    use pretty_assertions::assert_eq;
+
+    fn evaluate_where(clause: &str, row: Vec<Value>) -> Value {
+        evaluate_where_with(clause, &[], row)
+    }
+
+    fn evaluate_where_with(clause: &str, affinities: &[Affinity], row: Vec<Value>) -> Value {
+        let statement = format!("SELECT * FROM t WHERE {};", clause);
+        let selection: Selection = match sqlite3::AstParser::new().parse(&statement).unwrap() {
+            Ast::Select(selection) => *selection,
+            ast => panic!("unexpected statement: {:?}", ast),
+        };
+        let columns = vec![("a".to_string(), 0), ("b".to_string(), 1)]
+            .into_iter()
+            .collect();
+        evaluate(
+            selection.where_clause().unwrap(),
+            &columns,
+            affinities,
+            &row,
+        )
+    }
+
+    #[test]
+    fn comparisons_with_null_are_unknown() {
+        let row = vec![Value::Integer(1), Value::Null];
+        assert_eq!(evaluate_where("a = 1", row.clone()), Value::Integer(1));
+        assert_eq!(evaluate_where("b = 1", row.clone()), Value::Null);
+        assert_eq!(evaluate_where("b <> 1", row.clone()), Value::Null);
+        assert_eq!(evaluate_where("NOT b = 1", row.clone()), Value::Null);
+        assert_eq!(evaluate_where("b IS NULL", row.clone()), Value::Integer(1));
+        assert_eq!(evaluate_where("a IS NOT NULL", row), Value::Integer(1));
+    }
+
+    #[test]
+    fn logic_operators_follow_three_valued_logic() {
+        let row = vec![Value::Integer(1), Value::Null];
+        assert_eq!(
+            evaluate_where("b = 1 AND a = 2", row.clone()),
+            Value::Integer(0)
+        );
+        assert_eq!(evaluate_where("b = 1 AND a = 1", row.clone()), Value::Null);
+        assert_eq!(
+            evaluate_where("b = 1 OR a = 1", row.clone()),
+            Value::Integer(1)
+        );
+        assert_eq!(evaluate_where("b = 1 OR a = 2", row.clone()), Value::Null);
+        assert_eq!(
+            evaluate_where("NOT (a = 2 OR a = 3)", row),
+            Value::Integer(1)
+        );
+    }
+
+    #[test]
+    fn arithmetic_binds_tighter_than_comparisons() {
+        let row = vec![Value::Integer(7), Value::Real(2.5)];
+        assert_eq!(
+            evaluate_where("a + 1 * 2 = 9", row.clone()),
+            Value::Integer(1)
+        );
+        assert_eq!(
+            evaluate_where("(a + 1) * 2 - -1", row.clone()),
+            Value::Integer(17)
+        );
+        assert_eq!(
+            evaluate_where("a / 2 + a % 4", row.clone()),
+            Value::Integer(6)
+        );
+        assert_eq!(evaluate_where("a * b", row.clone()), Value::Real(17.5));
+        assert_eq!(evaluate_where("a / 0", row.clone()), Value::Null);
+        assert_eq!(
+            evaluate_where("'3 apples' + a", row.clone()),
+            Value::Integer(10)
+        );
+        assert_eq!(
+            evaluate_where("9223372036854775807 + a", row.clone()),
+            Value::Real(9223372036854775807.0 + 7.0)
+        );
+        assert_eq!(
+            evaluate_where("1e308 * 10 - 1e308 * 10", row.clone()),
+            Value::Null
+        );
+        assert_eq!(evaluate_where("1e308 * 10 * 0", row), Value::Null);
+    }
+
+    #[test]
+    fn compared_values_are_converted_by_the_affinities_of_their_columns() {
+        let affinities = [Affinity::Integer, Affinity::Text];
+        let row = vec![Value::Integer(1), Value::Text("1".to_string())];
+        let evaluate_where = |clause| evaluate_where_with(clause, &affinities, row.clone());
+        assert_eq!(evaluate_where("a = '1'"), Value::Integer(1));
+        assert_eq!(evaluate_where("a = ' 1.0 '"), Value::Integer(1));
+        assert_eq!(evaluate_where("b = 1"), Value::Integer(1));
+        assert_eq!(evaluate_where("a = b"), Value::Integer(1));
+        assert_eq!(evaluate_where("b < 2"), Value::Integer(1));
+        assert_eq!(evaluate_where("b + 0 = '1'"), Value::Integer(0));
+        assert_eq!(evaluate_where("a = 'one'"), Value::Integer(0));
+
+        // Values without an affinity are compared as they are
+        let row = vec![Value::Integer(1), Value::Text("1".to_string())];
+        assert_eq!(evaluate_where_with("a = b", &[], row), Value::Integer(0));
+    }
+
+    #[test]
+    fn ranges_hold_the_constants_converted_like_the_comparisons() {
+        let statement = "SELECT * FROM t WHERE a >= '2' AND b = 3 AND c < 'x';";
+        let selection: Selection = match sqlite3::AstParser::new().parse(statement).unwrap() {
+            Ast::Select(selection) => *selection,
+            ast => panic!("unexpected statement: {:?}", ast),
+        };
+        let columns = vec![
+            ("a".to_string(), 0),
+            ("b".to_string(), 1),
+            ("c".to_string(), 2),
+        ]
+        .into_iter()
+        .collect();
+        let affinities = [Affinity::Integer, Affinity::Text, Affinity::Blob];
+        let where_clause = selection.where_clause().unwrap();
+        assert_eq!(
+            column_ranges(where_clause, &columns, &affinities, 3),
+            vec![
+                (0, (Bound::Included(Value::Integer(2)), Bound::Unbounded)),
+                (
+                    1,
+                    (
+                        Bound::Included(Value::Text("3".to_string())),
+                        Bound::Included(Value::Text("3".to_string()))
+                    )
+                ),
+                (
+                    2,
+                    (
+                        Bound::Unbounded,
+                        Bound::Excluded(Value::Text("x".to_string()))
+                    )
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use super::{column_indices, Table};
+use crate::ast::{ForeignKey, ForeignKeyAction, Value};
+use std::collections::HashMap;
+use std::ops::Bound;
+
+/// Foreign key of the child table, with its columns and those of the
+/// parent table given by their indices in the rows.
+#[derive(Clone)]
+pub struct Reference {
+    pub child: String,
+    pub columns: Vec<usize>,
+    pub parent: String,
+    pub parent_columns: Vec<usize>,
+    pub on_delete: ForeignKeyAction,
+    pub on_update: ForeignKeyAction,
+}
+
+/// Values that the rows of the child table of the reference can only have
+/// in its columns once a statement is over if the parent table has a row
+/// with them.
+pub type Check = (Reference, Vec<Value>);
+
+impl Reference {
+    /// Values of the row in the columns of the child table, unless one of
+    /// them is NULL, which leaves the row free of the constraint.
+    pub fn child_values(&self, row: &[Value]) -> Option<Vec<Value>> {
+        values(row, &self.columns)
+    }
+
+    /// Values of the row in the columns of the parent table, unless one of
+    /// them is NULL, which no row of the child table can refer to.
+    pub fn parent_values(&self, row: &[Value]) -> Option<Vec<Value>> {
+        values(row, &self.parent_columns)
+    }
+}
+
+fn values(row: &[Value], columns: &[usize]) -> Option<Vec<Value>> {
+    let values = columns.iter().map(|i| row[*i].clone()).collect::<Vec<_>>();
+    Some(values).filter(|values| !values.contains(&Value::Null))
+}
+
+/// Foreign keys of the table.
+pub fn references<T: Table>(
+    tables: &HashMap<String, T>,
+    table: &T,
+) -> Result<Vec<Reference>, String> {
+    table
+        .foreign_keys()
+        .iter()
+        .map(|foreign_key| resolve(tables, table, foreign_key))
+        .collect()
+}
+
+/// Foreign keys of every table, itself included, that refer to the table.
+pub fn referring<T: Table>(
+    tables: &HashMap<String, T>,
+    parent: &str,
+) -> Result<Vec<Reference>, String> {
+    let mut references = vec![];
+    for table in tables.values() {
+        for foreign_key in table.foreign_keys() {
+            if foreign_key.parent_table == parent {
+                references.push(resolve(tables, table, &foreign_key)?);
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// Resolves the columns of the foreign key, which have to make up the
+/// primary key of the parent table or one of its UNIQUE indexes.
+fn resolve<T: Table>(
+    tables: &HashMap<String, T>,
+    child: &T,
+    foreign_key: &ForeignKey,
+) -> Result<Reference, String> {
+    let parent = tables
+        .get(&foreign_key.parent_table)
+        .ok_or(format!("no such table: {}", foreign_key.parent_table))?;
+    let mismatch = || {
+        format!(
+            "foreign key mismatch - \"{}\" referencing \"{}\"",
+            child.name(),
+            parent.name()
+        )
+    };
+    let parent_column_indices = column_indices(parent);
+    let parent_columns = match foreign_key.parent_columns.len() {
+        0 => {
+            let key = parent.key_indices();
+            key.iter()
+                .all(|i| *i < parent.columns().len())
+                .then_some(key)
+                .ok_or_else(mismatch)?
+        }
+        _ => foreign_key
+            .parent_columns
+            .iter()
+            .map(|name| {
+                parent_column_indices
+                    .get(name)
+                    .copied()
+                    .ok_or_else(mismatch)
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    let mut sorted_columns = parent_columns.clone();
+    sorted_columns.sort_unstable();
+    let is_unique = parent.unique_keys().into_iter().any(|mut key| {
+        key.sort_unstable();
+        key == sorted_columns
+    });
+    if !is_unique || parent_columns.len() != foreign_key.columns.len() {
+        return Err(mismatch());
+    }
+    let child_column_indices = column_indices(child);
+    let columns = foreign_key
+        .columns
+        .iter()
+        .map(|name| child_column_indices.get(name).copied().ok_or_else(mismatch))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Reference {
+        child: child.name(),
+        columns,
+        parent: parent.name(),
+        parent_columns,
+        on_delete: foreign_key.on_delete,
+        on_update: foreign_key.on_update,
+    })
+}
+
+/// Rows of the table with the values in the columns, which are looked up
+/// by their primary key or through an index of the first column if the
+/// table has one.
+pub fn rows_with<T: Table>(
+    table: &T,
+    columns: &[usize],
+    values: &[Value],
+) -> Result<Vec<Vec<Value>>, String> {
+    if let ([key_index], [value]) = (&table.key_indices()[..], values) {
+        if columns == [*key_index] {
+            return Ok(table.select_row(value)?.into_iter().collect());
+        }
+    }
+    let range = (
+        Bound::Included(values[0].clone()),
+        Bound::Included(values[0].clone()),
+    );
+    let rows = match table.select_rows_in_range(columns[0], range)? {
+        Some(rows) => rows,
+        None => table.select_rows()?,
+    };
+    rows.filter(|row| match row {
+        Ok(row) => columns
+            .iter()
+            .zip(values)
+            .all(|(i, value)| row[*i] == *value),
+        Err(_) => true,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{in_memory, open, run};
+    use super::super::Executor;
+    use crate::ast::Value;
+
+    fn failed() -> Result<Vec<Vec<Value>>, String> {
+        Err("FOREIGN KEY constraint failed".to_string())
+    }
+
+    #[test]
+    fn foreign_keys_are_only_enforced_once_turned_on() {
+        let pager = in_memory();
+        let mut executor = Executor::new(pager.clone());
+        let mut run = |statement| run(&mut executor, &pager, statement);
+
+        run("CREATE TABLE artist(id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+        run("CREATE TABLE track(name TEXT, artist INTEGER REFERENCES artist);").unwrap();
+        run("INSERT INTO track VALUES ('intro', 1);").unwrap();
+        assert_eq!(
+            run("PRAGMA foreign_keys;"),
+            Ok(vec![vec![Value::Integer(0)]])
+        );
+
+        run("PRAGMA foreign_keys = ON;").unwrap();
+        assert_eq!(run("INSERT INTO track VALUES ('outro', 2);"), failed());
+        run("INSERT INTO track VALUES ('outro', NULL);").unwrap();
+        run("INSERT INTO artist VALUES (2, 'duo');").unwrap();
+        run("BEGIN;").unwrap();
+        run("INSERT INTO track VALUES ('verse', '2');").unwrap();
+        assert_eq!(run("UPDATE track SET artist = 3;"), failed());
+        // Statements within a transaction are undone on their own
+        assert_eq!(run("PRAGMA foreign_keys = OFF;"), Ok(vec![]));
+        run("COMMIT;").unwrap();
+        assert_eq!(
+            run("PRAGMA foreign_keys;"),
+            Ok(vec![vec![Value::Integer(1)]])
+        );
+        assert_eq!(
+            run("SELECT artist FROM track;"),
+            Ok(vec![
+                vec![Value::Integer(1)],
+                vec![Value::Null],
+                vec![Value::Integer(2)],
+            ])
+        );
+    }
+
+    #[test]
+    fn rows_referring_to_deleted_or_updated_rows_take_the_action_of_their_key() {
+        let pager = in_memory();
+        let mut executor = Executor::new(pager.clone());
+        let mut run = |statement| run(&mut executor, &pager, statement);
+
+        run("PRAGMA foreign_keys = 1;").unwrap();
+        run("CREATE TABLE artist(id INTEGER PRIMARY KEY, name TEXT UNIQUE);").unwrap();
+        run("CREATE TABLE track(id INTEGER PRIMARY KEY, artist INTEGER \
+            REFERENCES artist ON DELETE CASCADE ON UPDATE CASCADE);")
+        .unwrap();
+        run("CREATE TABLE award(track INTEGER REFERENCES track ON DELETE SET NULL);").unwrap();
+        run(
+            "CREATE TABLE fan(artist TEXT, FOREIGN KEY (artist) REFERENCES artist(name) \
+            ON UPDATE RESTRICT);",
+        )
+        .unwrap();
+        for statement in &[
+            "INSERT INTO artist VALUES (1, 'solo');",
+            "INSERT INTO artist VALUES (2, 'duo');",
+            "INSERT INTO track VALUES (10, 1);",
+            "INSERT INTO track VALUES (20, 2);",
+            "INSERT INTO award VALUES (10);",
+            "INSERT INTO fan VALUES ('duo');",
+        ] {
+            run(statement).unwrap();
+        }
+
+        run("UPDATE artist SET id = 3 WHERE id = 1;").unwrap();
+        assert_eq!(
+            run("SELECT * FROM track;"),
+            Ok(vec![
+                vec![Value::Integer(10), Value::Integer(3)],
+                vec![Value::Integer(20), Value::Integer(2)],
+            ])
+        );
+        run("DELETE FROM artist WHERE id = 3;").unwrap();
+        assert_eq!(
+            run("SELECT * FROM track;"),
+            Ok(vec![vec![Value::Integer(20), Value::Integer(2)]])
+        );
+        assert_eq!(run("SELECT * FROM award;"), Ok(vec![vec![Value::Null]]));
+
+        assert_eq!(run("UPDATE artist SET name = 'trio';"), failed());
+        // Without an action the key is only checked once the statement is over
+        assert_eq!(run("DELETE FROM artist;"), failed());
+        run("DELETE FROM fan;").unwrap();
+        run("DROP TABLE artist;").unwrap();
+        assert_eq!(run("SELECT * FROM track;"), Ok(vec![]));
+        assert_eq!(
+            run("INSERT INTO track VALUES (30, 1);"),
+            Err("no such table: artist".to_string())
+        );
+    }
+
+    #[test]
+    fn foreign_keys_follow_their_parent_table_when_renamed() {
+        let pager = in_memory();
+        let mut executor = open(&pager);
+        let mut run = |statement| run(&mut executor, &pager, statement);
+
+        run("PRAGMA foreign_keys = 1;").unwrap();
+        run("CREATE TABLE artist(id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+        run("CREATE TABLE track(name TEXT, artist INTEGER REFERENCES artist(id));").unwrap();
+        run("INSERT INTO artist VALUES (1, 'solo');").unwrap();
+        run("ALTER TABLE artist RENAME TO band;").unwrap();
+
+        run("INSERT INTO track VALUES ('intro', 1);").unwrap();
+        assert_eq!(run("INSERT INTO track VALUES ('outro', 2);"), failed());
+        assert_eq!(run("DELETE FROM band;"), failed());
+        assert_eq!(
+            run("SELECT sql FROM sqlite_schema WHERE name = 'track';"),
+            Ok(vec![vec![Value::Text(
+                "CREATE TABLE track (name TEXT, artist INTEGER REFERENCES band(id))".to_string()
+            )]])
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use super::{expression, Table};
+use crate::ast::{BinaryOperator, Expression, Value};
+use crate::table::Affinity;
+use std::collections::HashMap;
+
+/// Table joined to the rows of the tables before it in the FROM clause.
+pub struct Join<'a, T: Table> {
+    pub table: &'a T,
+    /// Whether rows without a match are kept, with NULLs for the columns of
+    /// the table
+    pub is_left: bool,
+    pub constraint: Option<Expression>,
+    /// Expression over the columns of the tables before, whose value is the
+    /// primary key of the only row that can match once converted with the
+    /// affinity it comes with
+    pub key: Option<(Expression, Affinity)>,
+    /// Index of each column of the tables joined so far, this one included
+    pub columns: HashMap<String, usize>,
+    /// Affinity of each value of the joined rows
+    pub affinities: Vec<Affinity>,
+}
+
+/// Joins the table to each of the rows. The matching rows are looked up by
+/// their primary key if there is a key expression, or else found by going
+/// through every row of the table.
+pub fn join<T: Table>(rows: Vec<Vec<Value>>, join: &Join<T>) -> Result<Vec<Vec<Value>>, String> {
+    let num_values = super::row_len(join.table);
+    let all_rows = match join.key {
+        None => join.table.select_rows()?.collect::<Result<_, _>>()?,
+        Some(_) => vec![],
+    };
+    let mut joined = vec![];
+    for mut row in rows {
+        let looked_up;
+        let candidates = match &join.key {
+            None => &all_rows,
+            Some((key, affinity)) => {
+                let key = expression::evaluate(key, &join.columns, &join.affinities, &row);
+                looked_up = match affinity.apply(key) {
+                    // Nothing is equal to NULL
+                    Value::Null => vec![],
+                    key => join.table.select_row(&key)?.into_iter().collect(),
+                };
+                &looked_up
+            }
+        };
+        let mut has_match = false;
+        for candidate in candidates {
+            let mut joined_row = row.clone();
+            joined_row.extend(candidate.iter().cloned());
+            let is_match = join.constraint.as_ref().is_none_or(|constraint| {
+                expression::is_true(&expression::evaluate(
+                    constraint,
+                    &join.columns,
+                    &join.affinities,
+                    &joined_row,
+                ))
+            });
+            if is_match {
+                has_match = true;
+                joined.push(joined_row);
+            }
+        }
+        if !has_match && join.is_left {
+            row.extend(vec![Value::Null; num_values]);
+            joined.push(row);
+        }
+    }
+    Ok(joined)
+}
+
+/// Expression that the column at `key_index` has to be equal to for one of
+/// the conditions to hold, and that only refers to `outer_columns`, along
+/// with the affinity the comparison converts its value with. Comparisons
+/// converting the values of the column instead cannot be looked up by key.
+pub fn key_expression(
+    conditions: &[&Expression],
+    key_index: usize,
+    columns: &HashMap<String, usize>,
+    affinities: &[Affinity],
+    outer_columns: &HashMap<String, usize>,
+) -> Option<(Expression, Affinity)> {
+    let is_key = |expression: &Expression| match expression {
+        Expression::Column(name) => columns.get(name) == Some(&key_index),
+        _ => false,
+    };
+    let key = |expression: &Expression| {
+        expression::validate(expression, outer_columns).ok()?;
+        let (key_affinity, affinity) = expression::comparison_affinities(
+            affinities[key_index],
+            expression::affinity(expression, columns, affinities),
+        );
+        match key_affinity {
+            Affinity::Blob => Some((expression.clone(), affinity)),
+            _ => None,
+        }
+    };
+    conditions
+        .iter()
+        .flat_map(|condition| expression::conjuncts(condition))
+        .find_map(|conjunct| match conjunct {
+            Expression::Binary(left, BinaryOperator::Equal, right) => {
+                if is_key(left) {
+                    key(right)
+                } else if is_key(right) {
+                    key(left)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{self, in_memory, open};
+    use crate::ast::Value;
+
+    #[test]
+    fn rows_are_looked_up_by_keys_converted_like_the_comparison() {
+        let pager = in_memory();
+        let mut executor = open(&pager);
+        let mut run = |statement| testing::run(&mut executor, &pager, statement);
+        run("CREATE TABLE x(i INTEGER PRIMARY KEY, t TEXT);").unwrap();
+        run("CREATE TABLE y(a TEXT, b INTEGER);").unwrap();
+        run("CREATE TABLE z(t TEXT PRIMARY KEY);").unwrap();
+        run("INSERT INTO x VALUES (1, '1');").unwrap();
+        run("INSERT INTO y VALUES ('1', 1);").unwrap();
+        run("INSERT INTO y VALUES ('01', 2);").unwrap();
+        run("INSERT INTO y VALUES ('one', 3);").unwrap();
+        run("INSERT INTO z VALUES ('1');").unwrap();
+        run("INSERT INTO z VALUES ('01');").unwrap();
+
+        let text = |text: &str| Value::Text(text.to_string());
+        assert_eq!(
+            run("SELECT y.b, x.t FROM y JOIN x ON x.i = y.a;"),
+            Ok(vec![
+                vec![Value::Integer(1), text("1")],
+                vec![Value::Integer(2), text("1")],
+            ])
+        );
+        // The values of the key are converted, so every row is compared
+        assert_eq!(
+            run("SELECT x.i, z.t FROM x JOIN z ON z.t = x.i;"),
+            Ok(vec![
+                vec![Value::Integer(1), text("01")],
+                vec![Value::Integer(1), text("1")],
+            ])
+        );
+    }
+}
@@ -0,0 +1,1968 @@
+use crate::ast::{
+    self, Alteration, ColumnSet, Expression, ForeignKeyAction, FromClause, JoinOperator,
+    OrderingTerm, TableReference, Value,
+};
+use crate::table::Affinity;
+use std::collections::HashMap;
+use std::ops::Bound;
+
+#[cfg(test)]
+extern crate mockers_derive;
+
+#[cfg(test)]
+use mockers_derive::mocked;
+
+mod aggregate;
+pub mod expression;
+mod foreign_key;
+mod join;
+mod pager;
+mod sorter;
+#[cfg(test)]
+pub mod testing;
+
+use aggregate::Accumulator;
+use foreign_key::{Check, Reference};
+use sorter::{SortKey, Sorter};
+
+/// Bytes of rows a sort holds in memory before spilling them to temporary
+/// pages
+const SORT_MEMORY_LIMIT: usize = 4 << 20;
+const TEMPORARY_PAGE_SIZE: u16 = 4096;
+
+/// Names the rowid of tables without a PRIMARY KEY can be referred to by
+pub const ROWID_NAMES: [&str; 3] = ["rowid", "_rowid_", "oid"];
+
+/// Name of the catalog listing the tables and indexes of the database
+pub const SCHEMA_TABLE: &str = "sqlite_schema";
+
+pub trait Column {
+    fn name(&self) -> &String;
+    /// Affinity of the declared type of the column
+    fn affinity(&self) -> Affinity;
+}
+
+#[cfg_attr(test, mocked)]
+pub trait Table {
+    /// Name of the table
+    ///
+    /// Returns a String rather than reference because the mocker does not
+    /// support references
+    fn name(&self) -> String;
+    /// Stores the row, returning it as it was stored, with the values
+    /// converted to the types of their columns and the rowid set.
+    fn insert_row(&mut self, row: Vec<Value>) -> Result<Vec<Value>, String>;
+    fn insert_row_with_named_columns(
+        &mut self,
+        row: HashMap<String, Value>,
+    ) -> Result<Vec<Value>, String>;
+    fn row_len(&self) -> usize;
+    fn select_rows(&self) -> Result<Rows, String>;
+    fn select_rows_with_named_columns(&self, column_names: &Vec<String>) -> Result<Rows, String>;
+    /// Row whose primary key is `key`, which is looked up in the tree.
+    fn select_row(&self, key: &Value) -> Result<Option<Vec<Value>>, String>;
+    /// Replaces each row paired with its new values, which may have a
+    /// different primary key. Returns the rows as they were stored.
+    fn update_rows(
+        &mut self,
+        rows: Vec<(Vec<Value>, Vec<Value>)>,
+    ) -> Result<Vec<Vec<Value>>, String>;
+    /// Removes the rows, which are found by their primary key.
+    fn delete_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<(), String>;
+    /// Frees the storage of the rows once the table is dropped.
+    fn drop_rows(&mut self) -> Result<(), String>;
+    fn rename(&mut self, name: &str);
+    /// Makes the foreign keys referring to the table called `old_name`
+    /// refer to `new_name` instead.
+    fn rename_parent(&mut self, old_name: &str, new_name: &str);
+    fn add_column(&mut self, column: ast::Column) -> Result<(), String>;
+    fn drop_column(&mut self, name: &str) -> Result<(), String>;
+    fn create_index(&mut self, index: ast::IndexSchema) -> Result<(), String>;
+    fn index_names(&self) -> Vec<String>;
+    /// Schema the table would be created with as it is now
+    fn schema(&self) -> ast::TableSchema;
+    /// Page the root of the tree of the rows is on
+    fn root_page(&self) -> u32;
+    /// Indexes of the table, the automatic ones included, with the page the
+    /// root of their tree is on
+    fn indexes(&self) -> Vec<(ast::IndexSchema, u32)>;
+    /// Rows whose value of the column at `column` is in the range, in no
+    /// particular order. None if the table has no index to find them with.
+    fn select_rows_in_range(
+        &self,
+        column: usize,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<Option<Rows>, String>;
+    /// Indices of the columns the rows are stored in order of, in the order
+    /// they are compared in. Tables without a PRIMARY KEY are stored in
+    /// order of a rowid, which comes after the values of the columns in the
+    /// rows.
+    fn key_indices(&self) -> Vec<usize>;
+    /// Indices of the columns of the primary key and of each UNIQUE index,
+    /// which no two rows have the same values in. The rowid is left out.
+    fn unique_keys(&self) -> Vec<Vec<usize>>;
+    /// FOREIGN KEY constraints of the table, those of the columns first
+    fn foreign_keys(&self) -> Vec<ast::ForeignKey>;
+    /// Columns in the order of the values of the rows
+    fn columns(&self) -> Vec<Box<dyn Column>>;
+}
+
+pub trait Insertion {
+    fn table_name(&self) -> &String;
+    fn validate(&self) -> Result<(), String>;
+    fn column_names(&self) -> Option<Box<dyn Iterator<Item = String>>>;
+    fn values(&self) -> Box<dyn Iterator<Item = Value>>;
+}
+
+pub trait Selection {
+    fn from(&self) -> &FromClause;
+    fn validate(&self) -> Result<(), String>;
+    fn columns(&self) -> ColumnSet;
+    fn where_clause(&self) -> Option<&Expression>;
+    fn group_by(&self) -> &Vec<Expression>;
+    fn having(&self) -> Option<&Expression>;
+    fn order_by(&self) -> &Vec<OrderingTerm>;
+    fn limit(&self) -> Option<&Expression>;
+    fn offset(&self) -> Option<&Expression>;
+}
+
+pub trait Update {
+    fn table_name(&self) -> &String;
+    fn assignments(&self) -> &Vec<(String, Expression)>;
+    fn where_clause(&self) -> Option<&Expression>;
+}
+
+pub trait Deletion {
+    fn table_name(&self) -> &String;
+    fn where_clause(&self) -> Option<&Expression>;
+}
+
+/// Storage behind the tables, which holds the changes made since the last
+/// commit apart so that they can still be thrown away.
+#[cfg_attr(test, mocked)]
+pub trait Journal {
+    fn commit(&mut self) -> Result<(), String>;
+    fn rollback(&mut self) -> Result<(), String>;
+    /// Starts a savepoint nested in the ones already started.
+    fn savepoint(&mut self);
+    /// Ends the savepoint at `depth` and the ones nested in it.
+    fn release(&mut self, depth: usize) -> Result<(), String>;
+    /// Undoes every change made since the savepoint at `depth` was started,
+    /// ending the ones nested in it.
+    fn rollback_to(&mut self, depth: usize) -> Result<(), String>;
+}
+
+/// Change to the tables held by the executor that a rollback has to undo.
+/// Changes to the rows of a table are undone by the `Journal`.
+enum Change<T> {
+    Create(String),
+    Drop(T),
+    /// Table now called by the name, and the table as it was before
+    Alter(String, T),
+}
+
+struct Savepoint {
+    name: String,
+    /// Number of changes made before the savepoint was started
+    num_changes: usize,
+    /// Whether the savepoint was started outside of a transaction, making
+    /// its release commit the transaction
+    starts_transaction: bool,
+}
+
+pub struct Executor<T: Table, J: Journal> {
+    tables: HashMap<String, T>,
+    journal: J,
+    changes: Vec<Change<T>>,
+    savepoints: Vec<Savepoint>,
+    in_transaction: bool,
+    /// Whether FOREIGN KEY constraints are enforced, which they are not
+    /// until turned on with `PRAGMA foreign_keys`
+    foreign_keys: bool,
+    sort_memory_limit: usize,
+}
+
+impl<T: Table, J: Journal> Executor<T, J> {
+    pub fn new(journal: J) -> Executor<T, J> {
+        return Executor {
+            tables: HashMap::new(),
+            journal,
+            changes: vec![],
+            savepoints: vec![],
+            in_transaction: false,
+            foreign_keys: false,
+            sort_memory_limit: SORT_MEMORY_LIMIT,
+        };
+    }
+
+    /// Takes over the catalog of the database and the tables it lists,
+    /// keeping the catalog up to date with the tables from then on.
+    pub fn open(&mut self, catalog: T, tables: Vec<T>) {
+        for table in tables {
+            self.tables.insert(table.name(), table);
+        }
+        self.tables.insert(catalog.name(), catalog);
+    }
+
+    pub fn add_table(&mut self, table: T) -> Result<(), String> {
+        let table_name = table.name();
+        if table_name.starts_with("sqlite_") {
+            return Err(format!(
+                "object name reserved for internal use: {}",
+                table_name
+            ));
+        }
+        if self.table_exists(&table_name) {
+            return Err(format!("table {} already exists", &table_name).to_string());
+        }
+        self.tables.insert(table_name.to_string(), table);
+        self.changes.push(Change::Create(table_name.clone()));
+        self.update_schema(&table_name, &table_name)
+    }
+
+    /// Drops the table. With foreign keys enforced its rows are deleted
+    /// first, which the rows of other tables referring to them act on.
+    pub fn drop_table(&mut self, table_name: &str, if_exists: bool) -> Result<(), String> {
+        check_not_catalog(table_name, "dropped")?;
+        match self.table_exists(table_name) {
+            false if if_exists => Ok(()),
+            false => Err(format!("no such table: {}", table_name)),
+            true => self.atomically(|executor| executor.drop_table_atomically(table_name)),
+        }
+    }
+
+    fn drop_table_atomically(&mut self, table_name: &str) -> Result<(), String> {
+        if self.foreign_keys {
+            let rows = self.tables[table_name]
+                .select_rows()?
+                .collect::<Result<_, _>>()?;
+            let mut checks = vec![];
+            self.delete_rows(table_name, rows, &mut checks)?;
+            self.check_references(checks)?;
+        }
+        let mut table = self.tables.remove(table_name).unwrap();
+        let result = table.drop_rows();
+        self.changes.push(Change::Drop(table));
+        result?;
+        self.update_schema(table_name, table_name)
+    }
+
+    /// Starts a transaction, holding back the changes of the following
+    /// statements until `commit`.
+    pub fn begin(&mut self) -> Result<(), String> {
+        if self.in_transaction {
+            return Err("cannot start a transaction within a transaction".to_string());
+        }
+        self.in_transaction = true;
+        Ok(())
+    }
+
+    pub fn commit(&mut self) -> Result<(), String> {
+        if !self.in_transaction {
+            return Err("cannot commit - no transaction is active".to_string());
+        }
+        self.journal.commit()?;
+        self.changes.clear();
+        self.savepoints.clear();
+        self.in_transaction = false;
+        Ok(())
+    }
+
+    /// Undoes every change made since `begin`.
+    pub fn rollback(&mut self) -> Result<(), String> {
+        if !self.in_transaction {
+            return Err("cannot rollback - no transaction is active".to_string());
+        }
+        self.in_transaction = false;
+        self.savepoints.clear();
+        self.undo()
+    }
+
+    /// Starts a savepoint that can be rolled back to without ending the
+    /// transaction. Outside of a transaction this also starts one.
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.push(Savepoint {
+            name: name.to_string(),
+            num_changes: self.changes.len(),
+            starts_transaction: !self.in_transaction,
+        });
+        self.in_transaction = true;
+        self.journal.savepoint();
+    }
+
+    /// Ends the most recent savepoint called `name` and the ones started
+    /// after it, keeping their changes.
+    pub fn release(&mut self, name: &str) -> Result<(), String> {
+        let depth = self.savepoint_depth(name)?;
+        self.journal.release(depth)?;
+        if self.savepoints[depth].starts_transaction {
+            return self.commit();
+        }
+        self.savepoints.truncate(depth);
+        Ok(())
+    }
+
+    /// Undoes every change made since the most recent savepoint called
+    /// `name`, which stays open while the ones started after it end.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), String> {
+        let depth = self.savepoint_depth(name)?;
+        self.journal.rollback_to(depth)?;
+        self.savepoints.truncate(depth + 1);
+        self.undo_changes(self.savepoints[depth].num_changes);
+        Ok(())
+    }
+
+    fn savepoint_depth(&self, name: &str) -> Result<usize, String> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint.name.eq_ignore_ascii_case(name))
+            .ok_or(format!("no such savepoint: {}", name))
+    }
+
+    /// Has to be called after every statement. Outside of a transaction the
+    /// changes of the statement are committed, or undone if it failed.
+    pub fn end_statement(&mut self, succeeded: bool) -> Result<(), String> {
+        if self.in_transaction {
+            return Ok(());
+        }
+        if !succeeded {
+            return self.undo();
+        }
+        match self.journal.commit() {
+            Ok(()) => {
+                self.changes.clear();
+                Ok(())
+            }
+            Err(err) => {
+                self.undo()?;
+                Err(err)
+            }
+        }
+    }
+
+    fn undo(&mut self) -> Result<(), String> {
+        self.undo_changes(0);
+        self.journal.rollback()
+    }
+
+    /// Undoes the changes to the tables until only `num_changes` are left.
+    fn undo_changes(&mut self, num_changes: usize) {
+        while self.changes.len() > num_changes {
+            match self.changes.pop().unwrap() {
+                Change::Create(table_name) => {
+                    self.tables.remove(&table_name);
+                }
+                Change::Drop(table) => {
+                    self.tables.insert(table.name(), table);
+                }
+                Change::Alter(table_name, table) => {
+                    self.tables.remove(&table_name);
+                    self.tables.insert(table.name(), table);
+                }
+            }
+        }
+    }
+
+    /// Sets the setting called `name` to the value, or reads it without
+    /// one. Only `foreign_keys` is known, and it cannot be changed within a
+    /// transaction. Like in SQLite, nothing happens in the other cases.
+    pub fn pragma(&mut self, name: &str, value: Option<Value>) -> Result<Option<Value>, String> {
+        if !name.eq_ignore_ascii_case("foreign_keys") {
+            return Ok(None);
+        }
+        match value {
+            None => Ok(Some(Value::Integer(self.foreign_keys as i64))),
+            Some(_) if self.in_transaction => Ok(None),
+            Some(value) => {
+                self.foreign_keys = is_on(&value);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Runs the statement so that it leaves no change behind if it fails.
+    /// Outside of a transaction `end_statement` sees to it, but within one
+    /// the statement gets a savepoint of its own, as it may fail after
+    /// changing some of the rows.
+    fn atomically<F>(&mut self, statement: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Self) -> Result<(), String>,
+    {
+        if !self.in_transaction {
+            return statement(self);
+        }
+        let depth = self.savepoints.len();
+        let num_changes = self.changes.len();
+        self.journal.savepoint();
+        let result = statement(self);
+        if result.is_err() {
+            self.journal.rollback_to(depth)?;
+            self.undo_changes(num_changes);
+        }
+        self.journal.release(depth)?;
+        result
+    }
+
+    pub fn insert<I: Insertion>(&mut self, insertion: I) -> Result<(), String> {
+        let table_name = insertion.table_name();
+        check_not_catalog(table_name, "modified")?;
+        if !self.table_exists(table_name) {
+            return Err(format!("no such table: {}", table_name));
+        }
+        let result = insertion.validate();
+        if result.is_err() {
+            return result;
+        }
+        self.atomically(|executor| executor.insert_row(&insertion))
+    }
+
+    fn insert_row<I: Insertion>(&mut self, insertion: &I) -> Result<(), String> {
+        let table_name = insertion.table_name();
+        let table = self.tables.get_mut(table_name).unwrap();
+        let values = insertion.values();
+
+        let row = match insertion.column_names() {
+            None => table.insert_row(values.collect())?,
+            Some(column_names) => {
+                let mut row = HashMap::new();
+                for kv in column_names.zip(values) {
+                    let (column_name, value) = kv;
+                    row.insert(column_name.clone(), value.clone());
+                }
+                table.insert_row_with_named_columns(row)?
+            }
+        };
+        if !self.foreign_keys {
+            return Ok(());
+        }
+        let checks = foreign_key::references(&self.tables, &self.tables[table_name])?
+            .into_iter()
+            .filter_map(|reference| {
+                let values = reference.child_values(&row)?;
+                Some((reference, values))
+            })
+            .collect();
+        self.check_references(checks)
+    }
+
+    pub fn select<S: Selection>(&self, selection: S) -> Result<Rows, String> {
+        let from = selection.from();
+        let table = self.table(&from.table.name)?;
+        let joined_tables = from
+            .joins
+            .iter()
+            .map(|join| Ok((join, self.table(&join.table.name)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let is_plain = from.joins.is_empty()
+            && selection.where_clause().is_none()
+            && selection.group_by().is_empty()
+            && selection.having().is_none()
+            && selection.order_by().is_empty()
+            && selection.limit().is_none();
+        let column_names = match selection.columns() {
+            ColumnSet::WildCard if is_plain && rowid_index(table).is_none() => {
+                return table.select_rows()
+            }
+            ColumnSet::WildCard => None,
+            ColumnSet::Expressions(expressions) => expressions
+                .into_iter()
+                .map(|expression| match expression {
+                    Expression::Column(name) => Some(name),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+        };
+        if let (true, Some(column_names)) = (is_plain, &column_names) {
+            return table.select_rows_with_named_columns(column_names);
+        }
+
+        let mut columns = HashMap::new();
+        let mut affinities = vec![];
+        let mut all_columns = vec![];
+        let mut row_len = 0;
+        add_columns(
+            &mut columns,
+            &mut affinities,
+            &mut all_columns,
+            &mut row_len,
+            &from.table,
+            table,
+        );
+        let num_table_columns = row_len;
+        let mut joins = vec![];
+        for (join, joined_table) in joined_tables {
+            let outer_columns = columns.clone();
+            let offset = row_len;
+            add_columns(
+                &mut columns,
+                &mut affinities,
+                &mut all_columns,
+                &mut row_len,
+                &join.table,
+                joined_table,
+            );
+            if let Some(constraint) = &join.constraint {
+                expression::validate(constraint, &columns)?;
+            }
+            // Unlike the ON constraint of a LEFT JOIN, the WHERE clause can
+            // only drop the rows of an inner join that do not match
+            let is_left = join.operator == JoinOperator::Left;
+            let conditions = match is_left {
+                true => join.constraint.iter().collect::<Vec<_>>(),
+                false => join
+                    .constraint
+                    .iter()
+                    .chain(selection.where_clause())
+                    .collect(),
+            };
+            // Rows can only be looked up by a key of a single column
+            let key = match joined_table.key_indices()[..] {
+                [key_index] => join::key_expression(
+                    &conditions,
+                    offset + key_index,
+                    &columns,
+                    &affinities,
+                    &outer_columns,
+                ),
+                _ => None,
+            };
+            joins.push(join::Join {
+                table: joined_table,
+                is_left,
+                constraint: join.constraint.clone(),
+                key,
+                columns: columns.clone(),
+                affinities: affinities.clone(),
+            });
+        }
+        let result_columns = match selection.columns() {
+            ColumnSet::WildCard => all_columns,
+            ColumnSet::Expressions(expressions) => expressions,
+        };
+        let where_clause = selection.where_clause().cloned();
+        if let Some(where_clause) = &where_clause {
+            expression::validate(where_clause, &columns)?;
+        }
+        let group_by = selection
+            .group_by()
+            .iter()
+            .enumerate()
+            .map(|(i, expression)| resolve_position(expression, i, "GROUP BY", &result_columns))
+            .collect::<Result<Vec<_>, String>>()?;
+        for expression in &group_by {
+            expression::validate(expression, &columns)?;
+        }
+
+        // The results of aggregate calls are computed while grouping the
+        // rows, after which they are read from columns added to the groups
+        let mut calls = vec![];
+        let result_columns = result_columns
+            .iter()
+            .map(|expression| aggregate::extract_calls(expression, &mut calls))
+            .collect::<Vec<_>>();
+        let having = selection
+            .having()
+            .map(|expression| aggregate::extract_calls(expression, &mut calls));
+        let mut order_by = resolve_order_by(selection.order_by(), &result_columns)?;
+        for term in &mut order_by {
+            term.expression = aggregate::extract_calls(&term.expression, &mut calls);
+        }
+        let is_aggregate = !group_by.is_empty() || having.is_some() || !calls.is_empty();
+        let mut group_columns = columns.clone();
+        for (i, (name, arguments)) in calls.iter().enumerate() {
+            Accumulator::new(name, arguments.len())?;
+            for argument in arguments {
+                expression::validate(argument, &columns)?;
+            }
+            group_columns.insert(aggregate::result_column(i), row_len + i);
+        }
+        let expressions = result_columns
+            .iter()
+            .chain(&having)
+            .chain(order_by.iter().map(|term| &term.expression));
+        for expression in expressions {
+            expression::validate(expression, &group_columns)?;
+        }
+        let limit = constant(selection.limit())?;
+        let offset = constant(selection.offset())?.unwrap_or(0);
+
+        let (mut rows, is_in_key_order) = scan(
+            table,
+            where_clause.as_ref(),
+            &columns,
+            &affinities,
+            num_table_columns,
+        )?;
+        if !joins.is_empty() {
+            let mut joined_rows = rows.collect::<Result<_, _>>()?;
+            for join in &joins {
+                joined_rows = join::join(joined_rows, join)?;
+            }
+            rows = Box::new(joined_rows.into_iter().map(Ok));
+        }
+        if let Some(where_clause) = where_clause {
+            let columns = columns.clone();
+            let affinities = affinities.clone();
+            rows = filter_rows(rows, move |row| {
+                let value = expression::evaluate(&where_clause, &columns, &affinities, row);
+                expression::is_true(&value)
+            });
+        }
+        if is_aggregate {
+            let groups = aggregate::group(rows, row_len, &group_by, &calls, &columns, &affinities)?;
+            rows = Box::new(groups.into_iter().map(Ok));
+        }
+        let columns = group_columns;
+        // The results of the calls, which come after the values of the rows,
+        // have no affinity
+        if let Some(having) = having {
+            let columns = columns.clone();
+            let affinities = affinities.clone();
+            rows = filter_rows(rows, move |row| {
+                expression::is_true(&expression::evaluate(&having, &columns, &affinities, row))
+            });
+        }
+        // The rows of the first table come out of the tree in key order
+        // already unless they are read through an index, and joins keep
+        // that order, while groups come out in the order of their GROUP BY
+        // values. Sorting can be skipped if the ORDER BY terms are a prefix
+        // of the key columns, or start with all of them when there are no
+        // joins to repeat the keys.
+        let key_indices = table.key_indices();
+        let num_key_terms = order_by
+            .iter()
+            .zip(&key_indices)
+            .take_while(|(term, key_index)| {
+                let is_key_column = match &term.expression {
+                    Expression::Column(name) => columns.get(name) == Some(key_index),
+                    _ => false,
+                };
+                is_key_column && !term.descending && term.nulls_first
+            })
+            .count();
+        let is_in_order = order_by.is_empty()
+            || (is_in_key_order
+                && !is_aggregate
+                && (num_key_terms == order_by.len()
+                    || (num_key_terms == key_indices.len() && joins.is_empty())));
+        if !is_in_order {
+            let sort_keys = order_by
+                .iter()
+                .map(|term| SortKey {
+                    descending: term.descending,
+                    nulls_first: term.nulls_first,
+                })
+                .collect();
+            let columns = columns.clone();
+            let affinities = affinities.clone();
+            let records = rows.map(move |row| {
+                let row = row?;
+                let keys = order_by
+                    .iter()
+                    .map(|term| expression::evaluate(&term.expression, &columns, &affinities, &row))
+                    .collect();
+                Ok((keys, row))
+            });
+            rows = Sorter::new(sort_keys, self.sort_memory_limit)
+                .sort(records, || pager::temporary(TEMPORARY_PAGE_SIZE))?;
+        }
+        // Negative offsets count as none and negative limits as no limit
+        let rows = rows.skip(offset.max(0) as usize);
+        let rows: Rows = match limit {
+            Some(limit) if limit >= 0 => Box::new(rows.take(limit as usize)),
+            _ => Box::new(rows),
+        };
+        Ok(Box::new(rows.map(move |row| {
+            let row = row?;
+            Ok(result_columns
+                .iter()
+                .map(|expression| expression::evaluate(expression, &columns, &affinities, &row))
+                .collect())
+        })))
+    }
+
+    /// Sets the columns of the rows matching the WHERE clause to the values
+    /// of their expressions, which are evaluated against the row as it was
+    /// before the update.
+    pub fn update<U: Update>(&mut self, update: U) -> Result<(), String> {
+        let table_name = update.table_name();
+        check_not_catalog(table_name, "modified")?;
+        if !self.table_exists(table_name) {
+            return Err(format!("no such table: {}", table_name));
+        }
+        let table = &self.tables[table_name];
+        let columns = column_indices(table);
+        let affinities = column_affinities(table);
+        for (column_name, value) in update.assignments() {
+            if !columns.contains_key(column_name) {
+                return Err(format!("no such column: {}", column_name));
+            }
+            expression::validate(value, &columns)?;
+        }
+
+        let rows = matching_rows(table, update.where_clause(), &columns)?
+            .into_iter()
+            .map(|row| {
+                let mut updated_row = row.clone();
+                for (column_name, value) in update.assignments() {
+                    updated_row[columns[column_name]] =
+                        expression::evaluate(value, &columns, &affinities, &row);
+                }
+                (row, updated_row)
+            })
+            .collect();
+        self.atomically(|executor| {
+            let mut checks = vec![];
+            executor.update_rows(table_name, rows, &mut checks)?;
+            executor.check_references(checks)
+        })
+    }
+
+    /// Removes the rows matching the WHERE clause, or every row without one.
+    pub fn delete<D: Deletion>(&mut self, deletion: D) -> Result<(), String> {
+        let table_name = deletion.table_name();
+        check_not_catalog(table_name, "modified")?;
+        if !self.table_exists(table_name) {
+            return Err(format!("no such table: {}", table_name));
+        }
+        let table = &self.tables[table_name];
+        let columns = column_indices(table);
+        let rows = matching_rows(table, deletion.where_clause(), &columns)?;
+        self.atomically(|executor| {
+            let mut checks = vec![];
+            executor.delete_rows(table_name, rows, &mut checks)?;
+            executor.check_references(checks)
+        })
+    }
+
+    /// Updates the rows of the table. With foreign keys enforced, the rows
+    /// referring to them act on the changes to their keys, and the new
+    /// values of the foreign keys of the table are to be checked.
+    fn update_rows(
+        &mut self,
+        table_name: &str,
+        rows: Vec<(Vec<Value>, Vec<Value>)>,
+        checks: &mut Vec<Check>,
+    ) -> Result<(), String> {
+        let old_rows = rows.iter().map(|(row, _)| row.clone()).collect::<Vec<_>>();
+        let table = self.tables.get_mut(table_name).unwrap();
+        let updated_rows = table.update_rows(rows)?;
+        if !self.foreign_keys {
+            return Ok(());
+        }
+        let references = foreign_key::references(&self.tables, &self.tables[table_name])?;
+        let referring = foreign_key::referring(&self.tables, table_name)?;
+        for (row, updated_row) in old_rows.iter().zip(&updated_rows) {
+            for reference in &references {
+                if let Some(values) = reference.child_values(updated_row) {
+                    if reference.child_values(row).as_ref() != Some(&values) {
+                        checks.push((reference.clone(), values));
+                    }
+                }
+            }
+            for reference in &referring {
+                let new_values = reference
+                    .parent_columns
+                    .iter()
+                    .map(|i| updated_row[*i].clone());
+                let new_values = new_values.collect::<Vec<_>>();
+                match reference.parent_values(row) {
+                    Some(values) if values != new_values => {
+                        self.act_on_referring_rows(reference, values, Some(new_values), checks)?
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the rows of the table. With foreign keys enforced, the rows
+    /// referring to them act on their deletion.
+    fn delete_rows(
+        &mut self,
+        table_name: &str,
+        rows: Vec<Vec<Value>>,
+        checks: &mut Vec<Check>,
+    ) -> Result<(), String> {
+        let table = self.tables.get_mut(table_name).unwrap();
+        if !self.foreign_keys {
+            return table.delete_rows(rows);
+        }
+        table.delete_rows(rows.clone())?;
+        for reference in foreign_key::referring(&self.tables, table_name)? {
+            for row in &rows {
+                if let Some(values) = reference.parent_values(row) {
+                    self.act_on_referring_rows(&reference, values, None, checks)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the action of the reference on the rows of the child table
+    /// that refer to the values, which the parent table has just had a row
+    /// with deleted or, if there are new values, updated to them.
+    fn act_on_referring_rows(
+        &mut self,
+        reference: &Reference,
+        values: Vec<Value>,
+        new_values: Option<Vec<Value>>,
+        checks: &mut Vec<Check>,
+    ) -> Result<(), String> {
+        let action = match new_values {
+            None => reference.on_delete,
+            Some(_) => reference.on_update,
+        };
+        if action == ForeignKeyAction::NoAction {
+            checks.push((reference.clone(), values));
+            return Ok(());
+        }
+        let child = &self.tables[&reference.child];
+        let rows = foreign_key::rows_with(child, &reference.columns, &values)?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let new_values = match (action, new_values) {
+            (ForeignKeyAction::Restrict, _) => {
+                return Err("FOREIGN KEY constraint failed".to_string())
+            }
+            (ForeignKeyAction::Cascade, None) => {
+                return self.delete_rows(&reference.child, rows, checks)
+            }
+            (ForeignKeyAction::Cascade, Some(new_values)) => new_values,
+            _ => vec![Value::Null; values.len()],
+        };
+        let rows = rows
+            .into_iter()
+            .map(|row| {
+                let mut updated_row = row.clone();
+                for (i, value) in reference.columns.iter().zip(&new_values) {
+                    updated_row[*i] = value.clone();
+                }
+                (row, updated_row)
+            })
+            .collect();
+        self.update_rows(&reference.child, rows, checks)
+    }
+
+    /// Fails if the child table of a check has rows with its values while
+    /// the parent table has none.
+    fn check_references(&self, checks: Vec<Check>) -> Result<(), String> {
+        for (reference, values) in checks {
+            let parent = &self.tables[&reference.parent];
+            if !foreign_key::rows_with(parent, &reference.parent_columns, &values)?.is_empty() {
+                continue;
+            }
+            let child = &self.tables[&reference.child];
+            if !foreign_key::rows_with(child, &reference.columns, &values)?.is_empty() {
+                return Err("FOREIGN KEY constraint failed".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings the rows of the catalog about the table, which was called
+    /// `old_name` before the statement, and about its indexes up to date.
+    /// There is nothing to do for an executor without a catalog.
+    fn update_schema(&mut self, old_name: &str, table_name: &str) -> Result<(), String> {
+        if !self.table_exists(SCHEMA_TABLE) {
+            return Ok(());
+        }
+        let mut entries = match self.tables.get(table_name) {
+            None => vec![],
+            Some(table) => schema_rows(table),
+        };
+        let catalog = self.tables.get_mut(SCHEMA_TABLE).unwrap();
+        let old_name = Value::Text(old_name.to_string());
+        let rows = filter_rows(catalog.select_rows()?, move |row| row[2] == old_name)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut updates = vec![];
+        let mut deletions = vec![];
+        for row in rows {
+            // Tables and indexes keep their root page when renamed
+            let position = entries.iter().position(|entry| entry[3] == row[3]);
+            match position {
+                None => deletions.push(row),
+                Some(i) => {
+                    let mut entry = entries.remove(i);
+                    if entry[..] != row[..entry.len()] {
+                        entry.extend_from_slice(&row[entry.len()..]);
+                        updates.push((row, entry));
+                    }
+                }
+            }
+        }
+        catalog.delete_rows(deletions)?;
+        catalog.update_rows(updates)?;
+        for entry in entries {
+            catalog.insert_row(entry)?;
+        }
+        Ok(())
+    }
+
+    fn table_exists(&self, table_name: &str) -> bool {
+        return self.tables.get(table_name).is_some();
+    }
+
+    fn table(&self, table_name: &str) -> Result<&T, String> {
+        self.tables
+            .get(table_name)
+            .ok_or(format!("no such table: {}", table_name))
+    }
+}
+
+/// Rows of the catalog describing the table and its indexes.
+fn schema_rows<T: Table>(table: &T) -> Vec<Vec<Value>> {
+    let text = |text: &str| Value::Text(text.to_string());
+    let name = table.name();
+    let mut rows = vec![vec![
+        text("table"),
+        text(&name),
+        text(&name),
+        Value::Integer(table.root_page() as i64),
+        Value::Text(table.schema().to_string()),
+    ]];
+    for (index, root_page) in table.indexes() {
+        let sql = match index.name.starts_with("sqlite_autoindex_") {
+            true => Value::Null,
+            false => Value::Text(index.to_string()),
+        };
+        rows.push(vec![
+            text("index"),
+            text(&index.name),
+            text(&name),
+            Value::Integer(root_page as i64),
+            sql,
+        ]);
+    }
+    rows
+}
+
+/// Fails if the table is the catalog, which only changes along with the
+/// tables it lists.
+fn check_not_catalog(table_name: &str, change: &str) -> Result<(), String> {
+    match table_name == SCHEMA_TABLE {
+        true => Err(format!("table {} may not be {}", table_name, change)),
+        false => Ok(()),
+    }
+}
+
+/// Index of each column of the table in its rows, along with the index of
+/// the rowid under each of its names that no column has.
+fn column_indices<T: Table>(table: &T) -> HashMap<String, usize> {
+    let mut columns = table
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(i, column)| (column.name().clone(), i))
+        .collect::<HashMap<_, _>>();
+    if let Some(rowid_index) = rowid_index(table) {
+        for name in &ROWID_NAMES {
+            columns.entry(name.to_string()).or_insert(rowid_index);
+        }
+    }
+    columns
+}
+
+/// Affinity of each value in the rows of the table, which is that of the
+/// column it is in, or INTEGER for the rowid.
+fn column_affinities<T: Table>(table: &T) -> Vec<Affinity> {
+    let mut affinities = table
+        .columns()
+        .iter()
+        .map(|column| column.affinity())
+        .collect::<Vec<_>>();
+    affinities.resize(row_len(table), Affinity::Integer);
+    affinities
+}
+
+/// Whether the value of a setting turns it on, like a nonzero number or
+/// `ON`, `YES` and `TRUE` do.
+fn is_on(value: &Value) -> bool {
+    match value {
+        Value::Integer(i) => *i != 0,
+        Value::Real(r) => *r != 0.0,
+        Value::Text(text) => ["on", "yes", "true"].contains(&text.to_lowercase().as_str()),
+        _ => false,
+    }
+}
+
+/// Index of the rowid in the rows of the table if it has no PRIMARY KEY.
+fn rowid_index<T: Table>(table: &T) -> Option<usize> {
+    let num_columns = table.columns().len();
+    Some(num_columns).filter(|num_columns| table.key_indices() == [*num_columns])
+}
+
+/// Number of values in the rows of the table, the rowid included.
+fn row_len<T: Table>(table: &T) -> usize {
+    rowid_index(table).map_or(table.columns().len(), |rowid_index| rowid_index + 1)
+}
+
+impl<T: Table + Clone, J: Journal> Executor<T, J> {
+    /// Builds an index of the table, which is left as it was if that fails.
+    pub fn create_index(
+        &mut self,
+        index: ast::IndexSchema,
+        if_not_exists: bool,
+    ) -> Result<(), String> {
+        let index_name = index.name.to_lowercase();
+        if index_name.starts_with("sqlite_") {
+            return Err(format!(
+                "object name reserved for internal use: {}",
+                index.name
+            ));
+        }
+        check_not_catalog(&index.table_name, "indexed")?;
+        let exists = self
+            .tables
+            .values()
+            .any(|table| table.index_names().contains(&index_name));
+        if exists && if_not_exists {
+            return Ok(());
+        }
+        if exists {
+            return Err(format!("index {} already exists", index.name));
+        }
+        if self.table_exists(&index_name) {
+            return Err(format!("there is already a table named {}", index.name));
+        }
+        if !self.table_exists(&index.table_name) {
+            return Err(format!("no such table: {}", index.table_name));
+        }
+        self.atomically(|executor| executor.create_index_atomically(index))
+    }
+
+    fn create_index_atomically(&mut self, index: ast::IndexSchema) -> Result<(), String> {
+        let table = self.tables.get_mut(&index.table_name).unwrap();
+        let previous = table.clone();
+        table.create_index(index)?;
+        let table_name = previous.name();
+        self.changes
+            .push(Change::Alter(table_name.clone(), previous));
+        self.update_schema(&table_name, &table_name)
+    }
+
+    /// Renames the table or adds or drops one of its columns. The table is
+    /// left as it was if that fails.
+    pub fn alter_table(&mut self, table_name: &str, alteration: Alteration) -> Result<(), String> {
+        check_not_catalog(table_name, "altered")?;
+        if !self.table_exists(table_name) {
+            return Err(format!("no such table: {}", table_name));
+        }
+        if let Alteration::AddColumn(column) = &alteration {
+            let has_default = !matches!(
+                column.default,
+                None | Some(Expression::Literal(Value::Null))
+            );
+            if self.foreign_keys && column.references.is_some() && has_default {
+                return Err(
+                    "Cannot add a REFERENCES column with non-NULL default value".to_string()
+                );
+            }
+        }
+        if let Alteration::RenameTo(new_name) = &alteration {
+            if self.table_exists(&new_name.to_lowercase()) {
+                return Err(format!(
+                    "there is already another table or index with this name: {}",
+                    new_name
+                ));
+            }
+        }
+        self.atomically(|executor| executor.alter_table_atomically(table_name, alteration))
+    }
+
+    fn alter_table_atomically(
+        &mut self,
+        table_name: &str,
+        alteration: Alteration,
+    ) -> Result<(), String> {
+        let mut table = self.tables.remove(table_name).unwrap();
+        let previous = table.clone();
+        let result = match alteration {
+            Alteration::RenameTo(new_name) => {
+                table.rename(&new_name);
+                Ok(())
+            }
+            Alteration::AddColumn(column) => table.add_column(column),
+            Alteration::DropColumn(column_name) => table.drop_column(&column_name),
+        };
+        if result.is_err() {
+            self.tables.insert(previous.name(), previous);
+            return result;
+        }
+        let old_name = previous.name();
+        let new_name = table.name();
+        self.changes.push(Change::Alter(new_name.clone(), previous));
+        self.tables.insert(new_name.clone(), table);
+        self.update_schema(table_name, &new_name)?;
+        if new_name == old_name {
+            return Ok(());
+        }
+        self.rename_parent(&old_name, &new_name)
+    }
+
+    /// Makes the foreign keys of the tables that refer to the table called
+    /// `old_name` refer to it by its new name.
+    fn rename_parent(&mut self, old_name: &str, new_name: &str) -> Result<(), String> {
+        let children = self
+            .tables
+            .values()
+            .filter(|table| {
+                table
+                    .foreign_keys()
+                    .iter()
+                    .any(|foreign_key| foreign_key.parent_table == old_name)
+            })
+            .map(|table| table.name())
+            .collect::<Vec<_>>();
+        for child in children {
+            let table = self.tables.get_mut(&child).unwrap();
+            let previous = table.clone();
+            table.rename_parent(old_name, new_name);
+            self.changes.push(Change::Alter(child.clone(), previous));
+            self.update_schema(&child, &child)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the columns of the table to those of the tables before it in the
+/// FROM clause, whose rows take up the first `row_len` values of the joined
+/// rows, both qualified by the name of the table and not. The names of
+/// columns that several tables have are ambiguous unless qualified. The
+/// rowid is left out of `all_columns`, which `*` expands to, but not out of
+/// the `affinities` of the values of the rows.
+fn add_columns<T: Table>(
+    columns: &mut HashMap<String, usize>,
+    affinities: &mut Vec<Affinity>,
+    all_columns: &mut Vec<Expression>,
+    row_len: &mut usize,
+    table_reference: &TableReference,
+    table: &T,
+) {
+    let correlation_name = table_reference.correlation_name();
+    for (name, index) in column_indices(table) {
+        let index = *row_len + index;
+        columns.insert(format!("{}.{}", correlation_name, name), index);
+        columns
+            .entry(name)
+            .and_modify(|index| *index = expression::AMBIGUOUS)
+            .or_insert(index);
+    }
+    for column in table.columns() {
+        let qualified_name = format!("{}.{}", correlation_name, column.name());
+        all_columns.push(Expression::Column(qualified_name));
+    }
+    affinities.extend(column_affinities(table));
+    *row_len += self::row_len(table);
+}
+
+/// Replaces the ORDER BY terms that are integers by the result columns
+/// they are the position of, counting from 1.
+fn resolve_order_by(
+    order_by: &[OrderingTerm],
+    result_columns: &[Expression],
+) -> Result<Vec<OrderingTerm>, String> {
+    let mut resolved = vec![];
+    for (i, term) in order_by.iter().enumerate() {
+        let mut term = term.clone();
+        term.expression = resolve_position(&term.expression, i, "ORDER BY", result_columns)?;
+        resolved.push(term);
+    }
+    Ok(resolved)
+}
+
+/// The result column the `i`th term of the clause is the position of if it
+/// is an integer, or else the term itself.
+fn resolve_position(
+    term: &Expression,
+    i: usize,
+    clause: &str,
+    result_columns: &[Expression],
+) -> Result<Expression, String> {
+    let position = match term {
+        Expression::Literal(Value::Integer(position)) => *position,
+        term => return Ok(term.clone()),
+    };
+    if position < 1 || position as usize > result_columns.len() {
+        return Err(format!(
+            "{} {} term out of range - should be between 1 and {}",
+            ordinal(i + 1),
+            clause,
+            result_columns.len()
+        ));
+    }
+    Ok(result_columns[position as usize - 1].clone())
+}
+
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// Value of a LIMIT or OFFSET expression, which cannot refer to columns.
+fn constant(expression: Option<&Expression>) -> Result<Option<i64>, String> {
+    let expression = match expression {
+        None => return Ok(None),
+        Some(expression) => expression,
+    };
+    let no_columns = HashMap::new();
+    expression::validate(expression, &no_columns)?;
+    match expression::evaluate(expression, &no_columns, &[], &[]) {
+        Value::Integer(i) => Ok(Some(i)),
+        _ => Err("datatype mismatch".to_string()),
+    }
+}
+
+/// Rows read as they are iterated over, ending with the error of one that
+/// cannot be read.
+pub type Rows = Box<dyn Iterator<Item = Result<Vec<Value>, String>>>;
+
+/// Rows of the table, which are read through one of its indexes if the
+/// WHERE clause restricts an indexed column among the first `num_columns`
+/// to a range. Returns whether the rows come in key order, which they only
+/// do without an index.
+fn scan<T: Table>(
+    table: &T,
+    where_clause: Option<&Expression>,
+    columns: &HashMap<String, usize>,
+    affinities: &[Affinity],
+    num_columns: usize,
+) -> Result<(Rows, bool), String> {
+    if let Some(where_clause) = where_clause {
+        let ranges = expression::column_ranges(where_clause, columns, affinities, num_columns);
+        for (column, range) in ranges {
+            if let Some(rows) = table.select_rows_in_range(column, range)? {
+                return Ok((rows, false));
+            }
+        }
+    }
+    Ok((table.select_rows()?, true))
+}
+
+/// Rows of the table for which the WHERE clause holds, read in full before
+/// the caller starts changing the table.
+fn matching_rows<T: Table>(
+    table: &T,
+    where_clause: Option<&Expression>,
+    columns: &HashMap<String, usize>,
+) -> Result<Vec<Vec<Value>>, String> {
+    let where_clause = match where_clause {
+        None => return table.select_rows()?.collect(),
+        Some(where_clause) => where_clause,
+    };
+    expression::validate(where_clause, columns)?;
+    let affinities = column_affinities(table);
+    let (rows, _) = scan(
+        table,
+        Some(where_clause),
+        columns,
+        &affinities,
+        columns.len(),
+    )?;
+    rows.filter(|row| match row {
+        Ok(row) => {
+            let value = expression::evaluate(where_clause, columns, &affinities, row);
+            expression::is_true(&value)
+        }
+        Err(_) => true,
+    })
+    .collect()
+}
+
+/// Rows for which the predicate holds, keeping the error ending the rows.
+fn filter_rows(rows: Rows, predicate: impl Fn(&[Value]) -> bool + 'static) -> Rows {
+    Box::new(rows.filter(move |row| row.as_ref().map_or(true, |row| predicate(row))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlite3;
+    use mockers::Scenario;
+    use std::collections::HashMap;
+
+    struct NamedColumn(String);
+
+    impl Column for NamedColumn {
+        fn name(&self) -> &String {
+            &self.0
+        }
+
+        fn affinity(&self) -> Affinity {
+            Affinity::Blob
+        }
+    }
+
+    fn columns(names: &[&str]) -> Vec<Box<dyn Column>> {
+        names
+            .iter()
+            .map(|name| Box::new(NamedColumn(name.to_string())) as Box<dyn Column>)
+            .collect()
+    }
+
+    #[test]
+    fn should_fail_to_create_a_table_if_one_with_same_name_already_exists() {
+        let scenario = Scenario::new();
+        let table_name = "apples".to_string();
+        let (table, _) = scenario.create_mock_for::<dyn Table>();
+        let (table2, table2_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(table2_handle.name().and_return("apples".to_string()));
+        let mut tables: HashMap<String, TableMock> = HashMap::new();
+        tables.insert(table_name, table);
+
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::new(journal);
+        executor.tables = tables;
+        let result = executor.add_table(table2);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn should_fail_to_insert_row_if_table_does_not_exist() {
+        let scenario = Scenario::new();
+        let table_name = "oranges".to_string();
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::<TableMock, JournalMock>::new(journal);
+
+        let result = executor.insert(ast::Insertion::new(&table_name, None, vec![]));
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn statements_outside_of_a_transaction_are_committed() {
+        let scenario = Scenario::new();
+        let (journal, journal_handle) = scenario.create_mock_for::<dyn Journal>();
+        scenario.expect(journal_handle.commit().and_return(Ok(())));
+        let mut executor = Executor::<TableMock, JournalMock>::new(journal);
+
+        assert_eq!(executor.end_statement(true), Ok(()));
+    }
+
+    #[test]
+    fn rolling_back_a_transaction_drops_the_tables_created_in_it() {
+        let scenario = Scenario::new();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(table_handle.name().and_return("apples".to_string()));
+        let (journal, journal_handle) = scenario.create_mock_for::<dyn Journal>();
+        scenario.expect(journal_handle.rollback().and_return(Ok(())));
+        let mut executor = Executor::new(journal);
+
+        executor.begin().unwrap();
+        executor.add_table(table).unwrap();
+        assert_eq!(executor.end_statement(true), Ok(()));
+        executor.rollback().unwrap();
+        assert_eq!(executor.table_exists("apples"), false);
+    }
+
+    #[test]
+    fn failed_statements_outside_of_a_transaction_are_undone() {
+        let scenario = Scenario::new();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(table_handle.name().and_return("apples".to_string()));
+        let (journal, journal_handle) = scenario.create_mock_for::<dyn Journal>();
+        scenario.expect(journal_handle.rollback().and_return(Ok(())));
+        let mut executor = Executor::new(journal);
+
+        executor.add_table(table).unwrap();
+        assert_eq!(executor.end_statement(false), Ok(()));
+        assert_eq!(executor.table_exists("apples"), false);
+    }
+
+    #[test]
+    fn transactions_cannot_be_nested_or_ended_without_being_started() {
+        let scenario = Scenario::new();
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::<TableMock, JournalMock>::new(journal);
+
+        assert_eq!(executor.commit().is_err(), true);
+        assert_eq!(executor.rollback().is_err(), true);
+        executor.begin().unwrap();
+        assert_eq!(executor.begin().is_err(), true);
+    }
+
+    #[test]
+    fn rolling_back_to_a_savepoint_keeps_the_tables_created_before_it() {
+        let scenario = Scenario::new();
+        let (apples, apples_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(apples_handle.name().and_return("apples".to_string()));
+        let (pears, pears_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(pears_handle.name().and_return("pears".to_string()));
+        let (journal, journal_handle) = scenario.create_mock_for::<dyn Journal>();
+        scenario.expect(journal_handle.savepoint().and_return(()));
+        scenario.expect(journal_handle.rollback_to(0).and_return(Ok(())));
+        let mut executor = Executor::new(journal);
+
+        executor.begin().unwrap();
+        executor.add_table(apples).unwrap();
+        executor.savepoint("chunk");
+        executor.add_table(pears).unwrap();
+        executor.rollback_to("CHUNK").unwrap();
+
+        assert_eq!(executor.table_exists("apples"), true);
+        assert_eq!(executor.table_exists("pears"), false);
+        assert_eq!(executor.rollback_to("other").is_err(), true);
+    }
+
+    #[test]
+    fn releasing_a_savepoint_started_outside_of_a_transaction_commits() {
+        let scenario = Scenario::new();
+        let (journal, journal_handle) = scenario.create_mock_for::<dyn Journal>();
+        scenario.expect(journal_handle.savepoint().and_return_clone(()).times(2));
+        scenario.expect(journal_handle.release(1).and_return(Ok(())));
+        scenario.expect(journal_handle.release(0).and_return(Ok(())));
+        scenario.expect(journal_handle.commit().and_return(Ok(())));
+        let mut executor = Executor::<TableMock, JournalMock>::new(journal);
+
+        executor.savepoint("outer");
+        executor.savepoint("inner");
+        executor.release("inner").unwrap();
+        assert_eq!(executor.in_transaction, true);
+        executor.release("outer").unwrap();
+        assert_eq!(executor.in_transaction, false);
+    }
+
+    #[test]
+    fn updates_are_evaluated_against_the_rows_before_the_update() {
+        let scenario = Scenario::new();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(
+            table_handle
+                .columns()
+                .and_call_clone(|| columns(&["a", "b"]))
+                .times(..),
+        );
+        scenario.expect(
+            table_handle
+                .key_indices()
+                .and_return_clone(vec![0])
+                .times(..),
+        );
+        let rows = vec![
+            vec![Value::Integer(1), Value::Integer(2)],
+            vec![Value::Integer(3), Value::Integer(4)],
+        ];
+        // Without an index on the column the rows are all gone through
+        scenario.expect(
+            table_handle
+                .select_rows_in_range(0, (Bound::Excluded(Value::Integer(1)), Bound::Unbounded))
+                .and_return(Ok(None)),
+        );
+        scenario.expect(
+            table_handle
+                .select_rows()
+                .and_return(Ok(Box::new(rows.into_iter().map(Ok)))),
+        );
+        scenario.expect(
+            table_handle
+                .update_rows(vec![(
+                    vec![Value::Integer(3), Value::Integer(4)],
+                    vec![Value::Integer(4), Value::Integer(3)],
+                )])
+                .and_return(Ok(vec![vec![Value::Integer(4), Value::Integer(3)]])),
+        );
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::new(journal);
+        executor.tables.insert("apples".to_string(), table);
+
+        let statement = "UPDATE apples SET a = b, b = a WHERE a > 1;";
+        match sqlite3::AstParser::new().parse(statement).unwrap() {
+            ast::Ast::Update(update) => assert_eq!(executor.update(update), Ok(())),
+            ast => panic!("unexpected statement: {:?}", ast),
+        }
+    }
+
+    #[test]
+    fn deletions_remove_only_the_rows_matching_the_where_clause() {
+        let scenario = Scenario::new();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(
+            table_handle
+                .columns()
+                .and_call_clone(|| columns(&["a", "b"]))
+                .times(..),
+        );
+        scenario.expect(
+            table_handle
+                .key_indices()
+                .and_return_clone(vec![0])
+                .times(..),
+        );
+        let rows = vec![
+            vec![Value::Integer(1), Value::Null],
+            vec![Value::Integer(3), Value::Integer(4)],
+        ];
+        scenario.expect(
+            table_handle
+                .select_rows()
+                .and_return(Ok(Box::new(rows.into_iter().map(Ok)))),
+        );
+        scenario.expect(
+            table_handle
+                .delete_rows(vec![vec![Value::Integer(3), Value::Integer(4)]])
+                .and_return(Ok(())),
+        );
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::new(journal);
+        executor.tables.insert("apples".to_string(), table);
+
+        let statement = "DELETE FROM apples WHERE b <> 2;";
+        match sqlite3::AstParser::new().parse(statement).unwrap() {
+            ast::Ast::Delete(deletion) => assert_eq!(executor.delete(deletion), Ok(())),
+            ast => panic!("unexpected statement: {:?}", ast),
+        }
+    }
+
+    #[test]
+    fn rolling_back_a_transaction_restores_the_tables_dropped_in_it() {
+        let scenario = Scenario::new();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(table_handle.drop_rows().and_return(Ok(())));
+        scenario.expect(table_handle.name().and_return("apples".to_string()));
+        let (journal, journal_handle) = scenario.create_mock_for::<dyn Journal>();
+        scenario.expect(journal_handle.savepoint().and_return(()));
+        scenario.expect(journal_handle.release(0).and_return(Ok(())));
+        scenario.expect(journal_handle.rollback().and_return(Ok(())));
+        let mut executor = Executor::new(journal);
+        executor.tables.insert("apples".to_string(), table);
+
+        executor.begin().unwrap();
+        assert_eq!(executor.drop_table("pears", true), Ok(()));
+        assert_eq!(
+            executor.drop_table("pears", false),
+            Err("no such table: pears".to_string())
+        );
+        executor.drop_table("apples", false).unwrap();
+        assert_eq!(executor.table_exists("apples"), false);
+        executor.rollback().unwrap();
+        assert_eq!(executor.table_exists("apples"), true);
+    }
+
+    /// Runs the statement against a table of columns `a` and `b` keyed on
+    /// `a`, which holds `rows` unless the statement fails before reading
+    /// them.
+    fn select_from_apples(
+        statement: &str,
+        rows: Option<Vec<Vec<Value>>>,
+        sort_memory_limit: usize,
+    ) -> Result<Vec<Vec<Value>>, String> {
+        let scenario = Scenario::new();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(
+            table_handle
+                .columns()
+                .and_call_clone(|| columns(&["a", "b"]))
+                .times(..),
+        );
+        scenario.expect(
+            table_handle
+                .key_indices()
+                .and_return_clone(vec![0])
+                .times(..),
+        );
+        if let Some(rows) = rows {
+            scenario.expect(
+                table_handle
+                    .select_rows()
+                    .and_return(Ok(Box::new(rows.into_iter().map(Ok)))),
+            );
+        }
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::new(journal);
+        executor.sort_memory_limit = sort_memory_limit;
+        executor.tables.insert("apples".to_string(), table);
+
+        match sqlite3::AstParser::new().parse(statement).unwrap() {
+            ast::Ast::Select(selection) => executor.select(*selection).and_then(Iterator::collect),
+            ast => panic!("unexpected statement: {:?}", ast),
+        }
+    }
+
+    #[test]
+    fn selected_rows_are_ordered_limited_and_offset() {
+        let rows = (0..20)
+            .map(|i| {
+                let b = match i % 3 {
+                    0 => Value::Null,
+                    _ => Value::Integer(i % 4),
+                };
+                vec![Value::Integer(i), b]
+            })
+            .collect::<Vec<_>>();
+        let mut expected = rows.clone();
+        expected.sort_by(|x, y| match (&x[1], &y[1]) {
+            (Value::Null, Value::Null) => x[0].cmp(&y[0]).reverse(),
+            (Value::Null, _) => std::cmp::Ordering::Greater,
+            (_, Value::Null) => std::cmp::Ordering::Less,
+            (b1, b2) => b1.cmp(b2).then(x[0].cmp(&y[0]).reverse()),
+        });
+        let expected = expected[3..10]
+            .iter()
+            .map(|row| vec![row[1].clone(), row[0].clone()])
+            .collect::<Vec<_>>();
+
+        let statement = "SELECT b, a FROM apples ORDER BY 1 NULLS LAST, a DESC LIMIT 3, 7;";
+        assert_eq!(
+            select_from_apples(statement, Some(rows.clone()), SORT_MEMORY_LIMIT),
+            Ok(expected.clone())
+        );
+        // Spills every row to temporary pages
+        assert_eq!(select_from_apples(statement, Some(rows), 0), Ok(expected));
+    }
+
+    #[test]
+    fn ordering_by_the_key_column_keeps_the_order_of_the_tree() {
+        // Out of order, which only a sort would change
+        let rows = vec![
+            vec![Value::Integer(2), Value::Null],
+            vec![Value::Integer(1), Value::Null],
+        ];
+        let statement = "SELECT * FROM apples ORDER BY a LIMIT 5 OFFSET -1;";
+        assert_eq!(
+            select_from_apples(statement, Some(rows.clone()), 0),
+            Ok(rows)
+        );
+
+        let statement = "SELECT * FROM apples ORDER BY 3;";
+        assert_eq!(
+            select_from_apples(statement, None, 0),
+            Err("1st ORDER BY term out of range - should be between 1 and 2".to_string())
+        );
+    }
+
+    #[test]
+    fn rows_read_through_an_index_are_sorted_by_the_key() {
+        let scenario = Scenario::new();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(
+            table_handle
+                .columns()
+                .and_call_clone(|| columns(&["a", "b"]))
+                .times(..),
+        );
+        scenario.expect(
+            table_handle
+                .key_indices()
+                .and_return_clone(vec![0])
+                .times(..),
+        );
+        // In the order of the index on `b`
+        let rows = vec![
+            vec![Value::Integer(3), Value::Integer(2)],
+            vec![Value::Integer(1), Value::Integer(4)],
+        ];
+        scenario.expect(
+            table_handle
+                .select_rows_in_range(
+                    1,
+                    (
+                        Bound::Included(Value::Integer(2)),
+                        Bound::Excluded(Value::Integer(5)),
+                    ),
+                )
+                .and_return(Ok(Some(Box::new(rows.into_iter().map(Ok))))),
+        );
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::new(journal);
+        executor.tables.insert("apples".to_string(), table);
+
+        let statement = "SELECT a FROM apples WHERE b >= 2 AND 5 > b ORDER BY a;";
+        let result = match sqlite3::AstParser::new().parse(statement).unwrap() {
+            ast::Ast::Select(selection) => executor.select(*selection).and_then(Iterator::collect),
+            ast => panic!("unexpected statement: {:?}", ast),
+        };
+        assert_eq!(
+            result,
+            Ok(vec![vec![Value::Integer(1)], vec![Value::Integer(3)]])
+        );
+    }
+
+    #[test]
+    fn the_rowid_is_selectable_by_name_but_left_out_of_the_wildcard() {
+        let rows = vec![
+            vec![Value::Integer(7), Value::Null, Value::Integer(1)],
+            vec![Value::Integer(7), Value::Integer(2), Value::Integer(2)],
+        ];
+        let select = |statement: &str| {
+            let scenario = Scenario::new();
+            let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+            scenario.expect(
+                table_handle
+                    .columns()
+                    .and_call_clone(|| columns(&["a", "b"]))
+                    .times(..),
+            );
+            scenario.expect(
+                table_handle
+                    .key_indices()
+                    .and_return_clone(vec![2])
+                    .times(..),
+            );
+            scenario.expect(
+                table_handle
+                    .select_rows_in_range(mockers::matchers::ANY, mockers::matchers::ANY)
+                    .and_call_clone(|_, _| Ok(None))
+                    .times(..),
+            );
+            scenario.expect(
+                table_handle
+                    .select_rows()
+                    .and_return(Ok(Box::new(rows.clone().into_iter().map(Ok)))),
+            );
+            let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+            let mut executor = Executor::new(journal);
+            executor.tables.insert("apples".to_string(), table);
+            match sqlite3::AstParser::new().parse(statement).unwrap() {
+                ast::Ast::Select(selection) => executor
+                    .select(*selection)
+                    .and_then(Iterator::collect::<Result<Vec<_>, _>>),
+                ast => panic!("unexpected statement: {:?}", ast),
+            }
+        };
+
+        assert_eq!(
+            select("SELECT * FROM apples;"),
+            Ok(vec![
+                vec![Value::Integer(7), Value::Null],
+                vec![Value::Integer(7), Value::Integer(2)],
+            ])
+        );
+        assert_eq!(
+            select("SELECT oid, a FROM apples WHERE _rowid_ > 1 ORDER BY apples.rowid;"),
+            Ok(vec![vec![Value::Integer(2), Value::Integer(7)]])
+        );
+    }
+
+    #[test]
+    fn rows_are_grouped_with_nulls_together_and_groups_filtered() {
+        let b = [10, 10, 20, 0, 0, 20, 30];
+        let rows = (1..=7)
+            .zip(b.iter())
+            .map(|(a, b)| match b {
+                0 => vec![Value::Integer(a), Value::Null],
+                b => vec![Value::Integer(a), Value::Integer(*b)],
+            })
+            .collect();
+        let statement = "SELECT b, count(*), sum(a) FROM apples \
+            GROUP BY b HAVING count(a) > 1 ORDER BY 3 DESC;";
+        assert_eq!(
+            select_from_apples(statement, Some(rows), SORT_MEMORY_LIMIT),
+            Ok(vec![
+                vec![Value::Null, Value::Integer(2), Value::Integer(9)],
+                vec![Value::Integer(20), Value::Integer(2), Value::Integer(9)],
+                vec![Value::Integer(10), Value::Integer(2), Value::Integer(3)],
+            ])
+        );
+    }
+
+    #[test]
+    fn aggregates_without_group_by_make_up_one_row_even_without_rows() {
+        let statement = "SELECT count(*), max(a), a FROM apples;";
+        assert_eq!(
+            select_from_apples(statement, Some(vec![]), SORT_MEMORY_LIMIT),
+            Ok(vec![vec![Value::Integer(0), Value::Null, Value::Null]])
+        );
+
+        let statement = "SELECT a FROM apples WHERE count(*) > 1;";
+        assert_eq!(
+            select_from_apples(statement, None, SORT_MEMORY_LIMIT),
+            Err("misuse of aggregate: count()".to_string())
+        );
+        let statement = "SELECT median(a) FROM apples;";
+        assert_eq!(
+            select_from_apples(statement, None, SORT_MEMORY_LIMIT),
+            Err("no such function: median".to_string())
+        );
+    }
+
+    /// Runs the statement against the table `apples` of columns `a` and `b`
+    /// and the table `pears` of `pear_columns`, both keyed on their first
+    /// column.
+    fn select_from_apples_and_pears(
+        statement: &str,
+        pear_columns: &'static [&'static str],
+    ) -> Result<Vec<Vec<Value>>, String> {
+        let text = |s: &str| Value::Text(s.to_string());
+        let apples = vec![
+            vec![Value::Integer(1), Value::Integer(10)],
+            vec![Value::Integer(2), Value::Null],
+            vec![Value::Integer(3), Value::Integer(30)],
+            vec![Value::Integer(4), Value::Integer(20)],
+        ];
+        let pears = vec![
+            vec![Value::Integer(10), text("x")],
+            vec![Value::Integer(20), text("y")],
+        ];
+        let scenario = Scenario::new();
+        let (apples_table, apples_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(
+            apples_handle
+                .columns()
+                .and_call_clone(|| columns(&["a", "b"]))
+                .times(..),
+        );
+        scenario.expect(
+            apples_handle
+                .key_indices()
+                .and_return_clone(vec![0])
+                .times(..),
+        );
+        scenario.expect(
+            apples_handle
+                .select_rows()
+                .and_call_clone(move || Ok(Box::new(apples.clone().into_iter().map(Ok))))
+                .times(..),
+        );
+        let (pears_table, pears_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(
+            pears_handle
+                .columns()
+                .and_call_clone(move || columns(pear_columns))
+                .times(..),
+        );
+        scenario.expect(
+            pears_handle
+                .key_indices()
+                .and_return_clone(vec![0])
+                .times(..),
+        );
+        let rows = pears.clone();
+        scenario.expect(
+            pears_handle
+                .select_rows()
+                .and_call_clone(move || Ok(Box::new(rows.clone().into_iter().map(Ok))))
+                .times(..),
+        );
+        scenario.expect(
+            pears_handle
+                .select_row(mockers::matchers::ANY)
+                .and_call_clone(move |key: &Value| {
+                    Ok(pears.iter().find(|row| row[0] == *key).cloned())
+                })
+                .times(..),
+        );
+        let (journal, _) = scenario.create_mock_for::<dyn Journal>();
+        let mut executor = Executor::new(journal);
+        executor.tables.insert("apples".to_string(), apples_table);
+        executor.tables.insert("pears".to_string(), pears_table);
+
+        match sqlite3::AstParser::new().parse(statement).unwrap() {
+            ast::Ast::Select(selection) => executor.select(*selection).and_then(Iterator::collect),
+            ast => panic!("unexpected statement: {:?}", ast),
+        }
+    }
+
+    #[test]
+    fn left_joins_keep_the_rows_without_a_match() {
+        let text = |s: &str| Value::Text(s.to_string());
+        // Looks the pears up by their key
+        let statement = "SELECT a, pears.d FROM apples LEFT JOIN pears ON c = apples.b;";
+        let expected = vec![
+            vec![Value::Integer(1), text("x")],
+            vec![Value::Integer(2), Value::Null],
+            vec![Value::Integer(3), Value::Null],
+            vec![Value::Integer(4), text("y")],
+        ];
+        assert_eq!(
+            select_from_apples_and_pears(statement, &["c", "d"]),
+            Ok(expected)
+        );
+
+        // Goes through every pear
+        let statement = "SELECT a, p.d FROM apples LEFT OUTER JOIN pears AS p ON p.c < b;";
+        let expected = vec![
+            vec![Value::Integer(1), Value::Null],
+            vec![Value::Integer(2), Value::Null],
+            vec![Value::Integer(3), text("x")],
+            vec![Value::Integer(3), text("y")],
+            vec![Value::Integer(4), text("x")],
+        ];
+        assert_eq!(
+            select_from_apples_and_pears(statement, &["c", "d"]),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn inner_joins_keep_only_the_matching_pairs_of_rows() {
+        let statement = "SELECT apples.a, pears.a FROM apples, pears WHERE b = pears.a;";
+        assert_eq!(
+            select_from_apples_and_pears(statement, &["a", "d"]),
+            Ok(vec![
+                vec![Value::Integer(1), Value::Integer(10)],
+                vec![Value::Integer(4), Value::Integer(20)],
+            ])
+        );
+
+        let statement = "SELECT count(*) FROM apples CROSS JOIN pears;";
+        assert_eq!(
+            select_from_apples_and_pears(statement, &["c", "d"]),
+            Ok(vec![vec![Value::Integer(8)]])
+        );
+
+        let statement = "SELECT a FROM apples JOIN pears ON b = c;";
+        assert_eq!(
+            select_from_apples_and_pears(statement, &["a", "c"]),
+            Err("ambiguous column name: a".to_string())
+        );
+    }
+
+    #[test]
+    fn compared_values_are_converted_by_the_affinities_of_their_columns() {
+        let pager = testing::in_memory();
+        let mut executor = testing::open(&pager);
+        let mut run = |statement| testing::run(&mut executor, &pager, statement);
+        run("CREATE TABLE x(i INTEGER PRIMARY KEY, t TEXT);").unwrap();
+        run("INSERT INTO x VALUES (1, '1');").unwrap();
+
+        let row = Ok(vec![vec![Value::Integer(1), Value::Text("1".to_string())]]);
+        let statements = [
+            "SELECT * FROM x WHERE i = '1';",
+            "SELECT * FROM x WHERE i >= '1' AND i < '2';",
+            "SELECT * FROM x WHERE t = 1;",
+            "SELECT * FROM x WHERE i = t;",
+        ];
+        for statement in &statements {
+            assert_eq!(run(statement), row, "{}", statement);
+        }
+        // Indexes are looked up by the converted values too
+        run("CREATE INDEX x_t ON x(t);").unwrap();
+        for statement in &statements {
+            assert_eq!(run(statement), row, "{}", statement);
+        }
+        assert_eq!(run("SELECT * FROM x WHERE t > 10;"), Ok(vec![]));
+        run("DELETE FROM x WHERE t = 1;").unwrap();
+        assert_eq!(run("SELECT * FROM x;"), Ok(vec![]));
+    }
+
+    #[test]
+    fn failed_statements_within_a_transaction_are_undone() {
+        let pager = testing::in_memory();
+        let mut executor = testing::open(&pager);
+        let mut run = |statement| testing::run(&mut executor, &pager, statement);
+        run("CREATE TABLE t(id INTEGER PRIMARY KEY, v TEXT UNIQUE);").unwrap();
+        run("INSERT INTO t VALUES (1, 'a');").unwrap();
+        run("INSERT INTO t VALUES (2, 'b');").unwrap();
+
+        run("BEGIN;").unwrap();
+        assert_eq!(
+            run("UPDATE t SET v = 'z';"),
+            Err("UNIQUE constraint failed: t.v".to_string())
+        );
+        run("COMMIT;").unwrap();
+        assert_eq!(
+            run("SELECT * FROM t;"),
+            Ok(vec![
+                vec![Value::Integer(1), Value::Text("a".to_string())],
+                vec![Value::Integer(2), Value::Text("b".to_string())],
+            ])
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use super::{sorter, Journal};
+use crate::pager;
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::rc::Rc;
+use std::{env, process};
+
+impl<F: Write + Read + Seek> Journal for Rc<RefCell<pager::Pager<F>>> {
+    fn commit(&mut self) -> Result<(), String> {
+        self.borrow_mut().commit()
+    }
+
+    fn rollback(&mut self) -> Result<(), String> {
+        self.borrow_mut().rollback()
+    }
+
+    fn savepoint(&mut self) {
+        self.borrow_mut().begin_savepoint();
+    }
+
+    fn release(&mut self, depth: usize) -> Result<(), String> {
+        self.borrow_mut().release_savepoint(depth)
+    }
+
+    fn rollback_to(&mut self, depth: usize) -> Result<(), String> {
+        self.borrow_mut().rollback_to_savepoint(depth)
+    }
+}
+
+impl<F: Write + Read + Seek> sorter::Pages for pager::Pager<F> {
+    fn page_size(&self) -> usize {
+        self.page_size()
+    }
+
+    fn allocate_page(&mut self) -> Result<u32, String> {
+        self.allocate_page()
+    }
+
+    fn write_page(&mut self, page_no: u32, bytes: Vec<u8>) -> Result<(), String> {
+        self.write_page(page_no, bytes)
+    }
+
+    fn read_page(&mut self, page_no: u32) -> Result<Vec<u8>, String> {
+        self.read_page(page_no)
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.commit()
+    }
+}
+
+/// Pager over temporary files, which are deleted as soon as they are
+/// opened so that nothing is left behind once the pager is dropped.
+pub fn temporary(page_size: u16) -> Result<pager::Pager<File>, String> {
+    let open = |suffix: &str| -> Result<File, String> {
+        let path = env::temp_dir().join(format!(
+            "rsqlite3-{}-{}{}",
+            process::id(),
+            NEXT_TEMPORARY_FILE.with(|next| next.replace(next.get() + 1)),
+            suffix
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|err| err.to_string())?;
+        fs::remove_file(&path).ok();
+        Ok(file)
+    };
+    pager::Pager::new(open("-log")?, open("")?, lru::LruCache::new(16), page_size)
+}
+
+thread_local! {
+    static NEXT_TEMPORARY_FILE: Cell<u64> = const { Cell::new(0) };
+}
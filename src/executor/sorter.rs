@@ -0,0 +1,337 @@
+use super::Rows;
+use crate::ast::Value;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+/// Temporary pages that runs of sorted rows are spilled to.
+pub trait Pages {
+    fn page_size(&self) -> usize;
+    fn allocate_page(&mut self) -> Result<u32, String>;
+    fn write_page(&mut self, page_no: u32, bytes: Vec<u8>) -> Result<(), String>;
+    fn read_page(&mut self, page_no: u32) -> Result<Vec<u8>, String>;
+    /// Moves the pages written so far out of memory.
+    fn flush(&mut self) -> Result<(), String>;
+}
+
+/// How the values of one sort key are ordered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortKey {
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+/// Row along with the values of its sort keys.
+type Record = (Vec<Value>, Vec<Value>);
+
+/// Sorted records of a run or of the rows left in memory.
+type Source = Box<dyn Iterator<Item = Result<Record, String>>>;
+
+/// Sorts rows by their sort keys, keeping at most `memory_limit` bytes of
+/// them in memory. Larger inputs are sorted in runs that are spilled to
+/// temporary pages and merged back together as the result is read.
+pub struct Sorter {
+    sort_keys: Rc<Vec<SortKey>>,
+    memory_limit: usize,
+}
+
+impl Sorter {
+    pub fn new(sort_keys: Vec<SortKey>, memory_limit: usize) -> Sorter {
+        Sorter {
+            sort_keys: Rc::new(sort_keys),
+            memory_limit,
+        }
+    }
+
+    /// Sorts the rows paired with the values of their sort keys, failing
+    /// with the first error among them. The temporary pages are only opened
+    /// once the rows outgrow the memory limit.
+    pub fn sort<P: Pages + 'static>(
+        &self,
+        records: impl Iterator<Item = Result<Record, String>>,
+        open_pages: impl FnOnce() -> Result<P, String>,
+    ) -> Result<Rows, String> {
+        let mut open_pages = Some(open_pages);
+        let mut pages: Option<Rc<RefCell<P>>> = None;
+        let mut runs = vec![];
+        let mut buffer = vec![];
+        let mut buffered_bytes = 0;
+        for record in records {
+            let record = record?;
+            buffered_bytes += size(&record);
+            buffer.push(record);
+            if buffered_bytes <= self.memory_limit {
+                continue;
+            }
+            if pages.is_none() {
+                let open_pages = open_pages.take().unwrap();
+                pages = Some(Rc::new(RefCell::new(open_pages()?)));
+            }
+            self.sort_records(&mut buffer);
+            runs.push(write_run(
+                &mut *pages.as_ref().unwrap().borrow_mut(),
+                &buffer,
+            )?);
+            buffer.clear();
+            buffered_bytes = 0;
+        }
+        self.sort_records(&mut buffer);
+
+        let pages = match pages {
+            None => return Ok(Box::new(buffer.into_iter().map(|(_, row)| Ok(row)))),
+            Some(pages) => pages,
+        };
+        let mut sources: Vec<Source> = runs
+            .into_iter()
+            .map(|run| Box::new(RunReader::new(pages.clone(), run)) as Source)
+            .collect();
+        sources.push(Box::new(buffer.into_iter().map(Ok)));
+        Ok(Box::new(Merge::new(self.sort_keys.clone(), sources)))
+    }
+
+    fn sort_records(&self, records: &mut [Record]) {
+        // Stable, so that rows with equal keys stay in the order of the table
+        records.sort_by(|a, b| compare(&self.sort_keys, &a.0, &b.0));
+    }
+}
+
+fn compare(sort_keys: &[SortKey], a: &[Value], b: &[Value]) -> Ordering {
+    for ((sort_key, a), b) in sort_keys.iter().zip(a).zip(b) {
+        let ordering = match (a, b) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) if sort_key.nulls_first => Ordering::Less,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) if sort_key.nulls_first => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            (a, b) if sort_key.descending => b.cmp(a),
+            (a, b) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Estimate of the memory the record takes up.
+fn size(record: &Record) -> usize {
+    let values = record.0.iter().chain(record.1.iter());
+    values
+        .map(|value| match value {
+            Value::Text(text) => text.len() + 24,
+            Value::Blob(bytes) => bytes.len() + 24,
+            _ => 16,
+        })
+        .sum::<usize>()
+        + 48
+}
+
+/// Pages of a sorted run along with the number of records written to them.
+struct Run {
+    pages: Vec<u32>,
+    num_records: usize,
+}
+
+/// Writes the records one after the other, each preceded by its length,
+/// filling up one page after the other.
+fn write_run<P: Pages>(pages: &mut P, records: &[Record]) -> Result<Run, String> {
+    let mut bytes = vec![];
+    for record in records {
+        let encoded = rmp_serde::to_vec(record).map_err(|err| err.to_string())?;
+        bytes.extend((encoded.len() as u32).to_le_bytes().iter());
+        bytes.extend(encoded);
+    }
+    let mut run = Run {
+        pages: vec![],
+        num_records: records.len(),
+    };
+    for chunk in bytes.chunks(pages.page_size()) {
+        let page_no = pages.allocate_page()?;
+        let mut page = chunk.to_vec();
+        page.resize(pages.page_size(), 0);
+        pages.write_page(page_no, page)?;
+        run.pages.push(page_no);
+    }
+    pages.flush()?;
+    Ok(run)
+}
+
+/// Reads the records of a run back, one page at a time, until one cannot be
+/// read.
+struct RunReader<P: Pages> {
+    pages: Rc<RefCell<P>>,
+    run: std::vec::IntoIter<u32>,
+    page: io::Cursor<Vec<u8>>,
+    num_records: usize,
+}
+
+impl<P: Pages> RunReader<P> {
+    fn new(pages: Rc<RefCell<P>>, run: Run) -> RunReader<P> {
+        RunReader {
+            pages,
+            run: run.pages.into_iter(),
+            page: io::Cursor::new(vec![]),
+            num_records: run.num_records,
+        }
+    }
+
+    fn read_record(&mut self) -> io::Result<Record> {
+        let mut len = [0; 4];
+        self.read_exact(&mut len)?;
+        let mut encoded = vec![0; u32::from_le_bytes(len) as usize];
+        self.read_exact(&mut encoded)?;
+        rmp_serde::from_slice(&encoded).map_err(io::Error::other)
+    }
+}
+
+impl<P: Pages> Read for RunReader<P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.page.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let page_no = match self.run.next() {
+                None => return Ok(0),
+                Some(page_no) => page_no,
+            };
+            let page = self.pages.borrow_mut().read_page(page_no);
+            self.page = io::Cursor::new(page.map_err(io::Error::other)?);
+        }
+    }
+}
+
+impl<P: Pages> Iterator for RunReader<P> {
+    type Item = Result<Record, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.num_records == 0 {
+            return None;
+        }
+        self.num_records -= 1;
+        let record = self.read_record().map_err(|err| err.to_string());
+        if record.is_err() {
+            self.num_records = 0;
+        }
+        Some(record)
+    }
+}
+
+/// Merges sorted sources into one sorted sequence of rows. The error of a
+/// source that cannot be read comes out as soon as it is reached.
+struct Merge {
+    sort_keys: Rc<Vec<SortKey>>,
+    sources: Vec<std::iter::Peekable<Source>>,
+}
+
+impl Merge {
+    fn new(sort_keys: Rc<Vec<SortKey>>, sources: Vec<Source>) -> Merge {
+        Merge {
+            sort_keys,
+            sources: sources.into_iter().map(Iterator::peekable).collect(),
+        }
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<Vec<Value>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sort_keys = &self.sort_keys;
+        let mut smallest: Option<(usize, &Record)> = None;
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            let record = match source.peek() {
+                None => continue,
+                Some(Err(_)) => return source.next().map(|record| record.map(|(_, row)| row)),
+                Some(Ok(record)) => record,
+            };
+            // Earlier runs hold earlier rows, which win ties to keep the
+            // sort stable
+            let is_smaller = smallest.is_none_or(|(_, smallest)| {
+                compare(sort_keys, &record.0, &smallest.0) == Ordering::Less
+            });
+            if is_smaller {
+                smallest = Some((i, record));
+            }
+        }
+        let (i, _) = smallest?;
+        self.sources[i]
+            .next()
+            .map(|record| record.map(|(_, row)| row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pager;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    fn in_memory() -> Result<pager::Pager<Cursor<Vec<u8>>>, String> {
+        pager::Pager::new(
+            Cursor::new(vec![]),
+            Cursor::new(vec![]),
+            lru::LruCache::new(4),
+            512,
+        )
+    }
+
+    fn records(keys: impl Iterator<Item = i64>) -> Vec<Record> {
+        keys.enumerate()
+            .map(|(i, key)| {
+                let key = if key % 7 == 0 {
+                    Value::Null
+                } else {
+                    Value::Integer(key % 10)
+                };
+                (vec![key.clone()], vec![key, Value::Integer(i as i64)])
+            })
+            .collect()
+    }
+
+    fn expected(mut records: Vec<Record>, sort_key: SortKey) -> Vec<Vec<Value>> {
+        records.sort_by(|a, b| compare(&[sort_key], &a.0, &b.0));
+        records.into_iter().map(|(_, row)| row).collect()
+    }
+
+    #[test]
+    fn rows_are_sorted_in_memory_when_they_fit() {
+        let sort_key = SortKey {
+            descending: true,
+            nulls_first: false,
+        };
+        let records = records((0..50).map(|i| i * 37 % 50));
+        let sorter = Sorter::new(vec![sort_key], usize::MAX);
+        let sorted = sorter
+            .sort(
+                records.clone().into_iter().map(Ok),
+                || -> Result<pager::Pager<Cursor<Vec<u8>>>, String> { panic!("should not spill") },
+            )
+            .unwrap();
+
+        let sorted = sorted.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(sorted, expected(records, sort_key));
+        assert_eq!(sorted[0][0], Value::Integer(9));
+        assert_eq!(sorted[49][0], Value::Null);
+    }
+
+    #[test]
+    fn rows_that_do_not_fit_are_spilled_and_merged_stably() {
+        let sort_key = SortKey {
+            descending: false,
+            nulls_first: true,
+        };
+        let records = records((0..500).map(|i| i * 37 % 500));
+        let sorter = Sorter::new(vec![sort_key], 1000);
+        let sorted = sorter
+            .sort(records.clone().into_iter().map(Ok), in_memory)
+            .unwrap();
+
+        assert_eq!(
+            sorted.collect::<Result<Vec<_>, _>>(),
+            Ok(expected(records, sort_key))
+        );
+    }
+}
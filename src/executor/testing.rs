@@ -0,0 +1,55 @@
+//! Runs statements on tables stored by an in-memory pager, for the tests
+//! that need real tables.
+
+use super::Executor;
+use crate::ast::{Ast, Value};
+use crate::database::{self, Database};
+use crate::sqlite3;
+
+pub use database::{Pager, Table};
+
+/// Pager of a new database kept in memory, without a catalog.
+pub fn in_memory() -> Pager {
+    Database::open(database::MEMORY).unwrap().pager
+}
+
+/// Executor of the database stored by the pager, with the tables listed in
+/// its catalog.
+pub fn open(pager: &Pager) -> Executor<Table, Pager> {
+    Database::with_pager(pager.clone()).unwrap().executor
+}
+
+/// Runs the statement on tables stored by the pager, returning the rows
+/// of a selection or the value a pragma reads.
+pub fn run(
+    executor: &mut Executor<Table, Pager>,
+    pager: &Pager,
+    statement: &str,
+) -> Result<Vec<Vec<Value>>, String> {
+    let result = match sqlite3::AstParser::new().parse(statement).unwrap() {
+        Ast::Create(schema) => database::new_tree(pager)
+            .and_then(|rows| Table::new(schema, rows))
+            .and_then(|table| executor.add_table(table))
+            .map(|_| vec![]),
+        Ast::CreateIndex(index, if_not_exists) => {
+            executor.create_index(index, if_not_exists).map(|_| vec![])
+        }
+        Ast::Insert(insertion) => executor.insert(insertion).map(|_| vec![]),
+        Ast::Update(update) => executor.update(update).map(|_| vec![]),
+        Ast::Delete(deletion) => executor.delete(deletion).map(|_| vec![]),
+        Ast::DropTable(name, if_exists) => executor.drop_table(&name, if_exists).map(|_| vec![]),
+        Ast::AlterTable(name, alteration) => {
+            executor.alter_table(&name, alteration).map(|_| vec![])
+        }
+        Ast::Select(selection) => executor.select(*selection).and_then(|rows| rows.collect()),
+        Ast::Begin => executor.begin().map(|_| vec![]),
+        Ast::Commit => executor.commit().map(|_| vec![]),
+        Ast::Rollback => executor.rollback().map(|_| vec![]),
+        Ast::Pragma(name, value) => executor
+            .pragma(&name, value)
+            .map(|value| value.into_iter().map(|value| vec![value]).collect()),
+        ast => panic!("unexpected statement: {:?}", ast),
+    };
+    executor.end_statement(result.is_ok())?;
+    result
+}
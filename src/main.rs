@@ -2,31 +2,48 @@
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::{env, process};
 
 mod ast;
 mod bptree;
+mod database;
 mod executor;
 mod pager;
 mod sqlite3;
 mod table;
 
-use bptree::BPTree;
+use database::Database;
 use lalrpop_util::ParseError;
 
-use ast::{Ast, Value};
+use ast::Ast;
 
 fn main() {
     let mut rl = Editor::<()>::new();
     rl.load_history("history.txt").ok();
-    let mut executor = executor::Executor::new();
-    let bptree_degree = 4;
-    let bptree_page_byte_size = 16;
+    let path = env::args().nth(1);
+    let mut database = match Database::open(path.as_deref().unwrap_or(database::MEMORY)) {
+        Ok(database) => database,
+        Err(err) => {
+            println!("Error: {}", err);
+            process::exit(1);
+        }
+    };
     let print_err = |err: &str| println!("Error: {}", err.to_string());
     'main: loop {
         let readline = rl.readline("sqlite> ");
         match readline {
             Ok(buffer) => {
                 rl.add_history_entry(buffer.as_str());
+                let mut words = buffer.split_whitespace();
+                if words.next() == Some(".open") {
+                    // Without a file the new database is kept in memory
+                    let path = words.next().unwrap_or(database::MEMORY);
+                    match database.checkpoint().and_then(|_| Database::open(path)) {
+                        Ok(opened) => database = opened,
+                        Err(err) => print_err(&err),
+                    }
+                    continue;
+                }
                 let parse_result = sqlite3::AstParser::new().parse(buffer.as_str());
                 if parse_result.is_err() {
                     match parse_result.unwrap_err() {
@@ -54,49 +71,56 @@ fn main() {
                     continue;
                 }
                 let ast = parse_result.ok().unwrap();
-                match ast {
+                let executor = &mut database.executor;
+                let result = match ast {
                     Ast::Exit => break 'main,
-                    Ast::Create(schema) => {
-                        let result = table::Table::new(
-                            schema,
-                            BPTree::<Value, Vec<Value>>::new(
-                                bptree_degree,
-                                bptree_page_byte_size,
-                                bptree::Serializer::RMP,
-                            ),
-                        );
-                        match result {
-                            Err(err) => print_err(&err),
-                            Ok(table) => {
-                                let result = executor.add_table(table);
-                                if result.is_err() {
-                                    print_err(&result.unwrap_err());
-                                }
+                    Ast::Create(schema) => database::new_tree(&database.pager)
+                        .and_then(|rows| table::Table::new(schema, rows))
+                        .and_then(|table| executor.add_table(table)),
+                    Ast::CreateIndex(index, if_not_exists) => {
+                        executor.create_index(index, if_not_exists)
+                    }
+                    Ast::Insert(insertion) => executor.insert(insertion),
+                    Ast::Select(selection) => executor.select(*selection).and_then(|rows| {
+                        for row in rows {
+                            let row = row?;
+                            for val in &row[..row.len() - 1] {
+                                print!("{}|", val);
+                            }
+                            for val in &row[(row.len() - 1)..] {
+                                print!("{}\n", val);
                             }
                         }
+                        Ok(())
+                    }),
+                    Ast::Update(update) => executor.update(update),
+                    Ast::Delete(deletion) => executor.delete(deletion),
+                    Ast::DropTable(table_name, if_exists) => {
+                        executor.drop_table(&table_name, if_exists)
                     }
-                    Ast::Insert(insertion) => {
-                        let result = executor.insert(insertion);
-                        if result.is_err() {
-                            print_err(&result.unwrap_err());
-                        }
+                    Ast::AlterTable(table_name, alteration) => {
+                        executor.alter_table(&table_name, alteration)
                     }
-                    Ast::Select(selection) => {
-                        let result = executor.select(selection);
-                        match result {
-                            Err(err) => print_err(&err),
-                            Ok(rows) => {
-                                for row in rows {
-                                    for val in &row[..row.len() - 1] {
-                                        print!("{}|", val);
-                                    }
-                                    for val in &row[(row.len() - 1)..] {
-                                        print!("{}\n", val);
-                                    }
-                                }
-                            }
-                        }
+                    Ast::Begin => executor.begin(),
+                    Ast::Commit => executor.commit(),
+                    Ast::Rollback => executor.rollback(),
+                    Ast::Savepoint(name) => {
+                        executor.savepoint(&name);
+                        Ok(())
                     }
+                    Ast::Release(name) => executor.release(&name),
+                    Ast::RollbackTo(name) => executor.rollback_to(&name),
+                    Ast::Pragma(name, value) => executor.pragma(&name, value).map(|value| {
+                        if let Some(value) = value {
+                            println!("{}", value);
+                        }
+                    }),
+                };
+                if let Err(err) = &result {
+                    print_err(err);
+                }
+                if let Err(err) = executor.end_statement(result.is_ok()) {
+                    print_err(&err);
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -111,5 +135,8 @@ fn main() {
             }
         }
     }
+    if let Err(err) = database.checkpoint() {
+        print_err(&err);
+    }
     rl.save_history("history.txt").unwrap();
 }
@@ -1,34 +1,753 @@
-use crate::ast::Value;
-use rmp_serde;
-use std::io::{Read, Seek, Write};
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
 
+/// Page 0 holds the database header and is never handed out by
+/// `allocate_page`, which also lets 0 stand in for "no page".
+const HEADER_PAGE: u32 = 0;
+const MAGIC: &[u8; 8] = b"rsqlite3";
+const HEADER_SIZE: usize = 18;
+
+/// The transaction log starts with a header naming the page size and the
+/// salt of the current generation of frames.
+const LOG_MAGIC: &[u8; 8] = b"rsqlwal1";
+const LOG_HEADER_SIZE: u64 = 16;
+/// Every frame is a page number, the number of pages in the database if
+/// the frame ends a commit (0 otherwise), the salt and a checksum, followed
+/// by the page.
+///
+/// The checksum covers the rest of the frame and is seeded with the checksum
+/// of the frame before it, so a torn write or a frame left over from an
+/// earlier, unfinished commit ends the log.
+const FRAME_HEADER_SIZE: u64 = 16;
+
+/// Fixed-size page store on top of the database file.
+///
+/// Pages that have been read are kept in an LRU cache, while pages that
+/// have been written are held separately until the next `commit` so that
+/// they can never be evicted before reaching the transaction log.
+///
+/// Committed pages are appended to the transaction log as frames and only
+/// copied into the database file by `checkpoint`. Reads look for the latest
+/// committed frame of a page before going to the file.
 pub struct Pager<F: Write + Read + Seek> {
     transaction_log: F,
     file: F,
     page_size: u16,
-    page_cache: lru::LruCache<u32, Vec<Value>>,
+    page_cache: lru::LruCache<u32, Vec<u8>>,
+    dirty_pages: HashMap<u32, Vec<u8>>,
+    /// Offset in the transaction log of the latest committed frame of a page
+    log_index: HashMap<u32, u64>,
+    log_end: u64,
+    /// Frames carrying another salt are left over from before the last
+    /// checkpoint
+    log_salt: u32,
+    /// Checksum of the last committed frame, seeding the next one
+    log_checksum: u32,
     num_pages_on_disk: u32,
+    num_pages: u32,
+    free_list_head: u32,
+    savepoints: Vec<Savepoint>,
+}
+
+/// State of the uncommitted changes when a savepoint was started, so that
+/// they can be rolled back to it.
+struct Savepoint {
+    /// Contents of the pages before they were first written after the
+    /// savepoint, or `None` if they had not been written since the last
+    /// commit
+    pages: HashMap<u32, Option<Vec<u8>>>,
+    num_pages: u32,
+    free_list_head: u32,
 }
 
 impl<F: Write + Read + Seek> Pager<F> {
+    /// Opens the database stored in `file` and replays the frames committed
+    /// to `transaction_log`, initializing a new database with pages of
+    /// `page_size` bytes if neither holds one.
+    ///
+    /// The page size of an existing database is read from its header and
+    /// takes precedence over `page_size`.
     pub fn new(
         transaction_log: F,
-        file: F,
-        page_cache: lru::LruCache<u32, Vec<Value>>,
+        mut file: F,
+        page_cache: lru::LruCache<u32, Vec<u8>>,
         page_size: u16,
-    ) -> Pager<F> {
-        return Pager {
+    ) -> Result<Pager<F>, String> {
+        if (page_size as usize) < HEADER_SIZE {
+            return Err(format!("page size {} is too small", page_size));
+        }
+        let file_len = file.seek(SeekFrom::End(0)).map_err(|err| err.to_string())?;
+        let mut pager = Pager {
             transaction_log,
             file,
             page_size,
             page_cache,
+            dirty_pages: HashMap::new(),
+            log_index: HashMap::new(),
+            log_end: LOG_HEADER_SIZE,
+            log_salt: 0,
+            log_checksum: 0,
             num_pages_on_disk: 0,
+            num_pages: 1,
+            free_list_head: 0,
+            savepoints: vec![],
+        };
+        if file_len > 0 {
+            let mut header = [0; HEADER_SIZE];
+            pager
+                .file
+                .seek(SeekFrom::Start(0))
+                .and_then(|_| pager.file.read_exact(&mut header))
+                .map_err(|_| "file is not a database".to_string())?;
+            if &header[0..8] != MAGIC {
+                return Err("file is not a database".to_string());
+            }
+            pager.page_size = u16::from_le_bytes([header[8], header[9]]);
+            if (pager.page_size as usize) < HEADER_SIZE {
+                return Err("file is not a database".to_string());
+            }
+            pager.num_pages_on_disk = (file_len / pager.page_size as u64) as u32;
+        }
+        pager.open_transaction_log(file_len == 0)?;
+
+        if file_len == 0 && !pager.log_index.contains_key(&HEADER_PAGE) {
+            pager.write_header();
+            return Ok(pager);
+        }
+        let header = pager.read_page(HEADER_PAGE)?;
+        if &header[0..8] != MAGIC {
+            return Err("file is not a database".to_string());
+        }
+        pager.num_pages = read_u32(&header, 10);
+        pager.free_list_head = read_u32(&header, 14);
+        Ok(pager)
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size as usize
+    }
+
+    pub fn num_pages(&self) -> u32 {
+        self.num_pages
+    }
+
+    /// Returns a copy of the page, consulting uncommitted writes, the cache
+    /// and the transaction log before going to the file.
+    pub fn read_page(&mut self, page_no: u32) -> Result<Vec<u8>, String> {
+        if page_no >= self.num_pages {
+            return Err(format!("page {} is out of range", page_no));
+        }
+        if let Some(page) = self.dirty_pages.get(&page_no) {
+            return Ok(page.clone());
+        }
+        if let Some(page) = self.page_cache.get(&page_no) {
+            return Ok(page.clone());
+        }
+
+        let mut page = vec![0; self.page_size()];
+        if let Some(&offset) = self.log_index.get(&page_no) {
+            self.transaction_log
+                .seek(SeekFrom::Start(offset + FRAME_HEADER_SIZE))
+                .and_then(|_| self.transaction_log.read_exact(&mut page))
+                .map_err(|err| err.to_string())?;
+        } else if page_no < self.num_pages_on_disk {
+            self.file
+                .seek(SeekFrom::Start(self.page_offset(page_no)))
+                .and_then(|_| self.file.read_exact(&mut page))
+                .map_err(|err| err.to_string())?;
+        }
+        self.page_cache.put(page_no, page.clone());
+        Ok(page)
+    }
+
+    /// Replaces the contents of the page. The bytes are zero-padded up to
+    /// the page size and only reach the transaction log on the next
+    /// `commit`.
+    pub fn write_page(&mut self, page_no: u32, bytes: Vec<u8>) -> Result<(), String> {
+        if page_no == HEADER_PAGE || page_no >= self.num_pages {
+            return Err(format!("page {} is out of range", page_no));
+        }
+        if bytes.len() > self.page_size() {
+            return Err(format!(
+                "{} bytes do not fit in a page of {} bytes",
+                bytes.len(),
+                self.page_size
+            ));
+        }
+        self.put_dirty_page(page_no, bytes);
+        Ok(())
+    }
+
+    /// Hands out a zeroed page, reusing a freed one when there is any.
+    pub fn allocate_page(&mut self) -> Result<u32, String> {
+        let page_no = if self.free_list_head != 0 {
+            let page_no = self.free_list_head;
+            let page = self.read_page(page_no)?;
+            self.free_list_head = read_u32(&page, 0);
+            page_no
+        } else {
+            self.num_pages += 1;
+            self.num_pages - 1
+        };
+        self.put_dirty_page(page_no, vec![]);
+        self.write_header();
+        Ok(page_no)
+    }
+
+    /// Returns the page to the free list so that a later `allocate_page`
+    /// can reuse it.
+    pub fn free_page(&mut self, page_no: u32) -> Result<(), String> {
+        let next = self.free_list_head.to_le_bytes().to_vec();
+        self.write_page(page_no, next)?;
+        self.free_list_head = page_no;
+        self.write_header();
+        Ok(())
+    }
+
+    /// Appends every modified page to the transaction log. The changes are
+    /// durable once the frame marking the commit has been written.
+    pub fn commit(&mut self) -> Result<(), String> {
+        self.savepoints.clear();
+        if self.dirty_pages.is_empty() {
+            return Ok(());
+        }
+        let mut page_nos = self.dirty_pages.keys().cloned().collect::<Vec<u32>>();
+        page_nos.sort();
+
+        let mut frames = vec![];
+        let mut offsets = vec![];
+        let mut checksum = self.log_checksum;
+        for (i, &page_no) in page_nos.iter().enumerate() {
+            let db_size = if i == page_nos.len() - 1 {
+                self.num_pages
+            } else {
+                0
+            };
+            offsets.push((page_no, self.log_end + frames.len() as u64));
+            frames.extend_from_slice(&page_no.to_le_bytes());
+            frames.extend_from_slice(&db_size.to_le_bytes());
+            frames.extend_from_slice(&self.log_salt.to_le_bytes());
+            let page = &self.dirty_pages[&page_no];
+            checksum = frame_checksum(checksum, &frames[frames.len() - 12..], page);
+            frames.extend_from_slice(&checksum.to_le_bytes());
+            frames.extend_from_slice(page);
+        }
+        self.transaction_log
+            .seek(SeekFrom::Start(self.log_end))
+            .and_then(|_| self.transaction_log.write_all(&frames))
+            .and_then(|_| self.transaction_log.flush())
+            .map_err(|err| err.to_string())?;
+
+        self.log_end += frames.len() as u64;
+        self.log_checksum = checksum;
+        for (page_no, offset) in offsets {
+            self.log_index.insert(page_no, offset);
+            let page = self.dirty_pages.remove(&page_no).unwrap();
+            self.page_cache.put(page_no, page);
+        }
+        Ok(())
+    }
+
+    /// Discards every page modified since the last `commit`.
+    pub fn rollback(&mut self) -> Result<(), String> {
+        self.savepoints.clear();
+        self.dirty_pages.clear();
+        if self.num_pages_on_disk == 0 && !self.log_index.contains_key(&HEADER_PAGE) {
+            // Nothing has been committed to a new database yet
+            self.num_pages = 1;
+            self.free_list_head = 0;
+            self.write_header();
+            return Ok(());
+        }
+        let header = self.read_page(HEADER_PAGE)?;
+        self.num_pages = read_u32(&header, 10);
+        self.free_list_head = read_u32(&header, 14);
+        Ok(())
+    }
+
+    /// Starts a savepoint nested in the ones already started and returns
+    /// its depth.
+    pub fn begin_savepoint(&mut self) -> usize {
+        self.savepoints.push(Savepoint {
+            pages: HashMap::new(),
+            num_pages: self.num_pages,
+            free_list_head: self.free_list_head,
+        });
+        self.savepoints.len() - 1
+    }
+
+    /// Ends the savepoint at `depth` and the ones nested in it, keeping
+    /// their changes.
+    pub fn release_savepoint(&mut self, depth: usize) -> Result<(), String> {
+        self.check_savepoint(depth)?;
+        let released = self.savepoints.split_off(depth);
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            for pages in released.into_iter().map(|savepoint| savepoint.pages) {
+                for (page_no, page) in pages {
+                    savepoint.pages.entry(page_no).or_insert(page);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes every change made since the savepoint at `depth` was started.
+    /// The savepoint itself stays open, while the ones nested in it end.
+    pub fn rollback_to_savepoint(&mut self, depth: usize) -> Result<(), String> {
+        self.check_savepoint(depth)?;
+        for savepoint in self.savepoints.drain(depth + 1..).rev() {
+            restore_pages(&mut self.dirty_pages, savepoint.pages);
+        }
+        let savepoint = &mut self.savepoints[depth];
+        restore_pages(&mut self.dirty_pages, savepoint.pages.drain().collect());
+        self.num_pages = savepoint.num_pages;
+        self.free_list_head = savepoint.free_list_head;
+        Ok(())
+    }
+
+    fn check_savepoint(&self, depth: usize) -> Result<(), String> {
+        if depth >= self.savepoints.len() {
+            return Err(format!("no savepoint at depth {}", depth));
+        }
+        Ok(())
+    }
+
+    /// Copies the latest committed frame of every page into the database
+    /// file and starts the transaction log over.
+    pub fn checkpoint(&mut self) -> Result<(), String> {
+        let mut frames = self
+            .log_index
+            .iter()
+            .map(|(&page_no, &offset)| (page_no, offset))
+            .collect::<Vec<(u32, u64)>>();
+        frames.sort();
+
+        let mut page = vec![0; self.page_size()];
+        for &(page_no, offset) in &frames {
+            self.transaction_log
+                .seek(SeekFrom::Start(offset + FRAME_HEADER_SIZE))
+                .and_then(|_| self.transaction_log.read_exact(&mut page))
+                .map_err(|err| err.to_string())?;
+            self.file
+                .seek(SeekFrom::Start(self.page_offset(page_no)))
+                .and_then(|_| self.file.write_all(&page))
+                .map_err(|err| err.to_string())?;
+            self.num_pages_on_disk = cmp::max(self.num_pages_on_disk, page_no + 1);
+        }
+        self.file.flush().map_err(|err| err.to_string())?;
+
+        self.log_index.clear();
+        self.reset_transaction_log(self.log_salt.wrapping_add(1))
+    }
+
+    /// Indexes the frames of every complete commit in the transaction log,
+    /// or starts a new log if it does not belong to a database of this page
+    /// size.
+    ///
+    /// The log ends at the first frame that is short, belongs to another
+    /// generation or fails its checksum. Frames after the last commit marker
+    /// are from a commit that never finished and are overwritten by the next
+    /// one.
+    fn open_transaction_log(&mut self, is_new_database: bool) -> Result<(), String> {
+        let log_len = self
+            .transaction_log
+            .seek(SeekFrom::End(0))
+            .map_err(|err| err.to_string())?;
+        let mut header = [0; LOG_HEADER_SIZE as usize];
+        if log_len < LOG_HEADER_SIZE
+            || self
+                .transaction_log
+                .seek(SeekFrom::Start(0))
+                .and_then(|_| self.transaction_log.read_exact(&mut header))
+                .is_err()
+            || &header[0..8] != LOG_MAGIC
+        {
+            return self.reset_transaction_log(0);
+        }
+        let page_size = read_u32(&header, 8);
+        if page_size > u16::MAX as u32
+            || (page_size as usize) < HEADER_SIZE
+            || (!is_new_database && page_size != self.page_size as u32)
+        {
+            return self.reset_transaction_log(0);
+        }
+        self.page_size = page_size as u16;
+        self.log_salt = read_u32(&header, 12);
+
+        self.log_checksum = self.log_salt;
+
+        let frame_size = FRAME_HEADER_SIZE + self.page_size as u64;
+        let mut frame = vec![0; frame_size as usize];
+        let mut checksum = self.log_checksum;
+        let mut uncommitted = vec![];
+        let mut offset = LOG_HEADER_SIZE;
+        while offset + frame_size <= log_len {
+            self.transaction_log
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| self.transaction_log.read_exact(&mut frame))
+                .map_err(|err| err.to_string())?;
+            checksum = frame_checksum(checksum, &frame[..12], &frame[FRAME_HEADER_SIZE as usize..]);
+            if read_u32(&frame, 8) != self.log_salt || read_u32(&frame, 12) != checksum {
+                break;
+            }
+            uncommitted.push((read_u32(&frame, 0), offset));
+            offset += frame_size;
+            if read_u32(&frame, 4) != 0 {
+                self.log_index.extend(uncommitted.drain(..));
+                self.log_end = offset;
+                self.log_checksum = checksum;
+            }
+        }
+        Ok(())
+    }
+
+    fn reset_transaction_log(&mut self, salt: u32) -> Result<(), String> {
+        let mut header = LOG_MAGIC.to_vec();
+        header.extend_from_slice(&(self.page_size as u32).to_le_bytes());
+        header.extend_from_slice(&salt.to_le_bytes());
+        self.transaction_log
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.transaction_log.write_all(&header))
+            .and_then(|_| self.transaction_log.flush())
+            .map_err(|err| err.to_string())?;
+        self.log_salt = salt;
+        self.log_checksum = salt;
+        self.log_end = LOG_HEADER_SIZE;
+        Ok(())
+    }
+
+    fn put_dirty_page(&mut self, page_no: u32, mut bytes: Vec<u8>) {
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            if !savepoint.pages.contains_key(&page_no) {
+                let page = self.dirty_pages.get(&page_no).cloned();
+                savepoint.pages.insert(page_no, page);
+            }
+        }
+        bytes.resize(self.page_size(), 0);
+        self.page_cache.pop(&page_no);
+        self.dirty_pages.insert(page_no, bytes);
+    }
+
+    fn write_header(&mut self) {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&self.page_size.to_le_bytes());
+        header.extend_from_slice(&self.num_pages.to_le_bytes());
+        header.extend_from_slice(&self.free_list_head.to_le_bytes());
+        self.put_dirty_page(HEADER_PAGE, header);
+    }
+
+    fn page_offset(&self, page_no: u32) -> u64 {
+        page_no as u64 * self.page_size as u64
+    }
+}
+
+fn restore_pages(dirty_pages: &mut HashMap<u32, Vec<u8>>, pages: HashMap<u32, Option<Vec<u8>>>) {
+    for (page_no, page) in pages {
+        match page {
+            Some(page) => dirty_pages.insert(page_no, page),
+            None => dirty_pages.remove(&page_no),
         };
     }
+}
+
+/// FNV-1a over the frame header and page, starting from `seed`.
+fn frame_checksum(seed: u32, frame_header: &[u8], page: &[u8]) -> u32 {
+    frame_header
+        .iter()
+        .chain(page)
+        .fold(seed ^ 0x811c_9dc5, |hash, &byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    fn open<'a>(
+        log: &'a mut Cursor<Vec<u8>>,
+        file: &'a mut Cursor<Vec<u8>>,
+    ) -> Pager<&'a mut Cursor<Vec<u8>>> {
+        Pager::new(log, file, lru::LruCache::new(2), 64).unwrap()
+    }
+
+    #[test]
+    fn pages_are_persisted_across_reopening() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        {
+            let mut pager = open(&mut log, &mut file);
+            let page_no = pager.allocate_page().unwrap();
+            assert_eq!(page_no, 1);
+            pager.write_page(page_no, vec![1, 2, 3]).unwrap();
+            pager.commit().unwrap();
+        }
+
+        let mut pager = open(&mut log, &mut file);
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(&pager.read_page(1).unwrap()[..4], &[1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn uncommitted_pages_are_lost_on_reopening() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        {
+            let mut pager = open(&mut log, &mut file);
+            pager.commit().unwrap();
+            let page_no = pager.allocate_page().unwrap();
+            pager.write_page(page_no, vec![1]).unwrap();
+        }
+
+        let mut pager = open(&mut log, &mut file);
+        assert_eq!(pager.num_pages(), 1);
+        assert_eq!(pager.read_page(1).is_err(), true);
+    }
+
+    #[test]
+    fn reads_are_served_after_the_page_is_evicted_from_the_cache() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        let mut pager = open(&mut log, &mut file);
+        for i in 1..=4 {
+            let page_no = pager.allocate_page().unwrap();
+            pager.write_page(page_no, vec![i]).unwrap();
+        }
+        pager.commit().unwrap();
+
+        for i in 1..=4 {
+            assert_eq!(pager.read_page(i as u32).unwrap()[0], i);
+        }
+    }
+
+    #[test]
+    fn freed_pages_are_reused() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        let mut pager = open(&mut log, &mut file);
+        let first = pager.allocate_page().unwrap();
+        let second = pager.allocate_page().unwrap();
+        pager.write_page(second, vec![7]).unwrap();
+        pager.free_page(first).unwrap();
+        pager.free_page(second).unwrap();
+
+        assert_eq!(pager.allocate_page().unwrap(), second);
+        assert_eq!(pager.read_page(second).unwrap(), vec![0; 64]);
+        assert_eq!(pager.allocate_page().unwrap(), first);
+        assert_eq!(pager.allocate_page().unwrap(), 3);
+    }
+
+    #[test]
+    fn writes_larger_than_a_page_fail() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        let mut pager = open(&mut log, &mut file);
+        let page_no = pager.allocate_page().unwrap();
+        assert_eq!(pager.write_page(page_no, vec![0; 65]).is_err(), true);
+    }
+
+    #[test]
+    fn committed_pages_are_read_from_the_log_until_a_checkpoint() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        {
+            let mut pager = open(&mut log, &mut file);
+            let page_no = pager.allocate_page().unwrap();
+            pager.write_page(page_no, vec![5]).unwrap();
+            pager.commit().unwrap();
+        }
+        assert_eq!(file.get_ref().len(), 0);
+
+        let mut pager = open(&mut log, &mut file);
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(pager.read_page(1).unwrap()[0], 5);
+    }
+
+    #[test]
+    fn checkpoints_copy_committed_pages_into_the_file() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        {
+            let mut pager = open(&mut log, &mut file);
+            let page_no = pager.allocate_page().unwrap();
+            pager.write_page(page_no, vec![5]).unwrap();
+            pager.commit().unwrap();
+            pager.checkpoint().unwrap();
+        }
+        assert_eq!(file.get_ref().len(), 2 * 64);
+
+        let mut empty_log = Cursor::new(vec![]);
+        let mut pager = open(&mut empty_log, &mut file);
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(pager.read_page(1).unwrap()[0], 5);
+    }
+
+    #[test]
+    fn frames_from_before_a_checkpoint_are_ignored() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        {
+            let mut pager = open(&mut log, &mut file);
+            for i in 1..=2 {
+                let page_no = pager.allocate_page().unwrap();
+                pager.write_page(page_no, vec![i]).unwrap();
+            }
+            pager.commit().unwrap();
+            pager.checkpoint().unwrap();
+
+            // Overwrites only the first frame of the previous commit
+            pager.write_page(1, vec![3]).unwrap();
+            pager.commit().unwrap();
+        }
+
+        let mut pager = open(&mut log, &mut file);
+        assert_eq!(pager.read_page(1).unwrap()[0], 3);
+        assert_eq!(pager.read_page(2).unwrap()[0], 2);
+    }
+
+    #[test]
+    fn rolled_back_pages_are_discarded() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        let mut pager = open(&mut log, &mut file);
+        let page_no = pager.allocate_page().unwrap();
+        pager.write_page(page_no, vec![1]).unwrap();
+        pager.commit().unwrap();
+
+        pager.write_page(page_no, vec![2]).unwrap();
+        pager.allocate_page().unwrap();
+        pager.free_page(page_no).unwrap();
+        pager.rollback().unwrap();
+
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(pager.read_page(page_no).unwrap()[0], 1);
+        assert_eq!(pager.allocate_page().unwrap(), 2);
+    }
+
+    #[test]
+    fn rolling_back_to_a_savepoint_keeps_earlier_changes() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        let mut pager = open(&mut log, &mut file);
+        let page_no = pager.allocate_page().unwrap();
+        pager.write_page(page_no, vec![1]).unwrap();
+        pager.commit().unwrap();
+
+        pager.write_page(page_no, vec![2]).unwrap();
+        let outer = pager.begin_savepoint();
+        pager.write_page(page_no, vec![3]).unwrap();
+        pager.begin_savepoint();
+        let allocated = pager.allocate_page().unwrap();
+        pager.write_page(allocated, vec![4]).unwrap();
+        pager.rollback_to_savepoint(outer).unwrap();
+
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(pager.read_page(page_no).unwrap()[0], 2);
+        assert_eq!(pager.rollback_to_savepoint(outer + 1).is_err(), true);
+        pager.rollback().unwrap();
+        assert_eq!(pager.read_page(page_no).unwrap()[0], 1);
+    }
+
+    #[test]
+    fn released_savepoints_can_still_be_rolled_back_by_the_outer_one() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        let mut pager = open(&mut log, &mut file);
+        let page_no = pager.allocate_page().unwrap();
+        pager.commit().unwrap();
+
+        let outer = pager.begin_savepoint();
+        let inner = pager.begin_savepoint();
+        pager.write_page(page_no, vec![1]).unwrap();
+        pager.release_savepoint(inner).unwrap();
+        assert_eq!(pager.read_page(page_no).unwrap()[0], 1);
+
+        pager.rollback_to_savepoint(outer).unwrap();
+        assert_eq!(pager.read_page(page_no).unwrap()[0], 0);
+    }
+
+    /// Commits page 1 = [1], then page 1 = [2] and page 2 = [3] in a second
+    /// transaction, leaving the log as written.
+    fn commit_two_transactions(log: &mut Cursor<Vec<u8>>, file: &mut Cursor<Vec<u8>>) {
+        let mut pager = open(log, file);
+        let page_no = pager.allocate_page().unwrap();
+        pager.write_page(page_no, vec![1]).unwrap();
+        pager.commit().unwrap();
+
+        pager.write_page(page_no, vec![2]).unwrap();
+        let page_no = pager.allocate_page().unwrap();
+        pager.write_page(page_no, vec![3]).unwrap();
+        pager.commit().unwrap();
+    }
+
+    #[test]
+    fn torn_commits_are_discarded_on_reopening() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        commit_two_transactions(&mut log, &mut file);
+        // Crash halfway through the last frame
+        let len = log.get_ref().len();
+        log.get_mut().truncate(len - 40);
+
+        {
+            let mut pager = open(&mut log, &mut file);
+            assert_eq!(pager.num_pages(), 2);
+            assert_eq!(pager.read_page(1).unwrap()[0], 1);
+            pager.write_page(1, vec![4]).unwrap();
+            pager.commit().unwrap();
+        }
+
+        let mut pager = open(&mut log, &mut file);
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(pager.read_page(1).unwrap()[0], 4);
+    }
+
+    #[test]
+    fn commits_with_a_corrupted_frame_are_discarded_on_reopening() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        commit_two_transactions(&mut log, &mut file);
+        // The second commit starts after the header and two frames
+        let page_start = 16 + 2 * 80 + 16;
+        log.get_mut()[page_start + 10] ^= 0xff;
+
+        let mut pager = open(&mut log, &mut file);
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(pager.read_page(1).unwrap()[0], 1);
+    }
+
+    #[test]
+    fn interrupted_checkpoints_are_completed_from_the_log() {
+        let (mut log, mut file) = (Cursor::new(vec![]), Cursor::new(vec![]));
+        {
+            let mut pager = open(&mut log, &mut file);
+            let page_no = pager.allocate_page().unwrap();
+            pager.write_page(page_no, vec![1]).unwrap();
+            pager.commit().unwrap();
+            pager.checkpoint().unwrap();
+            pager.write_page(page_no, vec![2]).unwrap();
+            pager.commit().unwrap();
+        }
+        // Crash while the checkpoint was copying page 1 into the file
+        file.get_mut()[64..].copy_from_slice(&[0xff; 64]);
+
+        let mut pager = open(&mut log, &mut file);
+        assert_eq!(pager.read_page(1).unwrap()[0], 2);
+        pager.checkpoint().unwrap();
+        assert_eq!(file.get_ref()[64], 2);
+    }
+
+    #[test]
+    fn files_without_a_header_are_rejected() {
+        let mut log = Cursor::new(vec![]);
+        let mut file = Cursor::new(b"not a database file".to_vec());
+        let result = Pager::new(&mut log, &mut file, lru::LruCache::new(2), 64);
+        assert_eq!(result.is_err(), true);
+    }
 
-    pub fn insert(&self, row: &[Value]) -> u32 {
-        let serialized = rmp_serde::to_vec(&row).unwrap();
-        println!("{:#?}", serialized);
-        0
+    #[test]
+    fn files_with_a_page_size_too_small_for_the_header_are_rejected() {
+        let mut log = Cursor::new(vec![]);
+        let mut header = MAGIC.to_vec();
+        header.resize(64, 0);
+        let mut file = Cursor::new(header);
+        let result = Pager::new(&mut log, &mut file, lru::LruCache::new(2), 64);
+        assert_eq!(result.err(), Some("file is not a database".to_string()));
     }
 }
@@ -0,0 +1,138 @@
+use crate::ast::Value;
+
+/// Datatypes allowed in the columns of a STRICT table.
+pub const STRICT_DATATYPES: [&str; 6] = ["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"];
+
+/// Storage class a column prefers. Values are converted to it on insert
+/// whenever that loses no information.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Affinity {
+    Integer,
+    Real,
+    Text,
+    Numeric,
+    Blob,
+}
+
+impl Affinity {
+    /// Derives the affinity from the declared type of a column, following
+    /// the rules of SQLite.
+    pub fn of(datatype: Option<&str>) -> Affinity {
+        let datatype = match datatype {
+            None => return Affinity::Blob,
+            Some(datatype) => datatype.to_uppercase(),
+        };
+        let contains_any = |names: &[&str]| names.iter().any(|name| datatype.contains(name));
+        if contains_any(&["INT"]) {
+            Affinity::Integer
+        } else if contains_any(&["CHAR", "CLOB", "TEXT"]) {
+            Affinity::Text
+        } else if contains_any(&["BLOB"]) || datatype == "ANY" {
+            Affinity::Blob
+        } else if contains_any(&["REAL", "FLOA", "DOUB"]) {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    pub fn apply(self, value: Value) -> Value {
+        match (self, value) {
+            (Affinity::Blob, value) => value,
+            (Affinity::Text, Value::Integer(i)) => Value::Text(i.to_string()),
+            (Affinity::Text, Value::Real(r)) => Value::Text(Value::Real(r).to_string()),
+            (Affinity::Text, value) => value,
+            (Affinity::Real, value) => match Affinity::Numeric.apply(value) {
+                Value::Integer(i) => Value::Real(i as f64),
+                value => value,
+            },
+            (Affinity::Integer, value) | (Affinity::Numeric, value) => match value {
+                Value::Text(text) => parse_number(&text).unwrap_or(Value::Text(text)),
+                Value::Real(r) => real_to_integer(r).unwrap_or(Value::Real(r)),
+                value => value,
+            },
+        }
+    }
+}
+
+/// Reads text that is a well-formed integer or real literal, surrounding
+/// whitespace aside.
+fn parse_number(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(i) = text.parse::<i64>() {
+        return Some(Value::Integer(i));
+    }
+    // Leaves out the names of special values like "inf" that Rust accepts
+    let is_literal = text
+        .chars()
+        .all(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+        && text.chars().any(|c| c.is_ascii_digit());
+    if !is_literal {
+        return None;
+    }
+    let r = text.parse::<f64>().ok()?;
+    Some(real_to_integer(r).unwrap_or(Value::Real(r)))
+}
+
+/// Converts the real to an integer if that keeps its exact value.
+fn real_to_integer(r: f64) -> Option<Value> {
+    let i = r as i64;
+    if Value::Integer(i) == Value::Real(r) {
+        Some(Value::Integer(i))
+    } else {
+        None
+    }
+}
+
+/// Storage class values must have to be stored in a STRICT column of
+/// `datatype`, if there is one.
+pub fn strict_storage_class(datatype: &str) -> Option<&'static str> {
+    match datatype {
+        "INT" | "INTEGER" => Some("INTEGER"),
+        "REAL" => Some("REAL"),
+        "TEXT" => Some("TEXT"),
+        "BLOB" => Some("BLOB"),
+        _ => None,
+    }
+}
+
+/// Name of the storage class of the value, as used in error messages.
+pub fn storage_class(value: &Value) -> &'static str {
+    match value {
+        Value::Integer(_) => "INTEGER",
+        Value::Real(_) => "REAL",
+        Value::Text(_) => "TEXT",
+        Value::Blob(_) => "BLOB",
+        Value::Null => "NULL",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn affinity_is_derived_from_the_declared_type() {
+        assert_eq!(Affinity::of(Some("BIGINT")), Affinity::Integer);
+        assert_eq!(Affinity::of(Some("VARCHAR")), Affinity::Text);
+        assert_eq!(Affinity::of(Some("DOUBLE")), Affinity::Real);
+        assert_eq!(Affinity::of(Some("DECIMAL")), Affinity::Numeric);
+        assert_eq!(Affinity::of(Some("blob")), Affinity::Blob);
+        assert_eq!(Affinity::of(None), Affinity::Blob);
+    }
+
+    #[test]
+    fn values_are_converted_only_without_losing_information() {
+        let text = |s: &str| Value::Text(s.to_string());
+        assert_eq!(Affinity::Integer.apply(text(" 42 ")), Value::Integer(42));
+        assert_eq!(Affinity::Integer.apply(Value::Real(3.0)), Value::Integer(3));
+        assert_eq!(Affinity::Integer.apply(Value::Real(3.5)), Value::Real(3.5));
+        assert_eq!(Affinity::Numeric.apply(text("1e2")), Value::Integer(100));
+        assert_eq!(Affinity::Numeric.apply(text("inf")), text("inf"));
+        assert_eq!(Affinity::Real.apply(Value::Integer(2)), Value::Real(2.0));
+        assert_eq!(Affinity::Text.apply(Value::Real(2.0)), text("2.0"));
+        assert_eq!(Affinity::Blob.apply(text("7")), text("7"));
+        assert_eq!(Affinity::Text.apply(Value::Null), Value::Null);
+    }
+}
@@ -1,8 +1,74 @@
-use super::BPTree;
-use crate::{ast::Value, bptree};
+use super::{BPTree, Entries, IndexTree};
+use crate::ast::{Tuple, Value};
+use crate::bptree;
 
-impl BPTree for bptree::BPTree<Value, Vec<Value>> {
-    fn insert(&mut self, key: Value, value: Vec<Value>) -> Result<(), String> {
+impl BPTree for bptree::BPTree<Tuple, Vec<Value>> {
+    type Index = bptree::BPTree<Tuple, Tuple>;
+
+    fn insert(&mut self, key: Tuple, value: Vec<Value>) -> Result<(), String> {
         self.insert(bptree::Entry::new(key, value))
     }
+
+    fn update(&mut self, key: &Tuple, value: Vec<Value>) -> Result<(), String> {
+        self.update(key, value).map(|_| ())
+    }
+
+    fn remove(&mut self, key: &Tuple) -> Result<bool, String> {
+        self.remove(key).map(|row| row.is_some())
+    }
+
+    fn get(&self, key: &Tuple) -> Result<Option<Vec<Value>>, String> {
+        self.get(key)
+    }
+
+    fn last_key(&self) -> Result<Option<Tuple>, String> {
+        self.last().map(|entry| entry.map(|(key, _)| key))
+    }
+
+    fn free(&mut self) -> Result<(), String> {
+        self.clone().free()
+    }
+
+    fn scan(&self) -> Result<Box<dyn Iterator<Item = Result<Vec<Value>, String>>>, String> {
+        let rows = self.range(..)?.map(|entry| entry.map(|(_, row)| row));
+        Ok(Box::new(rows))
+    }
+
+    fn new_index(&self) -> Result<Self::Index, String> {
+        self.new_sibling()
+    }
+
+    fn open(&self, root_page: u32) -> Self {
+        self.open_sibling(root_page)
+    }
+
+    fn open_index(&self, root_page: u32) -> Self::Index {
+        self.open_sibling(root_page)
+    }
+
+    fn root_page(&self) -> u32 {
+        self.root_page()
+    }
+}
+
+impl IndexTree for bptree::BPTree<Tuple, Tuple> {
+    fn insert(&mut self, key: Tuple, primary_key: Tuple) -> Result<(), String> {
+        self.insert(bptree::Entry::new(key, primary_key))
+    }
+
+    fn remove(&mut self, key: &Tuple) -> Result<bool, String> {
+        self.remove(key).map(|primary_key| primary_key.is_some())
+    }
+
+    fn scan_from(&self, start: &Tuple) -> Result<Entries, String> {
+        Ok(Box::new(self.range(start.clone()..)?))
+    }
+
+    fn free(&mut self) -> Result<(), String> {
+        self.clone().free()
+    }
+
+    fn root_page(&self) -> u32 {
+        self.root_page()
+    }
 }
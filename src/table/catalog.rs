@@ -0,0 +1,167 @@
+//! Catalog of the database, a table on its first page listing the tables
+//! and indexes with the statements they were created by, like the
+//! `sqlite_schema` table of SQLite.
+
+use super::{BPTree, Table};
+use crate::ast::{self, Ast, Column, IndexSchema, Value};
+use crate::executor::SCHEMA_TABLE;
+use crate::sqlite3;
+use std::collections::HashMap;
+
+/// Page the root of the tree of the catalog is on
+pub const ROOT_PAGE: u32 = 1;
+
+/// Schema of the catalog, whose rows describe a table or an index each.
+/// Automatic indexes have no SQL, since their table creates them.
+pub fn schema() -> ast::TableSchema {
+    let columns = [
+        ("type", "TEXT"),
+        ("name", "TEXT"),
+        ("tbl_name", "TEXT"),
+        ("rootpage", "INTEGER"),
+        ("sql", "TEXT"),
+    ];
+    let columns = columns
+        .iter()
+        .map(|(name, datatype)| Column::new(name, Some(datatype), false))
+        .collect();
+    ast::TableSchema::new(SCHEMA_TABLE, columns)
+}
+
+/// Opens the catalog whose rows are stored in `rows`, along with the
+/// tables it lists, whose trees are stored like its own.
+pub fn open<T: BPTree + 'static>(rows: T) -> Result<(Table<T>, Vec<Table<T>>), String> {
+    let catalog = Table::new(schema(), rows)?;
+    let mut root_pages = HashMap::new();
+    let mut tables = vec![];
+    let mut indexes = HashMap::<String, Vec<IndexSchema>>::new();
+    for row in catalog.select_rows()? {
+        let row = row?;
+        let (kind, name, root_page, sql) = match &row[..] {
+            [Value::Text(kind), Value::Text(name), _, Value::Integer(root_page), sql, _] => {
+                (kind, name, *root_page as u32, sql)
+            }
+            _ => return Err("malformed database schema".to_string()),
+        };
+        let malformed = || format!("malformed database schema ({})", name);
+        root_pages.insert(name.clone(), root_page);
+        match (kind.as_str(), sql) {
+            ("table", Value::Text(sql)) => match parse(sql).ok_or_else(malformed)? {
+                Ast::Create(schema) => tables.push((schema, root_page)),
+                _ => return Err(malformed()),
+            },
+            ("index", Value::Text(sql)) => match parse(sql).ok_or_else(malformed)? {
+                Ast::CreateIndex(index, _) => indexes
+                    .entry(index.table_name.to_lowercase())
+                    .or_default()
+                    .push(index),
+                _ => return Err(malformed()),
+            },
+            ("index", Value::Null) => {}
+            _ => return Err(malformed()),
+        }
+    }
+    let tables = tables
+        .into_iter()
+        .map(|(schema, root_page)| {
+            let indexes = indexes
+                .remove(&schema.name.to_lowercase())
+                .unwrap_or_default();
+            let rows = catalog.rows.open(root_page);
+            Table::open(schema, rows, indexes, &root_pages)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok((catalog, tables))
+}
+
+/// Statement stored in the catalog, which leaves out its semicolon.
+fn parse(sql: &str) -> Option<Ast> {
+    sqlite3::AstParser::new().parse(&format!("{};", sql)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Value;
+    use crate::executor::testing::{self, in_memory, open};
+
+    #[test]
+    fn tables_and_indexes_are_opened_as_the_catalog_lists_them() {
+        let pager = in_memory();
+        let mut executor = open(&pager);
+        let mut run = |statement| testing::run(&mut executor, &pager, statement);
+        run("CREATE TABLE pets(id INTEGER PRIMARY KEY, name TEXT UNIQUE);").unwrap();
+        run("CREATE TABLE toys(name TEXT, pet INTEGER, UNIQUE (pet, name));").unwrap();
+        run("CREATE INDEX toy_names ON toys(name);").unwrap();
+        run("ALTER TABLE toys ADD COLUMN price REAL DEFAULT 0;").unwrap();
+        run("ALTER TABLE toys RENAME TO presents;").unwrap();
+        run("INSERT INTO pets VALUES (1, 'rex');").unwrap();
+        run("INSERT INTO presents VALUES ('ball', 1, 2.5);").unwrap();
+        run("BEGIN;").unwrap();
+        run("CREATE TABLE walks(pet INTEGER);").unwrap();
+        run("ROLLBACK;").unwrap();
+        assert_eq!(
+            run("INSERT INTO sqlite_schema VALUES ('table', 'x', 'x', 1, NULL);"),
+            Err("table sqlite_schema may not be modified".to_string())
+        );
+
+        let text = |text: &str| Value::Text(text.to_string());
+        let schema = vec![
+            vec![
+                text("table"),
+                text("pets"),
+                text("CREATE TABLE pets (id INTEGER PRIMARY KEY, name TEXT UNIQUE)"),
+            ],
+            vec![text("index"), text("sqlite_autoindex_pets_1"), Value::Null],
+            vec![
+                text("table"),
+                text("presents"),
+                text(
+                    "CREATE TABLE presents (name TEXT, pet INTEGER, price REAL DEFAULT 0, \
+                    UNIQUE (pet, name))",
+                ),
+            ],
+            vec![
+                text("index"),
+                text("sqlite_autoindex_presents_1"),
+                Value::Null,
+            ],
+            vec![
+                text("index"),
+                text("toy_names"),
+                text("CREATE INDEX toy_names ON presents (name)"),
+            ],
+        ];
+        assert_eq!(
+            run("SELECT type, name, sql FROM sqlite_schema;"),
+            Ok(schema.clone())
+        );
+
+        let mut executor = open(&pager);
+        let mut run = |statement| testing::run(&mut executor, &pager, statement);
+        assert_eq!(
+            run("SELECT type, name, sql FROM sqlite_schema;"),
+            Ok(schema)
+        );
+        assert_eq!(
+            run("INSERT INTO pets VALUES (2, 'rex');"),
+            Err("UNIQUE constraint failed: pets.name".to_string())
+        );
+        assert_eq!(
+            run("INSERT INTO presents VALUES ('ball', 1, 3);"),
+            Err("UNIQUE constraint failed: presents.pet, presents.name".to_string())
+        );
+        assert_eq!(
+            run("SELECT pet, price FROM presents WHERE name = 'ball';"),
+            Ok(vec![vec![Value::Integer(1), Value::Real(2.5)]])
+        );
+        run("DROP TABLE presents;").unwrap();
+        assert_eq!(
+            run("SELECT name FROM sqlite_schema;"),
+            Ok(vec![
+                vec![text("pets")],
+                vec![text("sqlite_autoindex_pets_1")]
+            ])
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use super::IndexTree;
+use crate::ast::{Tuple, Value};
+use std::ops::Bound;
+
+/// Secondary index of a table, whose entries map the values of the indexed
+/// columns of each row followed by the values of its primary key to that
+/// primary key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index<I: IndexTree> {
+    pub name: String,
+    /// Indices of the indexed columns in the rows
+    pub columns: Vec<usize>,
+    pub unique: bool,
+    tree: I,
+}
+
+impl<I: IndexTree> Index<I> {
+    pub fn new(name: &str, columns: Vec<usize>, unique: bool, tree: I) -> Index<I> {
+        Index {
+            name: name.to_lowercase(),
+            columns,
+            unique,
+            tree,
+        }
+    }
+
+    fn values(&self, row: &[Value]) -> Vec<Value> {
+        self.columns.iter().map(|i| row[*i].clone()).collect()
+    }
+
+    fn key(&self, row: &[Value], primary_key: &Tuple) -> Tuple {
+        let mut key = self.values(row);
+        key.extend(primary_key.0.iter().cloned());
+        Tuple(key)
+    }
+
+    pub fn insert(&mut self, row: &[Value], primary_key: &Tuple) -> Result<(), String> {
+        let key = self.key(row, primary_key);
+        self.tree.insert(key, primary_key.clone())
+    }
+
+    pub fn remove(&mut self, row: &[Value], primary_key: &Tuple) -> Result<(), String> {
+        let key = self.key(row, primary_key);
+        self.tree.remove(&key).map(|_| ())
+    }
+
+    /// Whether the index is unique and a row other than the one stored under
+    /// `primary_key` has the values of the row. NULLs differ from every
+    /// value, so rows with one never conflict.
+    pub fn conflicts(&self, row: &[Value], primary_key: &Tuple) -> Result<bool, String> {
+        let values = self.values(row);
+        if !self.unique || values.contains(&Value::Null) {
+            return Ok(false);
+        }
+        for entry in self.tree.scan_from(&Tuple(values.clone()))? {
+            let (key, other) = entry?;
+            if key.0[..values.len()] != values[..] {
+                break;
+            }
+            if other != *primary_key {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Primary keys of the rows whose value of the first indexed column is
+    /// in the range, in the order of the index.
+    pub fn primary_keys(&self, range: (Bound<Value>, Bound<Value>)) -> Result<Vec<Tuple>, String> {
+        let (start, end) = range;
+        let from = match &start {
+            Bound::Included(value) | Bound::Excluded(value) => Tuple(vec![value.clone()]),
+            Bound::Unbounded => Tuple(vec![]),
+        };
+        self.tree
+            .scan_from(&from)?
+            .skip_while(|entry| match (entry, &start) {
+                (Ok((key, _)), Bound::Excluded(value)) => key.0[0] == *value,
+                _ => false,
+            })
+            .take_while(|entry| match (entry, &end) {
+                (Ok((key, _)), Bound::Included(value)) => key.0[0] <= *value,
+                (Ok((key, _)), Bound::Excluded(value)) => key.0[0] < *value,
+                _ => true,
+            })
+            .map(|entry| entry.map(|(_, primary_key)| primary_key))
+            .collect()
+    }
+
+    pub fn root_page(&self) -> u32 {
+        self.tree.root_page()
+    }
+
+    pub fn free(&mut self) -> Result<(), String> {
+        self.tree.free()
+    }
+}
@@ -1,9 +1,12 @@
-use crate::ast::{Column, Value};
-use crate::executor;
+use crate::ast::{
+    self, Column, Expression, ForeignKey, IndexSchema, TableConstraint, Tuple, Value,
+};
+use crate::executor::{self, expression};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::iter::IntoIterator;
 use std::iter::Iterator;
+use std::ops::Bound;
 
 #[cfg(test)]
 extern crate mockers_derive;
@@ -11,7 +14,17 @@ extern crate mockers_derive;
 #[cfg(test)]
 use mockers_derive::mocked;
 
+/// Cursor over the entries of an index tree in key order, ending with the
+/// error of an entry that cannot be read.
+pub type Entries = Box<dyn Iterator<Item = Result<(Tuple, Tuple), String>>>;
+
+mod affinity;
 mod bptree;
+pub mod catalog;
+mod index;
+
+pub use affinity::{Affinity, STRICT_DATATYPES};
+use index::Index;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct IndexedColumn {
@@ -23,10 +36,52 @@ impl executor::Column for IndexedColumn {
     fn name(&self) -> &String {
         &self.column.name
     }
+
+    fn affinity(&self) -> Affinity {
+        Affinity::of(self.column.datatype.as_deref())
+    }
+}
+
+pub trait BPTree: Clone {
+    /// Tree the entries of the indexes of the table are stored in
+    type Index: IndexTree;
+
+    fn insert(&mut self, key: Tuple, value: Vec<Value>) -> Result<(), String>;
+    /// Replaces the row stored under `key`.
+    fn update(&mut self, key: &Tuple, value: Vec<Value>) -> Result<(), String>;
+    /// Removes the row stored under `key`, returning whether there was one.
+    fn remove(&mut self, key: &Tuple) -> Result<bool, String>;
+    /// Row stored under `key`, if there is one.
+    fn get(&self, key: &Tuple) -> Result<Option<Vec<Value>>, String>;
+    /// Largest key stored in the tree, if there is one.
+    fn last_key(&self) -> Result<Option<Tuple>, String>;
+    /// Frees the pages of the tree, which must not be used afterwards.
+    fn free(&mut self) -> Result<(), String>;
+    /// Cursor over the rows in primary key order, reading them from the
+    /// tree as it advances until a page cannot be read.
+    fn scan(&self) -> Result<executor::Rows, String>;
+    /// Creates an empty tree for an index of the table.
+    fn new_index(&self) -> Result<Self::Index, String>;
+    /// Opens the tree of the rows of another table whose root is on
+    /// `root_page`, stored like this one.
+    fn open(&self, root_page: u32) -> Self;
+    /// Opens the tree of an index of the table whose root is on `root_page`.
+    fn open_index(&self, root_page: u32) -> Self::Index;
+    /// Page the root of the tree is on, which it can be opened from.
+    fn root_page(&self) -> u32;
 }
 
-pub trait BPTree: IntoIterator<Item = Vec<Value>> + Clone {
-    fn insert(&mut self, key: Value, value: Vec<Value>) -> Result<(), String>;
+/// Tree of the entries of an index, which map a tuple to a primary key.
+pub trait IndexTree: Clone {
+    fn insert(&mut self, key: Tuple, primary_key: Tuple) -> Result<(), String>;
+    /// Removes the entry of `key`, returning whether there was one.
+    fn remove(&mut self, key: &Tuple) -> Result<bool, String>;
+    /// Cursor over the entries from `start` on, in key order.
+    fn scan_from(&self, start: &Tuple) -> Result<Entries, String>;
+    /// Frees the pages of the tree, which must not be used afterwards.
+    fn free(&mut self) -> Result<(), String>;
+    /// Page the root of the tree is on, which it can be opened from.
+    fn root_page(&self) -> u32;
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -34,36 +89,147 @@ pub struct Table<T: BPTree> {
     pub name: String,
     rows: T,
     columns: HashMap<String, IndexedColumn>,
-    pk_idx: usize,
+    /// Indices in the rows of the columns of the primary key, whose values
+    /// make up the keys of the rows in the tree. Tables without a PRIMARY
+    /// KEY are keyed by a rowid stored after the values of the columns.
+    primary_key: Vec<usize>,
+    /// FOREIGN KEY constraints of the table, besides the REFERENCES
+    /// constraints of its columns
+    foreign_keys: Vec<ForeignKey>,
+    strict: bool,
+    #[serde(skip)]
+    indexes: Vec<Index<T::Index>>,
 }
 
 impl<T: BPTree + 'static> executor::Table for Table<T> {
-    fn select_rows(&self) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+    fn select_rows(&self) -> Result<executor::Rows, String> {
         self.select_rows()
     }
     fn select_rows_with_named_columns(
         &self,
         column_names: &Vec<String>,
-    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+    ) -> Result<executor::Rows, String> {
         self.select_rows_with_named_columns(column_names)
     }
-    fn insert_row(&mut self, row: Vec<Value>) -> Result<&mut dyn executor::Table, String> {
+    fn select_row(&self, key: &Value) -> Result<Option<Vec<Value>>, String> {
+        self.rows.get(&Tuple(vec![key.clone()]))
+    }
+    fn insert_row(&mut self, row: Vec<Value>) -> Result<Vec<Value>, String> {
         self.insert_row(row)
-            .map(|table| table as &mut dyn executor::Table)
     }
 
     fn insert_row_with_named_columns(
         &mut self,
         row: HashMap<String, Value>,
-    ) -> Result<&mut dyn executor::Table, String> {
+    ) -> Result<Vec<Value>, String> {
         self.insert_row_with_named_columns(row)
-            .map(|table| table as &mut dyn executor::Table)
+    }
+
+    fn update_rows(
+        &mut self,
+        rows: Vec<(Vec<Value>, Vec<Value>)>,
+    ) -> Result<Vec<Vec<Value>>, String> {
+        self.update_rows(rows)
+    }
+
+    fn delete_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<(), String> {
+        self.delete_rows(rows)
+    }
+
+    fn drop_rows(&mut self) -> Result<(), String> {
+        for index in &mut self.indexes {
+            index.free()?;
+        }
+        self.rows.free()
+    }
+
+    fn rename(&mut self, name: &str) {
+        self.rename(name)
+    }
+
+    fn rename_parent(&mut self, old_name: &str, new_name: &str) {
+        self.rename_parent(old_name, new_name)
+    }
+
+    fn add_column(&mut self, column: Column) -> Result<(), String> {
+        self.add_column(column)
+    }
+
+    fn drop_column(&mut self, name: &str) -> Result<(), String> {
+        self.drop_column(name)
+    }
+
+    fn create_index(&mut self, index: IndexSchema) -> Result<(), String> {
+        self.create_index(index)
+    }
+
+    fn schema(&self) -> ast::TableSchema {
+        self.schema()
+    }
+
+    fn root_page(&self) -> u32 {
+        self.rows.root_page()
+    }
+
+    fn indexes(&self) -> Vec<(IndexSchema, u32)> {
+        let mut names = self.columns.values().collect::<Vec<_>>();
+        names.sort_by_key(|column| column.index);
+        self.indexes
+            .iter()
+            .map(|index| {
+                let columns = index
+                    .columns
+                    .iter()
+                    .map(|i| names[*i].column.name.clone())
+                    .collect();
+                let schema = IndexSchema::new(&index.name, &self.name, columns, index.unique);
+                (schema, index.root_page())
+            })
+            .collect()
+    }
+
+    fn index_names(&self) -> Vec<String> {
+        self.indexes
+            .iter()
+            .map(|index| index.name.clone())
+            .collect()
+    }
+
+    fn select_rows_in_range(
+        &self,
+        column: usize,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<Option<executor::Rows>, String> {
+        self.select_rows_in_range(column, range)
     }
 
     fn row_len(&self) -> usize {
         self.row_len()
     }
 
+    fn key_indices(&self) -> Vec<usize> {
+        self.primary_key.clone()
+    }
+
+    fn unique_keys(&self) -> Vec<Vec<usize>> {
+        let primary_key = (!self.has_rowid()).then(|| self.primary_key.clone());
+        let unique_indexes = self.indexes.iter().filter(|index| index.unique);
+        primary_key
+            .into_iter()
+            .chain(unique_indexes.map(|index| index.columns.clone()))
+            .collect()
+    }
+
+    fn foreign_keys(&self) -> Vec<ForeignKey> {
+        let mut columns = self.columns.values().collect::<Vec<_>>();
+        columns.sort_by_key(|column| column.index);
+        columns
+            .into_iter()
+            .filter_map(|column| column.column.references.clone())
+            .chain(self.foreign_keys.iter().cloned())
+            .collect()
+    }
+
     fn name(&self) -> String {
         return self.name.clone();
     }
@@ -77,23 +243,63 @@ impl<T: BPTree + 'static> executor::Table for Table<T> {
 pub trait TableSchema {
     fn table_name(&self) -> String;
     fn columns(&self) -> Vec<Column>;
+    fn constraints(&self) -> Vec<TableConstraint>;
+    fn is_strict(&self) -> bool;
     fn validate(&self) -> Result<(), String>;
 }
 
 impl<T: BPTree + 'static> Table<T> {
-    pub fn new<'a, TS>(table_schema: TS, rows: T) -> Result<Table<T>, String>
-    where
-        TS: TableSchema,
-    {
+    pub fn new<TS: TableSchema>(table_schema: TS, rows: T) -> Result<Table<T>, String> {
+        let (mut table, automatic_indexes) = Table::without_indexes(table_schema, rows)?;
+        for index in automatic_indexes {
+            table.create_index(index)?;
+        }
+
+        Ok(table)
+    }
+
+    /// Opens a table stored before, with its automatic indexes and the
+    /// `indexes` created on it, whose trees are found by their name in
+    /// `root_pages`.
+    pub fn open<TS: TableSchema>(
+        table_schema: TS,
+        rows: T,
+        indexes: Vec<IndexSchema>,
+        root_pages: &HashMap<String, u32>,
+    ) -> Result<Table<T>, String> {
+        let (mut table, automatic_indexes) = Table::without_indexes(table_schema, rows)?;
+        for index in automatic_indexes.into_iter().chain(indexes) {
+            let name = index.name.to_lowercase();
+            let root_page = root_pages.get(&name).ok_or(format!(
+                "malformed database schema ({}) - missing index",
+                name
+            ))?;
+            let columns = table.index_columns(&index)?;
+            let tree = table.rows.open_index(*root_page);
+            table
+                .indexes
+                .push(Index::new(&name, columns, index.unique, tree));
+        }
+
+        Ok(table)
+    }
+
+    /// Table of the schema without its indexes, with the automatic indexes
+    /// of its UNIQUE constraints it has to get.
+    fn without_indexes<TS: TableSchema>(
+        table_schema: TS,
+        rows: T,
+    ) -> Result<(Table<T>, Vec<IndexSchema>), String> {
         table_schema.validate()?;
         let columns = table_schema.columns();
         let table_name = table_schema.table_name();
-        let mut pk_idx = 0;
+        let num_columns = columns.len();
+        let mut primary_key = vec![];
 
         let mut mapped_columns = HashMap::new();
         for (i, column) in columns.into_iter().enumerate() {
             if column.is_primary_key {
-                pk_idx = i;
+                primary_key.push(i);
             }
 
             mapped_columns.insert(
@@ -104,22 +310,99 @@ impl<T: BPTree + 'static> Table<T> {
                 },
             );
         }
-        return Ok(Table {
+        let mut unique_constraints = vec![];
+        let mut foreign_keys = vec![];
+        for constraint in table_schema.constraints() {
+            match constraint {
+                TableConstraint::PrimaryKey(names) => {
+                    primary_key = names
+                        .iter()
+                        .map(|name| mapped_columns[name].index)
+                        .collect()
+                }
+                TableConstraint::Unique(names) => unique_constraints.push(names),
+                TableConstraint::ForeignKey(foreign_key) => foreign_keys.push(foreign_key),
+            }
+        }
+        if primary_key.is_empty() {
+            primary_key.push(num_columns);
+        }
+        let table = Table {
             name: table_name.to_lowercase(),
             rows,
-            pk_idx,
+            primary_key,
             columns: mapped_columns,
-        });
+            foreign_keys,
+            strict: table_schema.is_strict(),
+            indexes: vec![],
+        };
+        // UNIQUE columns get an index of their own, named like in SQLite,
+        // and so do the UNIQUE constraints of the table after them
+        let mut unique_columns = table
+            .columns
+            .values()
+            .filter(|column| column.column.unique && !column.column.is_primary_key)
+            .collect::<Vec<_>>();
+        unique_columns.sort_by_key(|column| column.index);
+        let unique_columns = unique_columns
+            .iter()
+            .map(|column| vec![column.column.name.clone()])
+            .chain(unique_constraints)
+            .collect::<Vec<_>>();
+        let automatic_indexes = unique_columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, columns)| {
+                let name = format!("sqlite_autoindex_{}_{}", table.name, i + 1);
+                IndexSchema::new(&name, &table.name, columns, true)
+            })
+            .collect();
+
+        Ok((table, automatic_indexes))
     }
-    pub fn select_rows(&self) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
-        return Ok(Box::new(self.rows.clone().into_iter()));
+
+    /// Schema the table would be created with as it is now, which spells
+    /// out the primary key and UNIQUE constraints over several columns as
+    /// constraints of the table.
+    pub fn schema(&self) -> ast::TableSchema {
+        let mut columns = self.columns.values().collect::<Vec<_>>();
+        columns.sort_by_key(|column| column.index);
+        let columns = columns
+            .into_iter()
+            .map(|column| column.column.clone())
+            .collect::<Vec<_>>();
+        let names = |indices: &[usize]| indices.iter().map(|i| columns[*i].name.clone()).collect();
+        let mut constraints = vec![];
+        if !self.has_rowid() && !columns.iter().any(|column| column.is_primary_key) {
+            constraints.push(TableConstraint::PrimaryKey(names(&self.primary_key)));
+        }
+        for index in self.automatic_indexes() {
+            if !matches!(index.columns[..], [i] if columns[i].unique) {
+                constraints.push(TableConstraint::Unique(names(&index.columns)));
+            }
+        }
+        constraints.extend(
+            self.foreign_keys
+                .iter()
+                .cloned()
+                .map(TableConstraint::ForeignKey),
+        );
+        ast::TableSchema {
+            name: self.name.clone(),
+            columns,
+            constraints,
+            strict: self.strict,
+        }
+    }
+    pub fn select_rows(&self) -> Result<executor::Rows, String> {
+        self.rows.scan()
     }
     pub fn select_rows_with_named_columns(
         &self,
         column_names: &Vec<String>,
-    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+    ) -> Result<executor::Rows, String> {
         for column_name in column_names {
-            if self.columns.get(column_name).is_none() {
+            if self.column_index(column_name).is_none() {
                 return Err(format!("no such column: {}", column_name));
             }
         }
@@ -129,79 +412,504 @@ impl<T: BPTree + 'static> Table<T> {
             return Err(result.unwrap_err());
         }
 
-        return Ok(Box::new(
-            self.rows
-                .clone()
-                .into_iter()
-                .map(move |row| {
-                    let mut filtered_row = vec![];
-                    for i in &indices {
-                        filtered_row.push(row[*i].clone())
-                    }
-                    filtered_row
-                })
-                .into_iter(),
-        ));
+        return Ok(Box::new(self.rows.scan()?.map(move |row| {
+            let row = row?;
+            let mut filtered_row = vec![];
+            for i in &indices {
+                filtered_row.push(row[*i].clone())
+            }
+            Ok(filtered_row)
+        })));
     }
     pub fn columns(&self) -> Vec<Box<dyn executor::Column>> {
         let mut columns = vec![];
         for pair in &self.columns {
             let (_, column) = pair;
-            columns.push(Box::new(column.clone()) as Box<dyn executor::Column>);
+            columns.push(column.clone());
         }
+        columns.sort_by_key(|column| column.index);
 
         columns
+            .into_iter()
+            .map(|column| Box::new(column) as Box<dyn executor::Column>)
+            .collect()
     }
-    pub fn insert_row(&mut self, row: Vec<Value>) -> Result<&mut Table<T>, String> {
+    /// Stores the row, returning it as it was stored.
+    pub fn insert_row(&mut self, row: Vec<Value>) -> Result<Vec<Value>, String> {
         if row.len() != self.row_len() {
             return Err(self.wrong_num_of_columns_error(row.len()));
         }
 
-        self.rows.insert(row[self.pk_idx].clone(), row)?;
-
-        Ok(self)
+        let mut row = self.apply_affinities(row)?;
+        if self.has_rowid() {
+            row.push(Value::Null);
+        }
+        self.insert(row)
     }
 
     fn insert_row_with_named_columns(
         &mut self,
         row: HashMap<String, Value>,
-    ) -> Result<&mut Table<T>, String> {
-        if row.len() > self.row_len() {
+    ) -> Result<Vec<Value>, String> {
+        if row.len() > self.num_values() {
             return Err(self.wrong_num_of_columns_error(row.len()));
         }
 
         let mut indices = vec![];
         let column_names = row.keys().map(|k| k.clone()).collect();
-        let result = self.indices(&column_names, &mut indices);
-        if result.is_err() {
-            return result.map(|_| self);
-        }
+        self.indices(&column_names, &mut indices)?;
 
-        let mut row_vec = vec![Value::Null; self.row_len()];
+        let mut row_vec = vec![Value::Null; self.num_values()];
+        for column in self.columns.values() {
+            if let Some(default) = &column.column.default {
+                row_vec[column.index] = evaluate_constant(default);
+            }
+        }
         for kv in indices.iter().zip(row.values()) {
             let (index, value) = kv;
             row_vec[*index] = value.clone();
         }
 
-        self.rows.insert(row_vec[self.pk_idx].clone(), row_vec)?;
+        let row_vec = self.apply_affinities(row_vec)?;
+        self.insert(row_vec)
+    }
 
-        Ok(self)
+    /// Replaces each row paired with its new values, returning the rows as
+    /// they were stored. When the primary key changes, the row moves to the
+    /// new key, which must not be taken yet.
+    pub fn update_rows(
+        &mut self,
+        rows: Vec<(Vec<Value>, Vec<Value>)>,
+    ) -> Result<Vec<Vec<Value>>, String> {
+        let mut updated_rows = vec![];
+        for (row, updated_row) in rows {
+            if updated_row.len() != self.num_values() {
+                return Err(self.wrong_num_of_columns_error(updated_row.len()));
+            }
+            let mut updated_row = self.apply_affinities(updated_row)?;
+            if let Some(rowid_index) = self.rowid_index() {
+                updated_row[rowid_index] = rowid(updated_row[rowid_index].clone())?;
+            }
+            self.check_constraints(&updated_row)?;
+            let key = &self.key(&row);
+            let updated_key = self.key(&updated_row);
+            if *key != updated_key {
+                self.check_primary_key(&updated_key)?;
+            }
+            // The row does not conflict with its own values
+            self.check_unique(&updated_row, key)?;
+            for index in &mut self.indexes {
+                index.remove(&row, key)?;
+            }
+            if *key == updated_key {
+                self.rows.update(key, updated_row.clone())?;
+            } else {
+                self.rows.remove(key)?;
+                self.rows.insert(updated_key.clone(), updated_row.clone())?;
+            }
+            for index in &mut self.indexes {
+                index.insert(&updated_row, &updated_key)?;
+            }
+            updated_rows.push(updated_row);
+        }
+
+        Ok(updated_rows)
     }
 
-    fn indices(&self, column_names: &Vec<String>, dst: &mut Vec<usize>) -> Result<(), String> {
-        for column_name in column_names {
-            if !self.columns.contains_key(column_name) {
+    pub fn delete_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<(), String> {
+        for row in rows {
+            let key = &self.key(&row);
+            self.rows.remove(key)?;
+            for index in &mut self.indexes {
+                index.remove(&row, key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renames the table and its automatic indexes.
+    pub fn rename(&mut self, name: &str) {
+        let name = name.to_lowercase();
+        let prefix = format!("sqlite_autoindex_{}_", self.name);
+        for index in &mut self.indexes {
+            if let Some(number) = index.name.strip_prefix(&prefix) {
+                index.name = format!("sqlite_autoindex_{}_{}", name, number);
+            }
+        }
+        self.name = name;
+    }
+
+    /// Makes the foreign keys referring to the table called `old_name`
+    /// refer to `new_name` instead.
+    pub fn rename_parent(&mut self, old_name: &str, new_name: &str) {
+        let references = self
+            .columns
+            .values_mut()
+            .filter_map(|column| column.column.references.as_mut());
+        for foreign_key in references.chain(&mut self.foreign_keys) {
+            if foreign_key.parent_table == old_name {
+                foreign_key.parent_table = new_name.to_string();
+            }
+        }
+    }
+
+    /// Adds a column after the existing ones, which takes its default value
+    /// in the stored rows.
+    pub fn add_column(&mut self, column: Column) -> Result<(), String> {
+        if self.columns.contains_key(&column.name) {
+            return Err(format!("duplicate column name: {}", column.name));
+        }
+        if column.is_primary_key {
+            return Err("Cannot add a PRIMARY KEY column".to_string());
+        }
+        if column.unique {
+            return Err("Cannot add a UNIQUE column".to_string());
+        }
+        let mut column_indices = self.column_indices();
+        column_indices.insert(column.name.clone(), self.row_len());
+        column.validate_constraints(&column_indices)?;
+        let default = column
+            .default
+            .as_ref()
+            .map_or(Value::Null, evaluate_constant);
+        let default = Affinity::of(column.datatype.as_deref()).apply(default);
+        if column.not_null && default == Value::Null {
+            return Err("Cannot add a NOT NULL column with default value NULL".to_string());
+        }
+        if self.strict {
+            let datatype = column.datatype.as_deref().unwrap_or_default();
+            if !STRICT_DATATYPES.contains(&datatype) {
+                return Err(format!(
+                    "unknown datatype for {}.{}: \"{}\"",
+                    self.name, column.name, datatype
+                ));
+            }
+        }
+
+        let rows = self.rows.scan()?.collect::<Result<Vec<_>, _>>()?;
+        let index = self.row_len();
+        // The rowid stays after the values of the columns
+        if self.has_rowid() {
+            self.primary_key[0] += 1;
+        }
+        self.columns
+            .insert(column.name.clone(), IndexedColumn { column, index });
+        let mut updated_rows = vec![];
+        for mut row in rows {
+            row.insert(index, default.clone());
+            self.check_constraints(&row)?;
+            updated_rows.push(row);
+        }
+        for row in updated_rows {
+            self.rows.update(&self.key(&row), row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the column and its values from the stored rows. The columns
+    /// of the primary key and of UNIQUE constraints cannot be dropped.
+    pub fn drop_column(&mut self, name: &str) -> Result<(), String> {
+        let is_unique = |index: &usize| {
+            self.automatic_indexes()
+                .any(|unique| unique.columns.contains(index))
+        };
+        let index = match self.columns.get(name) {
+            None => return Err(format!("no such column: \"{}\"", name)),
+            Some(column) if self.primary_key.contains(&column.index) => {
+                return Err(format!("cannot drop PRIMARY KEY column: \"{}\"", name))
+            }
+            Some(column) if is_unique(&column.index) => {
+                return Err(format!("cannot drop UNIQUE column: \"{}\"", name))
+            }
+            Some(column) => column.index,
+        };
+        let mut column_indices = self.column_indices();
+        column_indices.remove(name);
+        let checks = self
+            .columns
+            .values()
+            .filter(|column| column.column.name != name)
+            .flat_map(|column| &column.column.checks);
+        for check in checks {
+            if let Err(err) = expression::validate(check, &column_indices) {
+                return Err(format!(
+                    "error in table {} after drop column: {}",
+                    self.name, err
+                ));
+            }
+        }
+        let mut foreign_key_columns = self.foreign_keys.iter().flat_map(|key| &key.columns);
+        if foreign_key_columns.any(|column| column == name) {
+            return Err(format!(
+                "error in table {} after drop column: unknown column \"{}\" in foreign key \
+                definition",
+                self.name, name
+            ));
+        }
+        let indexes = &self.indexes;
+        if let Some(indexed) = indexes
+            .iter()
+            .find(|indexed| indexed.columns.contains(&index))
+        {
+            return Err(format!(
+                "error in index {} after drop column: no such column: {}",
+                indexed.name, name
+            ));
+        }
+
+        let rows = self.rows.scan()?.collect::<Result<Vec<_>, _>>()?;
+        self.columns.remove(name);
+        for column in self.columns.values_mut() {
+            if column.index > index {
+                column.index -= 1;
+            }
+        }
+        let indexed_columns = self.indexes.iter_mut().flat_map(|index| &mut index.columns);
+        for indexed_column in self.primary_key.iter_mut().chain(indexed_columns) {
+            if *indexed_column > index {
+                *indexed_column -= 1;
+            }
+        }
+        for mut row in rows {
+            row.remove(index);
+            self.rows.update(&self.key(&row), row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Builds an index of the rows, failing if it is unique and some of them
+    /// have the same values.
+    pub fn create_index(&mut self, index: IndexSchema) -> Result<(), String> {
+        let columns = self.index_columns(&index)?;
+        let mut new_index = Index::new(&index.name, columns, index.unique, self.rows.new_index()?);
+        for row in self.rows.scan()? {
+            let row = row?;
+            let key = &self.key(&row);
+            if new_index.conflicts(&row, key)? {
+                return Err(self.unique_error(&new_index.columns));
+            }
+            new_index.insert(&row, key)?;
+        }
+        self.indexes.push(new_index);
+
+        Ok(())
+    }
+
+    /// Indices in the rows of the columns of the index.
+    fn index_columns(&self, index: &IndexSchema) -> Result<Vec<usize>, String> {
+        index
+            .columns
+            .iter()
+            .map(|name| match self.columns.get(name) {
+                None => Err(format!("no such column: {}", name)),
+                Some(column) => Ok(column.index),
+            })
+            .collect()
+    }
+
+    /// Indexes of the UNIQUE constraints of the table, in the order they
+    /// are numbered in.
+    fn automatic_indexes(&self) -> impl Iterator<Item = &Index<T::Index>> {
+        self.indexes
+            .iter()
+            .filter(|index| index.name.starts_with("sqlite_autoindex_"))
+    }
+
+    /// Rows whose value of the column at `column` is in the range, read
+    /// through an index starting with the column. None if there is no such
+    /// index.
+    pub fn select_rows_in_range(
+        &self,
+        column: usize,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<Option<executor::Rows>, String> {
+        let index = match self.indexes.iter().find(|index| index.columns[0] == column) {
+            None => return Ok(None),
+            Some(index) => index,
+        };
+        let mut rows = vec![];
+        for key in index.primary_keys(range)? {
+            rows.extend(self.rows.get(&key)?.map(Ok));
+        }
+        Ok(Some(Box::new(rows.into_iter())))
+    }
+
+    /// Stores a new row, adding it to the indexes. A NULL rowid is replaced
+    /// by one more than the largest rowid so far, or 1 for the first row.
+    fn insert(&mut self, mut row: Vec<Value>) -> Result<Vec<Value>, String> {
+        if let Some(rowid_index) = self.rowid_index() {
+            row[rowid_index] = match &row[rowid_index] {
+                Value::Null => match self.rows.last_key()?.as_ref().map(|key| &key.0[..]) {
+                    None => Value::Integer(1),
+                    Some([Value::Integer(i64::MAX)]) => {
+                        return Err("database or disk is full".to_string())
+                    }
+                    Some([Value::Integer(last)]) => Value::Integer(last + 1),
+                    Some(key) => return Err(format!("malformed rowid: {}", Tuple(key.to_vec()))),
+                },
+                value => rowid(value.clone())?,
+            };
+        }
+        let key = self.key(&row);
+        self.check_constraints(&row)?;
+        self.check_primary_key(&key)?;
+        self.check_unique(&row, &key)?;
+        self.rows.insert(key.clone(), row.clone())?;
+        for index in &mut self.indexes {
+            index.insert(&row, &key)?;
+        }
+
+        Ok(row)
+    }
+
+    /// Checks the NOT NULL constraints of the columns, then their CHECK
+    /// constraints, which fail when false but not when NULL.
+    fn check_constraints(&self, row: &[Value]) -> Result<(), String> {
+        let mut columns = self.columns.values().collect::<Vec<_>>();
+        columns.sort_by_key(|column| column.index);
+        for column in &columns {
+            if column.column.not_null && row[column.index] == Value::Null {
                 return Err(format!(
-                    "table {} has no column named {}",
-                    self.name, column_name
+                    "NOT NULL constraint failed: {}.{}",
+                    self.name, column.column.name
                 ));
             }
-            dst.push(self.columns.get(column_name).unwrap().index);
+        }
+        let column_indices = self.column_indices();
+        let affinities = columns
+            .iter()
+            .map(|column| Affinity::of(column.column.datatype.as_deref()))
+            .collect::<Vec<_>>();
+        for check in columns.iter().flat_map(|column| &column.column.checks) {
+            let value = expression::evaluate(check, &column_indices, &affinities, row);
+            if expression::is_false(&value) {
+                return Err(format!("CHECK constraint failed: {}", check));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Index of each column in the rows.
+    fn column_indices(&self) -> HashMap<String, usize> {
+        self.columns
+            .iter()
+            .map(|(name, column)| (name.clone(), column.index))
+            .collect()
+    }
+
+    /// Checks that no row is stored under the key yet.
+    fn check_primary_key(&self, key: &Tuple) -> Result<(), String> {
+        if self.rows.get(key)?.is_some() {
+            return Err(self.unique_error(&self.primary_key));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that no other row than the one stored under `key` has the
+    /// values of the row in the columns of a unique index.
+    fn check_unique(&self, row: &[Value], key: &Tuple) -> Result<(), String> {
+        for index in &self.indexes {
+            if index.conflicts(row, key)? {
+                return Err(self.unique_error(&index.columns));
+            }
         }
 
         Ok(())
     }
 
+    fn unique_error(&self, columns: &[usize]) -> String {
+        let mut names = self.columns.values().collect::<Vec<_>>();
+        names.sort_by_key(|column| column.index);
+        let names = columns
+            .iter()
+            .map(|i| match names.get(*i) {
+                Some(column) => format!("{}.{}", self.name, column.column.name),
+                None => format!("{}.rowid", self.name),
+            })
+            .collect::<Vec<_>>();
+        format!("UNIQUE constraint failed: {}", names.join(", "))
+    }
+
+    /// Key the row is stored under, made of the values of the columns of
+    /// the primary key.
+    fn key(&self, row: &[Value]) -> Tuple {
+        Tuple(self.primary_key.iter().map(|i| row[*i].clone()).collect())
+    }
+
+    /// Converts each value to the affinity of its column, checking in STRICT
+    /// tables that it ends up with the declared type.
+    fn apply_affinities(&self, mut row: Vec<Value>) -> Result<Vec<Value>, String> {
+        for indexed_column in self.columns.values() {
+            let column = &indexed_column.column;
+            let datatype = column.datatype.as_deref();
+            let value = std::mem::replace(&mut row[indexed_column.index], Value::Null);
+            let value = Affinity::of(datatype).apply(value);
+            if self.strict && value != Value::Null {
+                let expected = datatype.and_then(affinity::strict_storage_class);
+                if let Some(expected) = expected {
+                    if affinity::storage_class(&value) != expected {
+                        return Err(format!(
+                            "cannot store {} value in {} column {}.{}",
+                            affinity::storage_class(&value),
+                            expected,
+                            self.name,
+                            column.name
+                        ));
+                    }
+                }
+            }
+            row[indexed_column.index] = value;
+        }
+
+        Ok(row)
+    }
+
+    fn indices(&self, column_names: &Vec<String>, dst: &mut Vec<usize>) -> Result<(), String> {
+        for column_name in column_names {
+            match self.column_index(column_name) {
+                None => {
+                    return Err(format!(
+                        "table {} has no column named {}",
+                        self.name, column_name
+                    ))
+                }
+                Some(index) => dst.push(index),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Index of the column in the rows. The rowid can be referred to by any
+    /// of its names that no column has.
+    fn column_index(&self, name: &str) -> Option<usize> {
+        match self.columns.get(name) {
+            Some(column) => Some(column.index),
+            None if executor::ROWID_NAMES.contains(&name) => self.rowid_index(),
+            None => None,
+        }
+    }
+
+    /// Index of the rowid in the rows, which only tables without a PRIMARY
+    /// KEY have.
+    fn rowid_index(&self) -> Option<usize> {
+        self.has_rowid().then_some(self.row_len())
+    }
+
+    fn has_rowid(&self) -> bool {
+        self.primary_key == [self.row_len()]
+    }
+
+    /// Number of values in the rows, the rowid included.
+    fn num_values(&self) -> usize {
+        self.row_len() + self.has_rowid() as usize
+    }
+
     fn wrong_num_of_columns_error(&self, num_columns: usize) -> String {
         return format!(
             "table {} has {} columns but {} values were supplied",
@@ -216,31 +924,139 @@ impl<T: BPTree + 'static> Table<T> {
     }
 }
 
+/// Value of an expression that does not refer to any column.
+fn evaluate_constant(expression: &Expression) -> Value {
+    expression::evaluate(expression, &HashMap::new(), &[], &[])
+}
+
+/// Value converted to a rowid, which has to be an integer.
+fn rowid(value: Value) -> Result<Value, String> {
+    match Affinity::Integer.apply(value) {
+        Value::Integer(rowid) => Ok(Value::Integer(rowid)),
+        _ => Err("datatype mismatch".to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::BinaryOperator;
     use mockers::Scenario;
 
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    /// Tree keeping its rows in memory.
     #[derive(Clone)]
-    struct MockBpTree {}
+    struct MockBpTree {
+        rows: Rc<RefCell<BTreeMap<Tuple, Vec<Value>>>>,
+    }
 
     impl MockBpTree {
         fn new() -> MockBpTree {
-            MockBpTree {}
+            MockBpTree {
+                rows: Rc::new(RefCell::new(BTreeMap::new())),
+            }
         }
     }
 
-    impl BPTree for MockBpTree {
-        fn insert(&mut self, key: Value, value: Vec<Value>) -> Result<(), String> {
-            panic!("not implemented")
+    /// Index tree keeping its entries in memory.
+    #[derive(Clone)]
+    struct MockIndexTree {
+        entries: Rc<RefCell<BTreeMap<Tuple, Tuple>>>,
+    }
+
+    impl IndexTree for MockIndexTree {
+        fn insert(&mut self, key: Tuple, value: Tuple) -> Result<(), String> {
+            self.entries.borrow_mut().insert(key, value);
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &Tuple) -> Result<bool, String> {
+            Ok(self.entries.borrow_mut().remove(key).is_some())
+        }
+
+        fn scan_from(&self, start: &Tuple) -> Result<Entries, String> {
+            let entries = self
+                .entries
+                .borrow()
+                .range(start.clone()..)
+                .map(|(key, value)| Ok((key.clone(), value.clone())))
+                .collect::<Vec<_>>();
+            Ok(Box::new(entries.into_iter()))
+        }
+
+        fn free(&mut self) -> Result<(), String> {
+            self.entries.borrow_mut().clear();
+            Ok(())
+        }
+
+        fn root_page(&self) -> u32 {
+            0
         }
     }
 
-    impl IntoIterator for MockBpTree {
-        type Item = Vec<Value>;
-        type IntoIter = ::std::vec::IntoIter<Self::Item>;
-        fn into_iter(self) -> Self::IntoIter {
-            panic!("not implemented")
+    impl BPTree for MockBpTree {
+        type Index = MockIndexTree;
+
+        fn insert(&mut self, key: Tuple, value: Vec<Value>) -> Result<(), String> {
+            if self.rows.borrow().contains_key(&key) {
+                return Err(format!("duplicate entry: {}", key));
+            }
+            self.rows.borrow_mut().insert(key, value);
+            Ok(())
+        }
+
+        fn update(&mut self, key: &Tuple, value: Vec<Value>) -> Result<(), String> {
+            self.rows.borrow_mut().insert(key.clone(), value);
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &Tuple) -> Result<bool, String> {
+            Ok(self.rows.borrow_mut().remove(key).is_some())
+        }
+
+        fn get(&self, key: &Tuple) -> Result<Option<Vec<Value>>, String> {
+            Ok(self.rows.borrow().get(key).cloned())
+        }
+
+        fn last_key(&self) -> Result<Option<Tuple>, String> {
+            Ok(self.rows.borrow().keys().next_back().cloned())
+        }
+
+        fn free(&mut self) -> Result<(), String> {
+            self.rows.borrow_mut().clear();
+            Ok(())
+        }
+
+        fn scan(&self) -> Result<executor::Rows, String> {
+            let rows = self
+                .rows
+                .borrow()
+                .values()
+                .cloned()
+                .map(Ok)
+                .collect::<Vec<_>>();
+            Ok(Box::new(rows.into_iter()))
+        }
+
+        fn new_index(&self) -> Result<MockIndexTree, String> {
+            Ok(MockIndexTree {
+                entries: Rc::new(RefCell::new(BTreeMap::new())),
+            })
+        }
+
+        fn open(&self, _root_page: u32) -> MockBpTree {
+            unimplemented!("trees in memory are not stored on pages")
+        }
+
+        fn open_index(&self, _root_page: u32) -> MockIndexTree {
+            unimplemented!("trees in memory are not stored on pages")
+        }
+
+        fn root_page(&self) -> u32 {
+            0
         }
     }
 
@@ -256,11 +1072,12 @@ mod tests {
                 .table_name()
                 .and_return("animals".to_string()),
         );
-        scenario.expect(
-            table_schema_handle
-                .columns()
-                .and_return(vec![Column::new("feet", false), Column::new("eyes", false)]),
-        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("feet", None, false),
+            Column::new("eyes", None, false),
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
 
         let mut table = Table::new(table_schema, MockBpTree::new()).unwrap();
         let result = table.insert_row(vec![Value::Integer(49)]);
@@ -289,8 +1106,10 @@ mod tests {
         scenario.expect(
             table_schema_handle
                 .columns()
-                .and_return(vec![Column::new("feet", false)]),
+                .and_return(vec![Column::new("feet", None, false)]),
         );
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
 
         let mut table = Table::new(table_schema, MockBpTree::new()).unwrap();
 
@@ -299,4 +1118,514 @@ mod tests {
         let result = table.insert_row_with_named_columns(row);
         assert_eq!(result.is_err(), true);
     }
+
+    #[test]
+    fn values_are_converted_to_the_affinity_of_their_column() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("legs", Some("INTEGER"), true),
+            Column::new("name", Some("VARCHAR"), false),
+            Column::new("weight", Some("REAL"), false),
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let rows = MockBpTree::new();
+        let mut table = Table::new(table_schema, rows.clone()).unwrap();
+        table
+            .insert_row(vec![
+                Value::Text("4".to_string()),
+                Value::Integer(7),
+                Value::Integer(30),
+            ])
+            .unwrap();
+
+        assert_eq!(
+            rows.rows.borrow()[&Tuple(vec![Value::Integer(4)])],
+            vec![
+                Value::Integer(4),
+                Value::Text("7".to_string()),
+                Value::Real(30.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_tables_reject_values_of_the_wrong_type() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![Column::new(
+            "legs",
+            Some("INTEGER"),
+            true,
+        )]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(true));
+
+        let rows = MockBpTree::new();
+        let mut table = Table::new(table_schema, rows.clone()).unwrap();
+        let result = table.insert_row(vec![Value::Text("four".to_string())]);
+        assert_eq!(
+            result.err(),
+            Some("cannot store TEXT value in INTEGER column animals.legs".to_string())
+        );
+
+        table
+            .insert_row(vec![Value::Text("4".to_string())])
+            .unwrap();
+        assert_eq!(rows.rows.borrow().len(), 1);
+    }
+
+    #[test]
+    fn updated_rows_move_when_their_primary_key_changes() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("legs", Some("INTEGER"), true),
+            Column::new("name", Some("TEXT"), false),
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let rows = MockBpTree::new();
+        let mut table = Table::new(table_schema, rows.clone()).unwrap();
+        let cat = vec![Value::Integer(4), Value::Text("cat".to_string())];
+        let bird = vec![Value::Integer(2), Value::Text("bird".to_string())];
+        table.insert_row(cat.clone()).unwrap();
+        table.insert_row(bird.clone()).unwrap();
+
+        let fish = vec![
+            Value::Text("0".to_string()),
+            Value::Text("fish".to_string()),
+        ];
+        let snake = vec![Value::Integer(2), Value::Text("snake".to_string())];
+        table
+            .update_rows(vec![(cat.clone(), fish), (bird.clone(), snake)])
+            .unwrap();
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                vec![Value::Integer(0), Value::Text("fish".to_string())],
+                vec![Value::Integer(2), Value::Text("snake".to_string())],
+            ])
+        );
+
+        let result = table.update_rows(vec![(bird, vec![Value::Integer(0), Value::Null])]);
+        assert_eq!(
+            result,
+            Err("UNIQUE constraint failed: animals.legs".to_string())
+        );
+    }
+
+    #[test]
+    fn deleted_rows_are_removed_by_their_primary_key() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("name", None, false),
+            Column::new("legs", None, true),
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let rows = MockBpTree::new();
+        let mut table = Table::new(table_schema, rows.clone()).unwrap();
+        let cat = vec![Value::Text("cat".to_string()), Value::Integer(4)];
+        let bird = vec![Value::Text("bird".to_string()), Value::Integer(2)];
+        table.insert_row(cat.clone()).unwrap();
+        table.insert_row(bird).unwrap();
+
+        table.delete_rows(vec![cat]).unwrap();
+        assert_eq!(
+            rows.rows.borrow().keys().collect::<Vec<_>>(),
+            vec![&Tuple(vec![Value::Integer(2)])]
+        );
+    }
+
+    #[test]
+    fn added_and_dropped_columns_rewrite_the_stored_rows() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("name", None, false),
+            Column::new("legs", None, true),
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let rows = MockBpTree::new();
+        let mut table = Table::new(table_schema, rows.clone()).unwrap();
+        table
+            .insert_row(vec![Value::Text("cat".to_string()), Value::Integer(4)])
+            .unwrap();
+
+        table.add_column(Column::new("eyes", None, false)).unwrap();
+        assert_eq!(
+            table.add_column(Column::new("eyes", None, false)),
+            Err("duplicate column name: eyes".to_string())
+        );
+        table
+            .insert_row_with_named_columns(
+                vec![
+                    ("legs".to_string(), Value::Integer(2)),
+                    ("eyes".to_string(), Value::Integer(2)),
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            table.drop_column("legs"),
+            Err("cannot drop PRIMARY KEY column: \"legs\"".to_string())
+        );
+        table.drop_column("name").unwrap();
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                vec![Value::Integer(2), Value::Integer(2)],
+                vec![Value::Integer(4), Value::Null],
+            ])
+        );
+        let column_names = table
+            .columns()
+            .iter()
+            .map(|column| column.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(column_names, vec!["legs", "eyes"]);
+    }
+
+    #[test]
+    fn unique_indexes_reject_duplicates_and_follow_updates_and_deletions() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("id", Some("INTEGER"), true),
+            Column::new("name", Some("TEXT"), false),
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let mut table = Table::new(table_schema, MockBpTree::new()).unwrap();
+        let row = |id: i64, name: &str| vec![Value::Integer(id), Value::Text(name.to_string())];
+        table.insert_row(row(1, "cat")).unwrap();
+        table.insert_row(row(2, "dog")).unwrap();
+        table.insert_row(row(3, "cat")).unwrap();
+
+        let index =
+            |unique| IndexSchema::new("by_name", "animals", vec!["name".to_string()], unique);
+        assert_eq!(
+            table.create_index(index(true)),
+            Err("UNIQUE constraint failed: animals.name".to_string())
+        );
+        table.delete_rows(vec![row(3, "cat")]).unwrap();
+        table.create_index(index(true)).unwrap();
+
+        assert_eq!(
+            table.insert_row(row(3, "dog")).err(),
+            Some("UNIQUE constraint failed: animals.name".to_string())
+        );
+        table
+            .insert_row(vec![Value::Integer(3), Value::Null])
+            .unwrap();
+        table
+            .insert_row(vec![Value::Integer(4), Value::Null])
+            .unwrap();
+        table
+            .update_rows(vec![(row(2, "dog"), row(2, "emu"))])
+            .unwrap();
+        table.insert_row(row(5, "dog")).unwrap();
+
+        let range = (
+            Bound::Included(Value::Text("a".to_string())),
+            Bound::Unbounded,
+        );
+        let rows = table.select_rows_in_range(1, range).unwrap().unwrap();
+        assert_eq!(
+            rows.collect::<Result<Vec<_>, _>>(),
+            Ok(vec![row(1, "cat"), row(5, "dog"), row(2, "emu")])
+        );
+        let unindexed = table.select_rows_in_range(0, (Bound::Unbounded, Bound::Unbounded));
+        assert!(unindexed.unwrap().is_none());
+    }
+
+    #[test]
+    fn tables_without_a_primary_key_are_keyed_by_a_rowid() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("legs", Some("INTEGER"), false),
+            Column::new("name", Some("TEXT"), false),
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let rows = MockBpTree::new();
+        let mut table = Table::new(table_schema, rows.clone()).unwrap();
+        let text = |s: &str| Value::Text(s.to_string());
+        table
+            .insert_row(vec![Value::Integer(4), text("cat")])
+            .unwrap();
+        table
+            .insert_row(vec![Value::Integer(4), text("dog")])
+            .unwrap();
+        table
+            .insert_row_with_named_columns(
+                vec![
+                    ("oid".to_string(), text("10")),
+                    ("name".to_string(), text("bird")),
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap();
+        table
+            .insert_row(vec![Value::Integer(0), text("fish")])
+            .unwrap();
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                vec![Value::Integer(4), text("cat"), Value::Integer(1)],
+                vec![Value::Integer(4), text("dog"), Value::Integer(2)],
+                vec![Value::Null, text("bird"), Value::Integer(10)],
+                vec![Value::Integer(0), text("fish"), Value::Integer(11)],
+            ])
+        );
+
+        let result = table.update_rows(vec![(
+            vec![Value::Integer(4), text("dog"), Value::Integer(2)],
+            vec![Value::Integer(4), text("dog"), text("two")],
+        )]);
+        assert_eq!(result, Err("datatype mismatch".to_string()));
+
+        table.add_column(Column::new("eyes", None, false)).unwrap();
+        table
+            .delete_rows(vec![vec![
+                Value::Integer(0),
+                text("fish"),
+                Value::Null,
+                Value::Integer(11),
+            ]])
+            .unwrap();
+        table
+            .insert_row(vec![Value::Null, text("ant"), Value::Integer(2)])
+            .unwrap();
+        assert_eq!(
+            table
+                .select_rows_with_named_columns(&vec!["_rowid_".to_string(), "eyes".to_string()])
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                vec![Value::Integer(1), Value::Null],
+                vec![Value::Integer(2), Value::Null],
+                vec![Value::Integer(10), Value::Null],
+                vec![Value::Integer(11), Value::Integer(2)],
+            ])
+        );
+    }
+
+    #[test]
+    fn column_constraints_are_enforced_on_insertions_and_updates() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        let mut legs = Column::new("legs", Some("INTEGER"), false);
+        legs.not_null = true;
+        legs.default = Some(Expression::Literal(Value::Text("4".to_string())));
+        legs.checks = vec![Expression::binary(
+            Expression::Column("legs".to_string()),
+            BinaryOperator::GreaterOrEqual,
+            Expression::Literal(Value::Integer(0)),
+        )];
+        let mut name = Column::new("name", Some("TEXT"), false);
+        name.unique = true;
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("animals".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("id", None, true),
+            legs,
+            name,
+        ]));
+        scenario.expect(table_schema_handle.constraints().and_return(vec![]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let mut table = Table::new(table_schema, MockBpTree::new()).unwrap();
+        assert_eq!(
+            executor::Table::index_names(&table),
+            vec!["sqlite_autoindex_animals_1"]
+        );
+        let text = |s: &str| Value::Text(s.to_string());
+        table
+            .insert_row_with_named_columns(
+                vec![
+                    ("id".to_string(), Value::Integer(1)),
+                    ("name".to_string(), text("cat")),
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap();
+
+        let row = |id: i64, legs: Value, name: &str| vec![Value::Integer(id), legs, text(name)];
+        let cat = row(1, Value::Integer(4), "cat");
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![cat.clone()])
+        );
+        assert_eq!(
+            table.insert_row(row(2, Value::Null, "dog")).err(),
+            Some("NOT NULL constraint failed: animals.legs".to_string())
+        );
+        assert_eq!(
+            table.insert_row(row(2, Value::Integer(-1), "dog")).err(),
+            Some("CHECK constraint failed: legs >= 0".to_string())
+        );
+        assert_eq!(
+            table.insert_row(row(2, Value::Integer(4), "cat")).err(),
+            Some("UNIQUE constraint failed: animals.name".to_string())
+        );
+        assert_eq!(
+            table.update_rows(vec![(cat.clone(), row(1, Value::Null, "cat"))]),
+            Err("NOT NULL constraint failed: animals.legs".to_string())
+        );
+        table
+            .update_rows(vec![(cat, row(1, text("2"), "bird"))])
+            .unwrap();
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![row(1, Value::Integer(2), "bird")])
+        );
+    }
+
+    #[test]
+    fn composite_primary_keys_order_rows_by_each_of_their_columns_in_turn() {
+        let scenario = Scenario::new();
+        let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
+
+        scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(
+            table_schema_handle
+                .table_name()
+                .and_return("likes".to_string()),
+        );
+        scenario.expect(table_schema_handle.columns().and_return(vec![
+            Column::new("person", Some("INTEGER"), false),
+            Column::new("fruit", Some("TEXT"), false),
+            Column::new("rank", Some("INTEGER"), false),
+        ]));
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        scenario.expect(table_schema_handle.constraints().and_return(vec![
+            TableConstraint::PrimaryKey(names(&["fruit", "person"])),
+            TableConstraint::Unique(names(&["person", "rank"])),
+        ]));
+        scenario.expect(table_schema_handle.is_strict().and_return(false));
+
+        let rows = MockBpTree::new();
+        let mut table = Table::new(table_schema, rows.clone()).unwrap();
+        assert_eq!(
+            executor::Table::index_names(&table),
+            vec!["sqlite_autoindex_likes_1"]
+        );
+        let row = |person: i64, fruit: &str, rank: i64| {
+            vec![
+                Value::Integer(person),
+                Value::Text(fruit.to_string()),
+                Value::Integer(rank),
+            ]
+        };
+        table.insert_row(row(2, "pear", 1)).unwrap();
+        table.insert_row(row(1, "pear", 1)).unwrap();
+        table.insert_row(row(1, "apple", 2)).unwrap();
+        assert_eq!(
+            table.insert_row(row(1, "pear", 3)).err(),
+            Some("UNIQUE constraint failed: likes.fruit, likes.person".to_string())
+        );
+        assert_eq!(
+            table.insert_row(row(2, "apple", 1)).err(),
+            Some("UNIQUE constraint failed: likes.person, likes.rank".to_string())
+        );
+        assert_eq!(
+            table.update_rows(vec![(row(2, "pear", 1), row(1, "apple", 1))]),
+            Err("UNIQUE constraint failed: likes.fruit, likes.person".to_string())
+        );
+        table
+            .update_rows(vec![(row(2, "pear", 1), row(2, "apple", 1))])
+            .unwrap();
+        assert_eq!(
+            table.insert_row(row(2, "kiwi", 1)).err(),
+            Some("UNIQUE constraint failed: likes.person, likes.rank".to_string())
+        );
+
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                row(1, "apple", 2),
+                row(2, "apple", 1),
+                row(1, "pear", 1)
+            ])
+        );
+        assert_eq!(
+            table.drop_column("person"),
+            Err("cannot drop PRIMARY KEY column: \"person\"".to_string())
+        );
+        assert_eq!(
+            table.drop_column("rank"),
+            Err("cannot drop UNIQUE column: \"rank\"".to_string())
+        );
+        assert_eq!(rows.rows.borrow().len(), 3);
+    }
 }